@@ -0,0 +1,144 @@
+/// The CPU and floating point settings rustc is given through `RUSTFLAGS`. Later flags win,
+/// as they do for rustc.
+#[derive(Debug, Default, PartialEq)]
+pub struct RustcAbi {
+    pub cpu: Option<String>,
+    /// Whether `+soft-float` is on. The `thumbv7em-none-eabihf` target is hard-float otherwise.
+    pub soft_float: bool,
+    pub fp64: Option<bool>,
+}
+
+impl RustcAbi {
+    /// Reads the codegen options out of `flags`, accepting both `-Cname=value` and
+    /// `-C name=value`.
+    pub fn parse<'a>(flags: impl IntoIterator<Item = &'a str>) -> RustcAbi {
+        let mut abi = RustcAbi::default();
+        let mut flags = flags.into_iter();
+        while let Some(flag) = flags.next() {
+            let option = match flag.strip_prefix("-C") {
+                Some("") => flags.next().unwrap_or_default(),
+                Some(option) => option,
+                None => continue,
+            };
+            if let Some(cpu) = option.strip_prefix("target-cpu=") {
+                abi.cpu = Some(cpu.to_string());
+            } else if let Some(features) = option.strip_prefix("target-feature=") {
+                for feature in features.split(',') {
+                    let enabled = feature.starts_with('+');
+                    match feature.trim_start_matches(['+', '-'].as_ref()) {
+                        "soft-float" => abi.soft_float = enabled,
+                        "fp64" => abi.fp64 = Some(enabled),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        abi
+    }
+}
+
+/// The `-mcpu`, `-mfpu` and `-mfloat-abi` flags gcc is given. Later flags win, as they do
+/// for gcc.
+#[derive(Debug, Default, PartialEq)]
+pub struct GccAbi {
+    pub cpu: Option<String>,
+    pub fpu: Option<String>,
+    pub float_abi: Option<String>,
+}
+
+impl GccAbi {
+    pub fn parse<'a>(flags: impl IntoIterator<Item = &'a str>) -> GccAbi {
+        let mut abi = GccAbi::default();
+        for flag in flags {
+            if let Some(cpu) = flag.strip_prefix("-mcpu=") {
+                abi.cpu = Some(cpu.to_string());
+            } else if let Some(fpu) = flag.strip_prefix("-mfpu=") {
+                abi.fpu = Some(fpu.to_string());
+            } else if let Some(float_abi) = flag.strip_prefix("-mfloat-abi=") {
+                abi.float_abi = Some(float_abi.to_string());
+            }
+        }
+        abi
+    }
+
+    /// Whether the FPU only does single precision, or `None` for `auto`, `none` and no FPU.
+    fn single_precision(&self) -> Option<bool> {
+        match self.fpu.as_deref() {
+            None | Some("auto") | Some("none") => None,
+            Some(fpu) => Some(fpu.contains("-sp-")),
+        }
+    }
+}
+
+/// Cross-checks the flags rustc and gcc build the device binary with, returning what
+/// disagrees. `what` names the gcc step in the messages.
+pub fn mismatches(rustc: &RustcAbi, gcc: &GccAbi, what: &str) -> Vec<String> {
+    let mut mismatches = Vec::new();
+    if let (Some(rustc_cpu), Some(gcc_cpu)) = (rustc.cpu.as_ref(), gcc.cpu.as_ref()) {
+        if rustc_cpu != gcc_cpu {
+            mismatches.push(format!(
+                "rustc targets -Ctarget-cpu={} but gcc {} with -mcpu={}",
+                rustc_cpu, what, gcc_cpu
+            ));
+        }
+    }
+    let gcc_hard_float = gcc.float_abi.as_deref() == Some("hard");
+    if rustc.soft_float == gcc_hard_float {
+        mismatches.push(format!(
+            "rustc uses the {}-float ABI but gcc {} with -mfloat-abi={}",
+            if rustc.soft_float { "soft" } else { "hard" },
+            what,
+            gcc.float_abi.as_deref().unwrap_or("soft")
+        ));
+    }
+    if let (Some(fp64), Some(single)) = (rustc.fp64, gcc.single_precision()) {
+        if fp64 == single {
+            mismatches.push(format!(
+                "rustc {} 64-bit floating point but gcc {} with the {}-precision -mfpu={}",
+                if fp64 { "enables" } else { "disables" },
+                what,
+                if single { "single" } else { "double" },
+                gcc.fpu.as_deref().unwrap_or_default()
+            ));
+        }
+    }
+    mismatches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_abi_mismatches() {
+        let gcc =
+            GccAbi::parse("-mthumb -mfloat-abi=hard -mcpu=cortex-m7 -mfpu=fpv5-sp-d16".split(' '));
+        let rustc = RustcAbi::parse(
+            "-Ctarget-cpu=cortex-m7 -Ctarget-feature=-fp64 -Crelocation-model=pic".split(' '),
+        );
+        assert_eq!(mismatches(&rustc, &gcc, "links"), Vec::<String>::new());
+
+        let rustc = RustcAbi::parse(
+            "-Ctarget-cpu=cortex-m7 -Ctarget-feature=-fp64 -C target-cpu=cortex-m4 \
+            -Ctarget-feature=+soft-float,+fp64"
+                .split(' '),
+        );
+        assert_eq!(
+            rustc,
+            RustcAbi {
+                cpu: Some("cortex-m4".to_string()),
+                soft_float: true,
+                fp64: Some(true),
+            }
+        );
+        assert_eq!(
+            mismatches(&rustc, &gcc, "links"),
+            vec![
+                "rustc targets -Ctarget-cpu=cortex-m4 but gcc links with -mcpu=cortex-m7",
+                "rustc uses the soft-float ABI but gcc links with -mfloat-abi=hard",
+                "rustc enables 64-bit floating point but gcc links with the \
+                single-precision -mfpu=fpv5-sp-d16",
+            ]
+        );
+    }
+}
@@ -0,0 +1,201 @@
+use anyhow::{bail, Context, Error};
+use cargo_metadata::{Metadata, PackageId};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Files that affect every target when they change, wherever they are: the lock file pins
+/// the version of every dependency.
+const SHARED_FILES: &[&str] = &["Cargo.lock"];
+
+fn git(dir: &Path, args: &[&str]) -> Result<String, Error> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .with_context(|| format!("Running git {}", args.join(" ")))?;
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The files that differ from `base` in the repository containing `dir`, committed or not,
+/// along with new files git doesn't ignore.
+pub fn changed_files(dir: &Path, base: &str) -> Result<Vec<PathBuf>, Error> {
+    let root = PathBuf::from(git(dir, &["rev-parse", "--show-toplevel"])?.trim());
+    let diff = git(&root, &["diff", "--name-only", base, "--"])?;
+    let untracked = git(&root, &["ls-files", "--others", "--exclude-standard"])?;
+    Ok(diff
+        .lines()
+        .chain(untracked.lines())
+        .filter(|line| !line.is_empty())
+        .map(|line| root.join(line))
+        .collect())
+}
+
+/// `path` with symbolic links resolved, so that paths from git and from cargo compare equal.
+/// A deleted file is resolved through its directory.
+fn canonical(path: &Path) -> PathBuf {
+    if let Ok(path) = path.canonicalize() {
+        return path;
+    }
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => canonical(parent).join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// The targets a set of changed files affects. A change in a package affects it and every
+/// package that depends on it, except that a change to an example's source file only affects
+/// that example. A change outside of every package, or to `Cargo.lock`, affects everything.
+#[derive(Debug, Default)]
+pub struct Affected {
+    everything: bool,
+    packages: HashSet<PackageId>,
+    example_sources: HashSet<PathBuf>,
+}
+
+impl Affected {
+    pub fn new(metadata: &Metadata, changed: &[PathBuf]) -> Affected {
+        let package_dirs: Vec<(&PackageId, PathBuf)> = metadata
+            .packages
+            .iter()
+            .map(|package| {
+                let dir = package.manifest_path.parent().expect("parent");
+                (&package.id, canonical(dir))
+            })
+            .collect();
+        let example_sources: Vec<PathBuf> = metadata
+            .packages
+            .iter()
+            .flat_map(|package| package.targets.iter())
+            .filter(|target| target.kind.iter().any(|kind| kind == "example"))
+            .map(|target| canonical(&target.src_path))
+            .collect();
+
+        let mut affected = Affected::default();
+        for file in changed.iter().map(|file| canonical(file)) {
+            let name = file.file_name().unwrap_or_default();
+            if SHARED_FILES.iter().any(|shared| name == *shared) {
+                affected.everything = true;
+            } else if example_sources.contains(&file) {
+                affected.example_sources.insert(file);
+            } else {
+                let owner = package_dirs
+                    .iter()
+                    .filter(|(_, dir)| file.starts_with(dir))
+                    .max_by_key(|(_, dir)| dir.components().count());
+                match owner {
+                    Some((id, _)) => {
+                        affected.packages.insert((*id).clone());
+                    }
+                    None => affected.everything = true,
+                }
+            }
+        }
+
+        if let Some(resolve) = metadata.resolve.as_ref() {
+            loop {
+                let dependents: Vec<PackageId> = resolve
+                    .nodes
+                    .iter()
+                    .filter(|node| !affected.packages.contains(&node.id))
+                    .filter(|node| {
+                        node.dependencies
+                            .iter()
+                            .any(|dependency| affected.packages.contains(dependency))
+                    })
+                    .map(|node| node.id.clone())
+                    .collect();
+                if dependents.is_empty() {
+                    break;
+                }
+                affected.packages.extend(dependents);
+            }
+        }
+        affected
+    }
+
+    /// Whether `example`, or without one the root package, needs building. A target that
+    /// can't be found is assumed to.
+    pub fn includes(&self, metadata: &Metadata, example: Option<&str>) -> bool {
+        if self.everything {
+            return true;
+        }
+        match example {
+            Some(example) => {
+                let found = metadata.packages.iter().find_map(|package| {
+                    package
+                        .targets
+                        .iter()
+                        .find(|target| {
+                            target.name == example
+                                && target.kind.iter().any(|kind| kind == "example")
+                        })
+                        .map(|target| (package, target))
+                });
+                match found {
+                    Some((package, target)) => {
+                        self.packages.contains(&package.id)
+                            || self.example_sources.contains(&canonical(&target.src_path))
+                    }
+                    None => true,
+                }
+            }
+            None => match metadata
+                .resolve
+                .as_ref()
+                .and_then(|resolve| resolve.root.as_ref())
+            {
+                Some(root) => self.packages.contains(root),
+                None => true,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_dependencies() {
+        let workspace = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("fixtures")
+            .join("workspace");
+        let metadata = cargo_metadata::MetadataCommand::new()
+            .manifest_path(workspace.join("game").join("Cargo.toml"))
+            .exec()
+            .unwrap();
+        let game = workspace.join("game");
+        let engine = workspace.join("engine");
+        let includes = |changed: &[PathBuf], example| {
+            Affected::new(&metadata, changed).includes(&metadata, example)
+        };
+
+        let level1 = [game.join("examples").join("level1.rs")];
+        assert!(includes(&level1, Some("level1")));
+        assert!(!includes(&level1, Some("boss")));
+        assert!(!includes(&level1, None));
+
+        let engine_source = [engine.join("src").join("lib.rs")];
+        assert!(includes(&engine_source, Some("boss")));
+        assert!(includes(&engine_source, None));
+
+        assert!(includes(
+            &[game.join("images").join("player.txt")],
+            Some("boss")
+        ));
+        assert!(includes(&[workspace.join("Cargo.lock")], Some("boss")));
+        assert!(includes(&[workspace.join("README.md")], None));
+        assert!(!includes(&[], Some("boss")));
+    }
+}
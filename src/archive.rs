@@ -0,0 +1,579 @@
+use anyhow::{bail, Context, Error};
+use log::info;
+use serde_derive::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+use zip::{write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+/// Unix permissions of the files in an archive, so they extract readable by everyone and
+/// writable by the owner whatever the permissions in the target directory were.
+pub const FILE_MODE: u32 = 0o644;
+/// Unix permissions of the directories in an archive.
+pub const DIR_MODE: u32 = 0o755;
+/// The file type bits zip readers report in the mode of a regular file.
+const REGULAR_FILE: u32 = 0o100000;
+
+/// What `write_archive` did with the files of the pdx.
+#[derive(Debug, Default, PartialEq)]
+pub struct ArchiveStats {
+    /// Files compressed into the archive.
+    pub updated: usize,
+    /// Files copied over from the previous archive as they were.
+    pub reused: usize,
+}
+
+/// Lists the directories and files below `dir` as zip entry names, with `/` separators and
+/// directories ending in `/`, in a stable order.
+fn entries(dir: &Path) -> Result<Vec<(String, PathBuf)>, Error> {
+    let mut entries = Vec::new();
+    let mut dirs = vec![(String::new(), dir.to_path_buf())];
+    while let Some((prefix, dir)) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+            if entry.file_type()?.is_dir() {
+                dirs.push((format!("{}/", name), entry.path()));
+                entries.push((format!("{}/", name), entry.path()));
+            } else {
+                entries.push((name, entry.path()));
+            }
+        }
+    }
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(entries)
+}
+
+/// Zips the contents of `pdx_dir` into `archive_path`, at the root of the archive or, with
+/// `root_folder`, inside a folder named after the pdx directory.
+///
+/// If the archive already exists and `force` is false, entries whose size, CRC-32 and
+/// compression method match the file on disk are copied over from the old archive without
+/// being compressed again, so repackaging after changing one asset only compresses that one.
+/// The new archive is written next to the old one and moved into place when complete.
+pub fn write_archive(
+    archive_path: &Path,
+    pdx_dir: &Path,
+    compression: CompressionMethod,
+    root_folder: bool,
+    force: bool,
+) -> Result<ArchiveStats, Error> {
+    let options = FileOptions::default().compression_method(compression);
+    let dir_options = options.unix_permissions(DIR_MODE);
+    let file_options = options.unix_permissions(FILE_MODE);
+    let prefix = if root_folder {
+        let folder = pdx_dir
+            .file_name()
+            .with_context(|| format!("{:?} has no folder name", pdx_dir))?;
+        format!("{}/", folder.to_string_lossy())
+    } else {
+        String::new()
+    };
+    let mut previous = if force || !archive_path.is_file() {
+        None
+    } else {
+        File::open(archive_path)
+            .ok()
+            .and_then(|file| ZipArchive::new(file).ok())
+    };
+
+    let temp_path = archive_path.with_extension("zip.tmp");
+    let mut writer = ZipWriter::new(
+        File::create(&temp_path).with_context(|| format!("Creating {:?}", temp_path))?,
+    );
+    let mut stats = ArchiveStats::default();
+    if root_folder {
+        writer.add_directory(prefix.as_str(), dir_options)?;
+    }
+    for (name, path) in entries(pdx_dir)? {
+        let name = format!("{}{}", prefix, name);
+        if name.ends_with('/') {
+            writer.add_directory(name, dir_options)?;
+            continue;
+        }
+        let contents = fs::read(&path).with_context(|| format!("Reading {:?}", path))?;
+        let crc32 = crc32fast::hash(&contents);
+        if let Some(previous) = previous.as_mut() {
+            if let Ok(entry) = previous.by_name(&name) {
+                if entry.size() == contents.len() as u64
+                    && entry.crc32() == crc32
+                    && entry.compression() == compression
+                    && entry.unix_mode() == Some(REGULAR_FILE | FILE_MODE)
+                {
+                    writer.raw_copy_file(entry)?;
+                    stats.reused += 1;
+                    continue;
+                }
+            }
+        }
+        writer.start_file(name, file_options)?;
+        writer.write_all(&contents)?;
+        stats.updated += 1;
+    }
+    writer.finish()?;
+    drop(previous);
+    fs::rename(&temp_path, archive_path)
+        .with_context(|| format!("Moving {:?} to {:?}", temp_path, archive_path))?;
+    info!(
+        "write_archive {:?}: {} entries updated, {} reused",
+        archive_path, stats.updated, stats.reused
+    );
+    Ok(stats)
+}
+
+/// Entries of which a pdx needs at least one: the device binary or a simulator library.
+pub const PDEX_ENTRIES: &[&str] = &["pdex.bin", "pdex.dylib", "pdex.so", "pdex.dll"];
+
+/// Reopens a written archive and checks that it is readable and contains a non-empty
+/// `pdxinfo` and game binary, so an interrupted build isn't packaged without anyone noticing.
+pub fn verify_archive(archive_path: &Path) -> Result<(), Error> {
+    let file = File::open(archive_path).with_context(|| format!("Opening {:?}", archive_path))?;
+    let mut archive =
+        ZipArchive::new(file).with_context(|| format!("Reading {:?}", archive_path))?;
+
+    let folder = pdx_folder(&archive);
+    let mut sizes = Vec::new();
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        let name = entry.name().to_string();
+        // Reading every entry checks it decompresses and matches its CRC.
+        let size = io::copy(&mut entry, &mut io::sink())
+            .with_context(|| format!("Reading {} from {:?}", name, archive_path))?;
+        let name = match folder.as_ref() {
+            Some(folder) => name[folder.len()..].to_string(),
+            None => name,
+        };
+        sizes.push((name, size));
+    }
+    let size_of = |wanted: &str| {
+        sizes
+            .iter()
+            .find(|(name, _)| name == wanted)
+            .map(|(_, size)| *size)
+    };
+
+    let mut problems = Vec::new();
+    match size_of("pdxinfo") {
+        None => problems.push("pdxinfo is missing".to_string()),
+        Some(0) => problems.push("pdxinfo is empty".to_string()),
+        Some(_) => {}
+    }
+    let pdex: Vec<(&str, u64)> = PDEX_ENTRIES
+        .iter()
+        .filter_map(|name| size_of(name).map(|size| (*name, size)))
+        .collect();
+    if pdex.is_empty() {
+        problems.push(format!("none of {} is present", PDEX_ENTRIES.join(", ")));
+    }
+    for (name, size) in pdex {
+        if size == 0 {
+            problems.push(format!("{} is empty", name));
+        }
+    }
+    if !problems.is_empty() {
+        bail!(
+            "{:?} looks incomplete: {}",
+            archive_path,
+            problems.join("; ")
+        );
+    }
+    info!("verified {:?}: {} entries", archive_path, sizes.len());
+    Ok(())
+}
+
+/// Size and CRC-32 of a file in a pdx archive.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ArchivedFile {
+    pub size: u64,
+    pub crc32: u32,
+}
+
+/// The files of a pdx archive, by path inside the pdx, and its `pdxinfo`.
+#[derive(Debug, Default)]
+pub struct PdxContents {
+    pub files: BTreeMap<String, ArchivedFile>,
+    pub pdxinfo: Option<String>,
+}
+
+/// The `<name>.pdx/` folder, with its trailing `/`, that every entry of an archive is inside,
+/// as in archives written with a root folder or by zipping the pdx folder itself.
+fn pdx_folder(archive: &ZipArchive<File>) -> Option<String> {
+    let names: Vec<&str> = archive.file_names().collect();
+    names
+        .first()
+        .and_then(|name| name.split_once('/'))
+        .map(|(folder, _)| format!("{}/", folder))
+        .filter(|folder| {
+            folder.ends_with(".pdx/") && names.iter().all(|name| name.starts_with(folder))
+        })
+}
+
+/// Reads every file of a `.pdx.zip`. Archives may keep the files in a `<name>.pdx/` folder,
+/// which is left out of the paths so archives of both kinds compare.
+pub fn read_contents(archive_path: &Path) -> Result<PdxContents, Error> {
+    let file = File::open(archive_path).with_context(|| format!("Opening {:?}", archive_path))?;
+    let mut archive =
+        ZipArchive::new(file).with_context(|| format!("Reading {:?}", archive_path))?;
+    let folder = pdx_folder(&archive);
+
+    let mut contents = PdxContents::default();
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        if entry.is_dir() {
+            continue;
+        }
+        let name = entry.name().to_string();
+        let path = match folder.as_ref() {
+            Some(folder) => name[folder.len()..].to_string(),
+            None => name.clone(),
+        };
+        let mut data = Vec::new();
+        // Reading the entry checks it decompresses and matches its CRC.
+        entry
+            .read_to_end(&mut data)
+            .with_context(|| format!("Reading {} from {:?}", name, archive_path))?;
+        if path == "pdxinfo" {
+            contents.pdxinfo = Some(String::from_utf8_lossy(&data).into_owned());
+        }
+        contents.files.insert(
+            path,
+            ArchivedFile {
+                size: data.len() as u64,
+                crc32: entry.crc32(),
+            },
+        );
+    }
+    Ok(contents)
+}
+
+/// Reads every file of a `.pdx` directory, the same way `read_contents` reads an archive.
+pub fn read_dir_contents(pdx_dir: &Path) -> Result<PdxContents, Error> {
+    let mut contents = PdxContents::default();
+    for (name, path) in entries(pdx_dir).with_context(|| format!("Reading {:?}", pdx_dir))? {
+        if name.ends_with('/') {
+            continue;
+        }
+        let data = fs::read(&path).with_context(|| format!("Reading {:?}", path))?;
+        if name == "pdxinfo" {
+            contents.pdxinfo = Some(String::from_utf8_lossy(&data).into_owned());
+        }
+        contents.files.insert(
+            name,
+            ArchivedFile {
+                size: data.len() as u64,
+                crc32: crc32fast::hash(&data),
+            },
+        );
+    }
+    Ok(contents)
+}
+
+/// The checksums file written next to a pdx with `--emit-checksums`.
+#[derive(Debug, Serialize)]
+struct Checksums {
+    algorithm: &'static str,
+    /// Lowercase hex digests by path inside the pdx, in path order.
+    files: BTreeMap<String, String>,
+}
+
+/// The SHA-256 of every file of a `.pdx` directory, by the same paths `read_dir_contents`
+/// uses.
+fn sha256_files(pdx_dir: &Path) -> Result<BTreeMap<String, String>, Error> {
+    let mut files = BTreeMap::new();
+    for (name, path) in entries(pdx_dir).with_context(|| format!("Reading {:?}", pdx_dir))? {
+        if name.ends_with('/') {
+            continue;
+        }
+        let data = fs::read(&path).with_context(|| format!("Reading {:?}", path))?;
+        let digest = Sha256::digest(&data)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        files.insert(name, digest);
+    }
+    Ok(files)
+}
+
+/// Writes the SHA-256 of every file of `pdx_dir`, `pdex.*` and `pdxinfo` included, to
+/// `checksums_path` as JSON.
+pub fn write_checksums(checksums_path: &Path, pdx_dir: &Path) -> Result<(), Error> {
+    let checksums = Checksums {
+        algorithm: "sha256",
+        files: sha256_files(pdx_dir)?,
+    };
+    let json = serde_json::to_string_pretty(&checksums)?;
+    fs::write(checksums_path, json + "\n").with_context(|| format!("Writing {:?}", checksums_path))
+}
+
+/// Reads a `.pdx` directory or a `.pdx.zip` archive.
+pub fn read_pdx(path: &Path) -> Result<PdxContents, Error> {
+    if path.is_dir() {
+        read_dir_contents(path)
+    } else {
+        read_contents(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    fn read_entry(archive_path: &Path, name: &str) -> String {
+        let mut archive = ZipArchive::new(File::open(archive_path).unwrap()).unwrap();
+        let mut contents = String::new();
+        archive
+            .by_name(name)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        contents
+    }
+
+    #[test]
+    fn updates_changed_entries() {
+        let dir = TestDir::new("archive");
+        let pdx_dir = dir.join("Game.pdx");
+        fs::create_dir_all(pdx_dir.join("images")).unwrap();
+        fs::write(pdx_dir.join("pdxinfo"), "name=Game\n").unwrap();
+        fs::write(pdx_dir.join("images").join("a.pdi"), "first").unwrap();
+        let archive_path = dir.join("Game.pdx.zip");
+
+        let write = |force| {
+            write_archive(
+                &archive_path,
+                &pdx_dir,
+                CompressionMethod::Deflated,
+                false,
+                force,
+            )
+            .unwrap()
+        };
+        assert_eq!(
+            write(false),
+            ArchiveStats {
+                updated: 2,
+                reused: 0
+            }
+        );
+        fs::write(pdx_dir.join("images").join("a.pdi"), "second").unwrap();
+        assert_eq!(
+            write(false),
+            ArchiveStats {
+                updated: 1,
+                reused: 1
+            }
+        );
+        assert_eq!(
+            write(true),
+            ArchiveStats {
+                updated: 2,
+                reused: 0
+            }
+        );
+
+        assert_eq!(read_entry(&archive_path, "pdxinfo"), "name=Game\n");
+        assert_eq!(read_entry(&archive_path, "images/a.pdi"), "second");
+    }
+
+    #[test]
+    fn reads_contents_inside_pdx_folder() {
+        let dir = TestDir::new("contents");
+        let archive_path = dir.join("Game.pdx.zip");
+        let mut writer = ZipWriter::new(File::create(&archive_path).unwrap());
+        writer
+            .add_directory("Game.pdx/", FileOptions::default())
+            .unwrap();
+        writer
+            .start_file("Game.pdx/pdxinfo", FileOptions::default())
+            .unwrap();
+        writer.write_all(b"name=Game\n").unwrap();
+        writer
+            .start_file("Game.pdx/pdex.bin", FileOptions::default())
+            .unwrap();
+        writer.write_all(b"binary").unwrap();
+        writer.finish().unwrap();
+
+        let contents = read_contents(&archive_path).unwrap();
+        assert_eq!(contents.pdxinfo.as_deref(), Some("name=Game\n"));
+        assert_eq!(
+            contents.files.keys().collect::<Vec<_>>(),
+            vec!["pdex.bin", "pdxinfo"]
+        );
+        assert_eq!(
+            contents.files["pdex.bin"],
+            ArchivedFile {
+                size: 6,
+                crc32: crc32fast::hash(b"binary"),
+            }
+        );
+    }
+
+    #[test]
+    fn reads_directory_like_archive() {
+        let dir = TestDir::new("read-dir");
+        let pdx_dir = dir.join("Game.pdx");
+        fs::create_dir_all(pdx_dir.join("images")).unwrap();
+        fs::write(pdx_dir.join("pdxinfo"), "name=Game\n").unwrap();
+        fs::write(pdx_dir.join("pdex.bin"), "binary").unwrap();
+        fs::write(pdx_dir.join("images").join("a.pdi"), "image").unwrap();
+        let archive_path = dir.join("Game.pdx.zip");
+        write_archive(
+            &archive_path,
+            &pdx_dir,
+            CompressionMethod::Deflated,
+            false,
+            true,
+        )
+        .unwrap();
+
+        let from_dir = read_pdx(&pdx_dir).unwrap();
+        let from_archive = read_pdx(&archive_path).unwrap();
+        assert_eq!(from_dir.files, from_archive.files);
+        assert_eq!(from_dir.pdxinfo.as_deref(), Some("name=Game\n"));
+        assert_eq!(
+            from_dir.files.keys().collect::<Vec<_>>(),
+            vec!["images/a.pdi", "pdex.bin", "pdxinfo"]
+        );
+    }
+
+    #[test]
+    fn stores_unix_modes() {
+        let dir = TestDir::new("modes");
+        let pdx_dir = dir.join("Game.pdx");
+        fs::create_dir_all(pdx_dir.join("images")).unwrap();
+        fs::write(pdx_dir.join("pdxinfo"), "name=Game\n").unwrap();
+        fs::write(pdx_dir.join("images").join("a.pdi"), "image").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let private = fs::Permissions::from_mode(0o600);
+            fs::set_permissions(pdx_dir.join("pdxinfo"), private).unwrap();
+        }
+        let archive_path = dir.join("Game.pdx.zip");
+
+        write_archive(
+            &archive_path,
+            &pdx_dir,
+            CompressionMethod::Deflated,
+            false,
+            true,
+        )
+        .unwrap();
+        let mut archive = ZipArchive::new(File::open(&archive_path).unwrap()).unwrap();
+        for (name, mode) in [
+            ("images/", 0o040000 | DIR_MODE),
+            ("images/a.pdi", REGULAR_FILE | FILE_MODE),
+            ("pdxinfo", REGULAR_FILE | FILE_MODE),
+        ]
+        .iter()
+        {
+            assert_eq!(
+                archive.by_name(name).unwrap().unix_mode(),
+                Some(*mode),
+                "{}",
+                name
+            );
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let extracted = dir.join("extracted");
+            archive.extract(&extracted).unwrap();
+            let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+            assert_eq!(mode(&extracted.join("pdxinfo")), FILE_MODE);
+            assert_eq!(mode(&extracted.join("images")), DIR_MODE);
+        }
+    }
+
+    #[test]
+    fn writes_checksums() {
+        let dir = TestDir::new("checksums");
+        let pdx = dir.join("Game.pdx");
+        fs::create_dir_all(pdx.join("images")).unwrap();
+        fs::write(pdx.join("pdxinfo"), "name=Game\n").unwrap();
+        fs::write(pdx.join("images").join("a.pdi"), "abc").unwrap();
+
+        let checksums_path = dir.join("Game.checksums.json");
+        write_checksums(&checksums_path, &pdx).unwrap();
+        let checksums: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&checksums_path).unwrap()).unwrap();
+        assert_eq!(checksums["algorithm"], "sha256");
+        let files = checksums["files"].as_object().unwrap();
+        assert_eq!(
+            files.keys().collect::<Vec<_>>(),
+            vec!["images/a.pdi", "pdxinfo"]
+        );
+        assert_eq!(
+            files["images/a.pdi"],
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn writes_pdx_root_folder() {
+        let dir = TestDir::new("root");
+        let pdx_dir = dir.join("Game.pdx");
+        fs::create_dir_all(&pdx_dir).unwrap();
+        fs::write(pdx_dir.join("pdxinfo"), "name=Game\n").unwrap();
+        fs::write(pdx_dir.join("pdex.bin"), "binary").unwrap();
+        let archive_path = dir.join("Game.pdx.zip");
+
+        write_archive(
+            &archive_path,
+            &pdx_dir,
+            CompressionMethod::Deflated,
+            true,
+            true,
+        )
+        .unwrap();
+        let archive = ZipArchive::new(File::open(&archive_path).unwrap()).unwrap();
+        let mut names: Vec<&str> = archive.file_names().collect();
+        names.sort_unstable();
+        assert_eq!(
+            names,
+            vec!["Game.pdx/", "Game.pdx/pdex.bin", "Game.pdx/pdxinfo"]
+        );
+        verify_archive(&archive_path).unwrap();
+        assert_eq!(
+            read_contents(&archive_path).unwrap().pdxinfo.as_deref(),
+            Some("name=Game\n")
+        );
+    }
+
+    #[test]
+    fn verify_requires_pdex_and_pdxinfo() {
+        let dir = TestDir::new("verify");
+        let pdx_dir = dir.join("Game.pdx");
+        fs::create_dir_all(&pdx_dir).unwrap();
+        fs::write(pdx_dir.join("pdxinfo"), "name=Game\n").unwrap();
+        fs::write(pdx_dir.join("pdex.bin"), "").unwrap();
+        let archive_path = dir.join("Game.pdx.zip");
+
+        write_archive(
+            &archive_path,
+            &pdx_dir,
+            CompressionMethod::Deflated,
+            false,
+            true,
+        )
+        .unwrap();
+        let error = verify_archive(&archive_path).unwrap_err().to_string();
+        assert!(error.ends_with("pdex.bin is empty"), "{}", error);
+
+        fs::write(pdx_dir.join("pdex.bin"), "binary").unwrap();
+        write_archive(
+            &archive_path,
+            &pdx_dir,
+            CompressionMethod::Deflated,
+            false,
+            true,
+        )
+        .unwrap();
+        verify_archive(&archive_path).unwrap();
+    }
+}
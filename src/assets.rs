@@ -0,0 +1,612 @@
+use anyhow::{Context, Error};
+use log::info;
+use serde_derive::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::SystemTime,
+};
+
+/// An entry in a target's `assets` list. A plain string copies the file or directory to the
+/// same relative path in the pdx, while `{ from = "...", to = "..." }` copies it to a
+/// different location.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum Asset {
+    Path(String),
+    Mapped { from: String, to: String },
+}
+
+impl Asset {
+    /// Path of the asset relative to the project.
+    pub fn source(&self) -> &str {
+        match self {
+            Asset::Path(path) => path,
+            Asset::Mapped { from, .. } => from,
+        }
+    }
+
+    /// Path of the asset relative to the root of the pdx.
+    pub fn destination(&self) -> &str {
+        match self {
+            Asset::Path(path) => path,
+            Asset::Mapped { to, .. } => to,
+        }
+    }
+}
+
+/// A file to copy into the staging directory, along with the asset it came from.
+struct FileCopy<'a> {
+    asset: &'a Asset,
+    src_path: PathBuf,
+    dst_path: PathBuf,
+}
+
+/// The entries of each asset directory copied into the staging directory, in name order, by
+/// the directory's path relative to the staging directory and the path it was copied from.
+pub type DirSnapshot = BTreeMap<String, Vec<String>>;
+
+/// What an earlier `copy_assets` left in the staging directory.
+pub struct LastCopy {
+    /// When the copy started.
+    pub time: SystemTime,
+    /// The entries of the directories it copied.
+    pub dirs: DirSnapshot,
+}
+
+/// The snapshot stored at `path` by `store_snapshot`, or an empty one if there is none.
+pub fn read_snapshot(path: &Path) -> DirSnapshot {
+    fs::read(path)
+        .ok()
+        .and_then(|contents| serde_json::from_slice(&contents).ok())
+        .unwrap_or_default()
+}
+
+pub fn store_snapshot(path: &Path, dirs: &DirSnapshot) -> Result<(), Error> {
+    fs::write(path, serde_json::to_string(dirs)?).with_context(|| format!("Writing {:?}", path))
+}
+
+/// Copies every asset from `source_dir` into the staging directory `dest_dir`.
+///
+/// Directories are created first, one at a time, and then the files are copied on several
+/// threads, since games with thousands of small assets spend much of the build copying them.
+/// When several assets write the same file the last one wins, as if they were copied in order,
+/// and if copies fail the error for the first of them in asset order is returned.
+///
+/// With `since`, the staging directory is assumed to hold the assets as `since` copied them,
+/// and only files modified after it are copied. Directories not modified after it still have
+/// the entries it recorded, so they are taken from there instead of being listed again, while
+/// entries that are gone from a directory that was modified are removed from the staging
+/// directory. Returns the entries of the directories copied, to build on next time.
+///
+/// Files in a directory with one of the `source_extensions`, such as `player.aseprite`, are
+/// left out when a file with the same name and another extension, such as `player.png`, is
+/// next to them, since that is the file converted from them.
+pub fn copy_assets(
+    assets: &[Asset],
+    source_dir: &Path,
+    dest_dir: &Path,
+    since: Option<&LastCopy>,
+    source_extensions: &[String],
+) -> Result<DirSnapshot, Error> {
+    let mut plan = Plan {
+        dest_dir,
+        since,
+        source_extensions,
+        dirs: DirSnapshot::new(),
+        staged: HashSet::new(),
+        gone: Vec::new(),
+        copies: Vec::new(),
+    };
+    for asset in assets {
+        let src_path = source_dir.join(asset.source());
+        let dst_path = dest_dir.join(asset.destination());
+        plan.add(asset, &src_path, &dst_path)
+            .with_context(|| format!("Copying asset {:?}", asset.source()))?;
+    }
+    let Plan {
+        dirs,
+        staged,
+        gone,
+        copies,
+        ..
+    } = plan;
+    // Another asset may still put something at the same place.
+    for gone_path in gone.iter().filter(|path| !staged.contains(*path)) {
+        info!("removing {:?}, which is no longer an asset", gone_path);
+        if gone_path.is_dir() {
+            fs::remove_dir_all(gone_path)
+        } else {
+            fs::remove_file(gone_path)
+        }
+        .with_context(|| format!("Removing {:?}", gone_path))?;
+    }
+    info!("copying {} asset files", copies.len());
+    let mut last_copy = HashMap::new();
+    for (index, copy) in copies.iter().enumerate() {
+        last_copy.insert(copy.dst_path.as_path(), index);
+    }
+    let copies: Vec<&FileCopy> = copies
+        .iter()
+        .enumerate()
+        .filter(|(index, copy)| last_copy[copy.dst_path.as_path()] == *index)
+        .map(|(_, copy)| copy)
+        .collect();
+
+    let workers = thread::available_parallelism()
+        .map_or(1, |count| count.get())
+        .min(MAX_COPY_THREADS)
+        .min(copies.len().max(1));
+    let next = AtomicUsize::new(0);
+    let results: Vec<Mutex<Option<io::Result<u64>>>> =
+        copies.iter().map(|_| Mutex::new(None)).collect();
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let copy = match copies.get(index) {
+                    Some(copy) => copy,
+                    None => break,
+                };
+                info!("copy {:?} to {:?}", copy.src_path, copy.dst_path);
+                let result = fs::copy(&copy.src_path, &copy.dst_path);
+                *results[index].lock().expect("copy result") = Some(result);
+            });
+        }
+    });
+
+    for (copy, result) in copies.iter().zip(results) {
+        if let Some(Err(err)) = result.into_inner().expect("copy result") {
+            return Err(Error::new(err))
+                .with_context(|| format!("Copying {:?}", copy.src_path))
+                .with_context(|| format!("Copying asset {:?}", copy.asset.source()));
+        }
+    }
+    Ok(dirs)
+}
+
+/// Most of the time of a copy is spent waiting on the disk, so a few threads are enough.
+const MAX_COPY_THREADS: usize = 8;
+
+/// Whether `path` was modified before `since`. A path whose time can't be read counts as
+/// modified.
+fn modified_before(path: &Path, since: SystemTime) -> bool {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .is_ok_and(|modified| modified < since)
+}
+
+/// Whether `path` has one of the `source_extensions`, ignoring case.
+fn is_source_file(path: &Path, source_extensions: &[String]) -> bool {
+    path.extension().is_some_and(|ext| {
+        source_extensions
+            .iter()
+            .any(|source| ext.to_string_lossy().eq_ignore_ascii_case(source))
+    })
+}
+
+/// The files `copy_assets` is going to copy, and the directories it went through.
+struct Plan<'a, 'p> {
+    dest_dir: &'p Path,
+    since: Option<&'p LastCopy>,
+    source_extensions: &'p [String],
+    dirs: DirSnapshot,
+    /// Every path in the staging directory the assets put something at.
+    staged: HashSet<PathBuf>,
+    /// Staged paths of entries that are gone from their asset directory.
+    gone: Vec<PathBuf>,
+    copies: Vec<FileCopy<'a>>,
+}
+
+impl<'a, 'p> Plan<'a, 'p> {
+    /// The entries of the directory `src_path`, which is copied to `dst_path`: the ones the
+    /// last copy recorded if the directory wasn't modified since, or else a fresh listing, in
+    /// which case the recorded entries that are gone are noted for removal.
+    fn entries(
+        &mut self,
+        src_path: &Path,
+        dst_path: &Path,
+    ) -> Result<(String, Vec<String>), Error> {
+        let key = format!(
+            "{} <- {}",
+            dst_path
+                .strip_prefix(self.dest_dir)
+                .unwrap_or(dst_path)
+                .to_string_lossy(),
+            src_path.to_string_lossy()
+        );
+        let recorded = self
+            .since
+            .and_then(|since| Some((since.time, since.dirs.get(&key)?)));
+        if let Some((time, recorded)) = recorded {
+            if modified_before(src_path, time) {
+                return Ok((key, recorded.clone()));
+            }
+        }
+        let mut names = fs::read_dir(src_path)?
+            .map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().into_owned()))
+            .collect::<Result<Vec<_>, _>>()?;
+        names.sort();
+        if let Some((_, recorded)) = recorded {
+            self.gone.extend(
+                recorded
+                    .iter()
+                    .filter(|name| !names.contains(name))
+                    .map(|name| dst_path.join(name))
+                    .filter(|path| path.exists()),
+            );
+        }
+        Ok((key, names))
+    }
+
+    /// Creates the destination directories for a file, or for a directory and everything
+    /// below it, and adds the files to copy. With `since`, leaves out the files modified
+    /// before it that are already staged. Source files converted into a file next to them
+    /// are left out, and removed if an earlier build staged them.
+    fn add(&mut self, asset: &'a Asset, src_path: &Path, dst_path: &Path) -> Result<(), Error> {
+        self.staged.insert(dst_path.to_path_buf());
+        if src_path.is_dir() {
+            fs::create_dir_all(dst_path)?;
+            let (key, names) = self.entries(src_path, dst_path)?;
+            let converted_stems: Vec<PathBuf> = names
+                .iter()
+                .map(PathBuf::from)
+                .filter(|path| !is_source_file(path, self.source_extensions))
+                .filter_map(|path| path.file_stem().map(PathBuf::from))
+                .collect();
+            for name in &names {
+                let entry_path = src_path.join(name);
+                let entry_dst_path = dst_path.join(name);
+                if is_source_file(&entry_path, self.source_extensions)
+                    && entry_path
+                        .file_stem()
+                        .is_some_and(|stem| converted_stems.iter().any(|other| other == stem))
+                    && !entry_path.is_dir()
+                {
+                    info!("skipping source file {:?}", entry_path);
+                    if entry_dst_path.is_file() {
+                        fs::remove_file(&entry_dst_path)?;
+                    }
+                    continue;
+                }
+                self.add(asset, &entry_path, &entry_dst_path)?;
+            }
+            self.dirs.insert(key, names);
+        } else if self
+            .since
+            .is_some_and(|since| modified_before(src_path, since.time))
+            && dst_path.is_file()
+        {
+            // Already staged by an earlier build.
+        } else {
+            if let Some(dst_parent) = dst_path.parent() {
+                fs::create_dir_all(dst_parent)?;
+            }
+            self.copies.push(FileCopy {
+                asset,
+                src_path: src_path.to_path_buf(),
+                dst_path: dst_path.to_path_buf(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Finds fonts in the staging directory that would ship broken: a `.fnt` whose glyphs aren't
+/// embedded needs a `<name>-table-<width>-<height>.png` image next to it.
+pub fn check_fonts(staging_dir: &Path) -> Result<Vec<String>, Error> {
+    let mut problems = Vec::new();
+    let mut dirs = vec![staging_dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let mut files: Vec<PathBuf> = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else {
+                files.push(path);
+            }
+        }
+        for font in files
+            .iter()
+            .filter(|path| path.extension().is_some_and(|ext| ext == "fnt"))
+        {
+            let contents =
+                fs::read_to_string(font).with_context(|| format!("Reading font {:?}", font))?;
+            // Fonts saved with the glyph image embedded carry it in a `data=` line.
+            if contents.lines().any(|line| line.starts_with("data=")) {
+                continue;
+            }
+            let stem = font
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let table_prefix = format!("{}-table-", stem);
+            let has_table = files.iter().any(|path| {
+                path.file_name()
+                    .map(|name| name.to_string_lossy())
+                    .is_some_and(|name| name.starts_with(&table_prefix) && name.ends_with(".png"))
+            });
+            if !has_table {
+                problems.push(format!(
+                    "font {:?} has no {}<width>-<height>.png image next to it",
+                    font.strip_prefix(staging_dir).unwrap_or(font),
+                    table_prefix
+                ));
+            }
+        }
+    }
+    problems.sort();
+    Ok(problems)
+}
+
+/// Every file below `dir`, relative to it, in name order.
+fn relative_files(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(current) = dirs.pop() {
+        for entry in fs::read_dir(&current).with_context(|| format!("Reading {:?}", current))? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else {
+                files.push(path.strip_prefix(dir).unwrap_or(&path).to_path_buf());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// The path of what pdc makes of a staged file, without the extension it gets: the same name
+/// with another extension, such as `player.pdi` for `player.png`, except for image tables,
+/// where `walk-table-32-32.png` becomes `walk.pdt`.
+fn compiled_stem(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let stem = match stem.find("-table-") {
+        Some(index) => stem[..index].to_string(),
+        None => stem,
+    };
+    path.with_file_name(stem)
+}
+
+/// Finds the files of the staging directory that didn't make it into the pdx pdc wrote, under
+/// their own name or as the file compiled from them. Lua sources are left out, since pdc
+/// compiles them together into `main.pdz`.
+pub fn missing_from_pdx(staging_dir: &Path, pdx_dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let output: HashSet<PathBuf> = relative_files(pdx_dir)?.into_iter().collect();
+    let output_stems: HashSet<PathBuf> =
+        output.iter().map(|path| path.with_extension("")).collect();
+    Ok(relative_files(staging_dir)?
+        .into_iter()
+        .filter(|path| !path.extension().is_some_and(|ext| ext == "lua"))
+        .filter(|path| !output.contains(path) && !output_stems.contains(&compiled_stem(path)))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn parse_plain_and_mapped() {
+        #[derive(Deserialize)]
+        struct Target {
+            assets: Vec<Asset>,
+        }
+        let target: Target = toml::from_str(
+            r#"
+            assets = [
+                "images/background.png",
+                { from = "art/final/player.png", to = "images/player.png" },
+            ]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(target.assets[0].source(), "images/background.png");
+        assert_eq!(target.assets[0].destination(), "images/background.png");
+        assert_eq!(target.assets[1].source(), "art/final/player.png");
+        assert_eq!(target.assets[1].destination(), "images/player.png");
+    }
+
+    #[test]
+    fn fonts_need_their_image_table() {
+        let dir = TestDir::new("fonts");
+        let fonts = dir.join("fonts");
+        fs::create_dir_all(&fonts).unwrap();
+        fs::write(fonts.join("big.fnt"), "tracking=1\n").unwrap();
+        fs::write(fonts.join("big-table-16-16.png"), "").unwrap();
+        fs::write(fonts.join("small.fnt"), "tracking=1\n").unwrap();
+        fs::write(fonts.join("embedded.fnt"), "datalen=4\ndata=AAAA\n").unwrap();
+
+        let problems = check_fonts(&dir).unwrap();
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("small-table-"), "{}", problems[0]);
+    }
+
+    #[test]
+    fn copies_many_assets() {
+        let dir = TestDir::new("copy");
+        let levels = dir.join("project").join("levels");
+        fs::create_dir_all(&levels).unwrap();
+        for level in 0..100 {
+            fs::write(levels.join(format!("{}.json", level)), level.to_string()).unwrap();
+        }
+        fs::write(dir.join("project").join("boss.json"), "boss").unwrap();
+        let staging = dir.join("staging");
+        let assets = vec![
+            Asset::Path("levels".to_string()),
+            Asset::Mapped {
+                from: "boss.json".to_string(),
+                to: "levels/99.json".to_string(),
+            },
+        ];
+
+        copy_assets(&assets, &dir.join("project"), &staging, None, &[]).unwrap();
+        assert_eq!(fs::read_dir(staging.join("levels")).unwrap().count(), 100);
+        assert_eq!(
+            fs::read(staging.join("levels").join("7.json")).unwrap(),
+            b"7"
+        );
+        assert_eq!(
+            fs::read(staging.join("levels").join("99.json")).unwrap(),
+            b"boss"
+        );
+
+        let missing = vec![
+            Asset::Path("first.png".to_string()),
+            Asset::Path("second.png".to_string()),
+        ];
+        let err = copy_assets(&missing, &dir.join("project"), &staging, None, &[]).unwrap_err();
+        assert!(format!("{:#}", err).contains("first.png"), "{:#}", err);
+    }
+
+    #[test]
+    fn finds_files_pdc_left_out() {
+        let dir = TestDir::new("pdc-output");
+        let staging = dir.join("staging");
+        let pdx = dir.join("Game.pdx");
+        for (root, files) in [
+            (
+                &staging,
+                &[
+                    "pdxinfo",
+                    "pdex.elf",
+                    "main.lua",
+                    "images/player.png",
+                    "images/walk-table-32-32.png",
+                    "images/legacy.bmp",
+                    "notes.txt",
+                ][..],
+            ),
+            (
+                &pdx,
+                &[
+                    "pdxinfo",
+                    "pdex.bin",
+                    "main.pdz",
+                    "images/player.pdi",
+                    "images/walk.pdt",
+                ][..],
+            ),
+        ]
+        .iter()
+        {
+            for file in files.iter() {
+                let path = root.join(file);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, file).unwrap();
+            }
+        }
+
+        assert_eq!(
+            missing_from_pdx(&staging, &pdx).unwrap(),
+            vec![
+                PathBuf::from("images").join("legacy.bmp"),
+                PathBuf::from("notes.txt")
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_converted_source_files() {
+        let dir = TestDir::new("copy-sources");
+        let images = dir.join("project").join("images");
+        fs::create_dir_all(&images).unwrap();
+        for name in ["player.aseprite", "player.png", "enemy.PSD", "sketch.psd"].iter() {
+            fs::write(images.join(name), name).unwrap();
+        }
+        let staging = dir.join("staging");
+        fs::create_dir_all(staging.join("images")).unwrap();
+        fs::write(staging.join("images").join("player.aseprite"), "stale").unwrap();
+        let assets = vec![Asset::Path("images".to_string())];
+        let source_extensions = vec!["aseprite".to_string(), "psd".to_string()];
+
+        copy_assets(
+            &assets,
+            &dir.join("project"),
+            &staging,
+            None,
+            &source_extensions,
+        )
+        .unwrap();
+        let mut staged: Vec<String> = fs::read_dir(staging.join("images"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        staged.sort();
+        // Only sources with nothing converted from them next to them are kept.
+        assert_eq!(staged, vec!["enemy.PSD", "player.png", "sketch.psd"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copies_only_assets_modified_since() {
+        use std::{fs::File, time::Duration};
+
+        let dir = TestDir::new("copy-since");
+        let project = dir.join("project");
+        let levels = project.join("levels");
+        fs::create_dir_all(&levels).unwrap();
+        fs::write(levels.join("a.json"), "a").unwrap();
+        fs::write(levels.join("b.json"), "b").unwrap();
+        fs::write(project.join("top.json"), "top").unwrap();
+        let staging = dir.join("staging");
+        let assets = vec![
+            Asset::Path("levels".to_string()),
+            Asset::Path("top.json".to_string()),
+        ];
+        let dirs = copy_assets(&assets, &project, &staging, None, &[]).unwrap();
+
+        let old = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        let since = old + Duration::from_secs(10);
+        let new = old + Duration::from_secs(100);
+        let set_modified = |path: &Path, time| {
+            File::open(path).unwrap().set_modified(time).unwrap();
+        };
+        let staged = |path: &str| fs::read_to_string(staging.join(path)).unwrap();
+
+        // A file edited in place is copied although its directory wasn't modified.
+        fs::write(levels.join("a.json"), "a2").unwrap();
+        fs::write(project.join("top.json"), "top2").unwrap();
+        set_modified(&levels.join("a.json"), new);
+        set_modified(&levels.join("b.json"), old);
+        set_modified(&project.join("top.json"), old);
+        set_modified(&levels, old);
+        let last_copy = LastCopy { time: since, dirs };
+        let dirs = copy_assets(&assets, &project, &staging, Some(&last_copy), &[]).unwrap();
+        assert_eq!(staged("levels/a.json"), "a2");
+        assert_eq!(staged("levels/b.json"), "b");
+        assert_eq!(staged("top.json"), "top");
+
+        // The unmodified directory is not listed again, so a file slipped into it unseen is
+        // not picked up.
+        fs::write(levels.join("hidden.json"), "hidden").unwrap();
+        set_modified(&levels, old);
+        let last_copy = LastCopy { time: since, dirs };
+        let dirs = copy_assets(&assets, &project, &staging, Some(&last_copy), &[]).unwrap();
+        assert!(!staging.join("levels").join("hidden.json").exists());
+
+        // Once the directory is modified, new entries are copied and deleted ones removed.
+        fs::remove_file(levels.join("b.json")).unwrap();
+        fs::create_dir(levels.join("deep")).unwrap();
+        fs::write(levels.join("deep").join("c.json"), "c").unwrap();
+        set_modified(&levels, new);
+        let last_copy = LastCopy { time: since, dirs };
+        copy_assets(&assets, &project, &staging, Some(&last_copy), &[]).unwrap();
+        assert_eq!(staged("levels/deep/c.json"), "c");
+        assert_eq!(staged("levels/hidden.json"), "hidden");
+        assert!(!staging.join("levels").join("b.json").exists());
+        assert_eq!(staged("levels/a.json"), "a2");
+    }
+}
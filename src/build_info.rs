@@ -0,0 +1,114 @@
+use anyhow::{Context, Error};
+use log::info;
+use std::{
+    fs,
+    path::Path,
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Name of the environment variable that gives the game the path of the generated module.
+pub const BUILD_INFO_ENV: &str = "CRANK_BUILD_INFO";
+
+/// Formats seconds since the epoch as an ISO 8601 UTC timestamp, using the civil-from-days
+/// algorithm so no date library is needed.
+pub fn utc_timestamp(unix_time: u64) -> String {
+    let days = (unix_time / 86400) as i64;
+    let seconds = unix_time % 86400;
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+fn git(project_path: &Path, args: &[&str]) -> Option<String> {
+    Command::new("git")
+        .args(args)
+        .current_dir(project_path)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// The files with uncommitted changes in `project_path`, as `git status --porcelain` lists
+/// them, or `None` outside of a git repository.
+pub fn uncommitted_changes(project_path: &Path) -> Option<Vec<String>> {
+    let status = git(project_path, &["status", "--porcelain"])?;
+    Some(status.lines().map(String::from).collect())
+}
+
+/// The short hash of the commit checked out in `project_path`, with `-dirty` appended when
+/// there are uncommitted changes, or an empty string outside of a git repository.
+pub fn git_commit(project_path: &Path) -> String {
+    match git(project_path, &["rev-parse", "--short", "HEAD"]) {
+        Some(commit)
+            if uncommitted_changes(project_path).is_some_and(|changes| !changes.is_empty()) =>
+        {
+            format!("{}-dirty", commit)
+        }
+        Some(commit) => commit,
+        None => String::new(),
+    }
+}
+
+fn module_source(unix_time: u64, commit: &str, version: &str) -> String {
+    format!(
+        "// Generated by crank for each build.\n\
+        pub const BUILD_TIMESTAMP: &str = {:?};\n\
+        pub const BUILD_UNIX_TIME: u64 = {};\n\
+        pub const GIT_COMMIT: &str = {:?};\n\
+        pub const VERSION: &str = {:?};\n",
+        utc_timestamp(unix_time),
+        unix_time,
+        commit,
+        version
+    )
+}
+
+/// Writes the build info module to `path` for the game to `include!`.
+pub fn write(path: &Path, project_path: &Path, version: &str) -> Result<(), Error> {
+    let unix_time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    let source = module_source(unix_time, &git_commit(project_path), version);
+    info!("build info:\n{}", source);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("Creating {:?}", parent))?;
+    }
+    fs::write(path, source).with_context(|| format!("Writing {:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generates_consts() {
+        assert_eq!(utc_timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(utc_timestamp(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(
+            module_source(1_792_200_965, "abc1234", "0.1.0"),
+            "// Generated by crank for each build.\n\
+            pub const BUILD_TIMESTAMP: &str = \"2026-10-17T01:36:05Z\";\n\
+            pub const BUILD_UNIX_TIME: u64 = 1792200965;\n\
+            pub const GIT_COMMIT: &str = \"abc1234\";\n\
+            pub const VERSION: &str = \"0.1.0\";\n"
+        );
+    }
+}
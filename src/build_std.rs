@@ -0,0 +1,95 @@
+use anyhow::{Context, Error};
+use log::info;
+use std::{fs, path::Path};
+
+/// The crates `-Zbuild-std=core,alloc` builds from the toolchain's sources, with dashes in
+/// their names written as underscores.
+const BUILD_STD_CRATES: &[&str] = &[
+    "core",
+    "alloc",
+    "compiler_builtins",
+    "rustc_std_workspace_core",
+];
+
+/// The crate a file or directory cargo keeps in `deps`, `.fingerprint` or `build` belongs to,
+/// such as `core` for `libcore-0123abcd.rlib` or `.fingerprint/core-0123abcd`.
+fn crate_of(name: &str) -> Option<String> {
+    let (stem, _hash) = name.split('.').next()?.rsplit_once('-')?;
+    let stem = if name.ends_with(".rlib") || name.ends_with(".rmeta") {
+        stem.strip_prefix("lib")?
+    } else {
+        stem
+    };
+    Some(stem.replace('-', "_"))
+}
+
+/// Removes what cargo built for `-Zbuild-std` from `profile_dir`, the directory of one target
+/// and profile such as `target/thumbv7em-none-eabihf/debug`, so that the next build compiles
+/// core and alloc again while the game's other dependencies stay built. Returns how many
+/// files and directories were removed.
+pub fn clear(profile_dir: &Path) -> Result<usize, Error> {
+    let mut removed = 0;
+    for dir in ["deps", ".fingerprint", "build"].iter() {
+        let dir = profile_dir.join(dir);
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries {
+            let path = entry?.path();
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            if !crate_of(&name).is_some_and(|name| BUILD_STD_CRATES.contains(&name.as_str())) {
+                continue;
+            }
+            info!("removing {:?}", path);
+            if path.is_dir() {
+                fs::remove_dir_all(&path)
+            } else {
+                fs::remove_file(&path)
+            }
+            .with_context(|| format!("Removing {:?}", path))?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn clears_only_build_std_artifacts() {
+        let dir = TestDir::new("build-std");
+        let files = [
+            "deps/libcore-0123abcd.rlib",
+            "deps/libcore-0123abcd.rmeta",
+            "deps/core-0123abcd.d",
+            "deps/librustc_std_workspace_core-4567.rlib",
+            "deps/libcrankstart-89ab.rlib",
+            "deps/libcore_maths-cdef.rlib",
+            ".fingerprint/alloc-0123/lib-alloc",
+            ".fingerprint/rustc-std-workspace-core-4567/lib",
+            ".fingerprint/my_game-89ab/lib-my_game",
+            "build/compiler_builtins-0123/output",
+        ];
+        for file in files.iter() {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, file).unwrap();
+        }
+
+        assert_eq!(clear(&dir).unwrap(), 7);
+        for kept in [
+            "deps/libcrankstart-89ab.rlib",
+            "deps/libcore_maths-cdef.rlib",
+            ".fingerprint/my_game-89ab/lib-my_game",
+        ]
+        .iter()
+        {
+            assert!(dir.join(kept).is_file(), "{} was removed", kept);
+        }
+        assert!(!dir.join("build").join("compiler_builtins-0123").exists());
+    }
+}
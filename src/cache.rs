@@ -0,0 +1,123 @@
+use anyhow::{Context, Error};
+use log::debug;
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs,
+    hash::{Hash, Hasher},
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// A fingerprint of everything that goes into a pdx, used to skip relinking and rerunning pdc
+/// when none of it changed since the last successful build.
+///
+/// The hash is only compared against keys written by the same crank binary, so it doesn't
+/// need to be stable across Rust versions.
+pub struct BuildKey {
+    hasher: DefaultHasher,
+}
+
+impl BuildKey {
+    pub fn new() -> Self {
+        let mut hasher = DefaultHasher::new();
+        env!("CARGO_PKG_VERSION").hash(&mut hasher);
+        BuildKey { hasher }
+    }
+
+    pub fn add_str(&mut self, label: &str, value: &str) {
+        label.hash(&mut self.hasher);
+        value.hash(&mut self.hasher);
+    }
+
+    /// Adds the contents of a file, or of every file below a directory along with their
+    /// relative paths. A missing path is hashed as missing rather than failing, so that
+    /// removing an input changes the key.
+    pub fn add_path(&mut self, label: &str, path: &Path) -> Result<(), Error> {
+        label.hash(&mut self.hasher);
+        if path.is_dir() {
+            let mut entries = fs::read_dir(path)
+                .with_context(|| format!("Reading {:?}", path))?
+                .collect::<Result<Vec<_>, _>>()?;
+            entries.sort_by_key(|entry| entry.file_name());
+            for entry in entries {
+                let name = entry.file_name();
+                self.add_path(&name.to_string_lossy(), &entry.path())?;
+            }
+        } else if path.is_file() {
+            fs::read(path)
+                .with_context(|| format!("Reading {:?}", path))?
+                .hash(&mut self.hasher);
+        } else {
+            "missing".hash(&mut self.hasher);
+        }
+        Ok(())
+    }
+
+    pub fn finish(&self) -> String {
+        format!("{:016x}", self.hasher.finish())
+    }
+}
+
+/// Whether `key_path` holds `key`, meaning the output it was stored for is up to date.
+pub fn is_fresh(key_path: &Path, key: &str) -> bool {
+    let fresh = fs::read_to_string(key_path).is_ok_and(|stored| stored.trim() == key);
+    debug!("build key {} fresh: {}", key, fresh);
+    fresh
+}
+
+pub fn store(key_path: &Path, key: &str) -> Result<(), Error> {
+    fs::write(key_path, key).with_context(|| format!("Writing {:?}", key_path))
+}
+
+/// The time stored in the stamp at `stamp_path` by `store_stamp`, if it was stored for `key`.
+pub fn read_stamp(stamp_path: &Path, key: &str) -> Option<SystemTime> {
+    let stamp = fs::read_to_string(stamp_path).ok()?;
+    let (time, stored_key) = stamp.trim().split_once(' ')?;
+    if stored_key != key {
+        debug!("stamp {:?} is for {}, not {}", stamp_path, stored_key, key);
+        return None;
+    }
+    let (secs, nanos) = time.split_once('.')?;
+    Some(UNIX_EPOCH + Duration::new(secs.parse().ok()?, nanos.parse().ok()?))
+}
+
+/// Records that the output `stamp_path` stands for was up to date with its inputs, as
+/// described by `key`, at `time`.
+pub fn store_stamp(stamp_path: &Path, key: &str, time: SystemTime) -> Result<(), Error> {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let stamp = format!(
+        "{}.{:09} {}",
+        since_epoch.as_secs(),
+        since_epoch.subsec_nanos(),
+        key
+    );
+    fs::write(stamp_path, stamp).with_context(|| format!("Writing {:?}", stamp_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn key_changes_with_contents() {
+        let dir = TestDir::new("cache");
+        fs::create_dir_all(dir.join("images")).unwrap();
+        fs::write(dir.join("images").join("a.png"), "first").unwrap();
+
+        let key_for = |dir: &Path| {
+            let mut key = BuildKey::new();
+            key.add_path("assets", dir).unwrap();
+            key.finish()
+        };
+        let first = key_for(&dir);
+        assert_eq!(first, key_for(&dir));
+        fs::write(dir.join("images").join("a.png"), "second").unwrap();
+        assert_ne!(first, key_for(&dir));
+
+        let key_path = dir.join("key");
+        store(&key_path, &first).unwrap();
+        assert!(is_fresh(&key_path, &first));
+        assert!(!is_fresh(&key_path, &key_for(&dir)));
+    }
+}
@@ -3,9 +3,9 @@ use std::collections::HashMap;
 use std::path::PathBuf;
 use std::str::FromStr;
 
-pub const CFG_DIR: &'static str = ".Playdate";
-pub const CFG_FILENAME: &'static str = "config";
-pub const CFG_KEY_SDK_ROOT: &'static str = "SDKRoot";
+pub const CFG_DIR: &str = ".Playdate";
+pub const CFG_FILENAME: &str = "config";
+pub const CFG_KEY_SDK_ROOT: &str = "SDKRoot";
 
 pub struct SdkCfg(HashMap<String, String>);
 
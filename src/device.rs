@@ -0,0 +1,714 @@
+use crate::settings::{self, DeviceSettings, Parity, Settings};
+use anyhow::{anyhow, bail, Context, Error};
+use log::info;
+use std::{
+    env, fmt, fs,
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    path::PathBuf,
+    sync::mpsc,
+    thread, time,
+};
+
+use std::process::Command;
+
+#[cfg(unix)]
+use std::path::Path;
+
+#[cfg(target_os = "linux")]
+use walkdir::WalkDir;
+
+/// Prefix of a serial device setting that names a serial-over-network bridge rather than a
+/// local device node.
+const REMOTE_PREFIX: &str = "tcp://";
+
+/// The explicitly chosen serial device: `--device-serial`, then `PLAYDATE_SERIAL_DEVICE`, then
+/// the configured `[device] serial_device`.
+pub fn configured_device(device_serial: Option<&str>, settings: &Settings) -> Option<String> {
+    device_serial
+        .map(String::from)
+        .or_else(|| env::var("PLAYDATE_SERIAL_DEVICE").ok())
+        .or_else(|| settings.device.serial_device.clone())
+}
+
+/// The `host:port` of a remote device, if the serial device is given as `tcp://host:port`.
+pub fn remote_address(device_serial: Option<&str>, settings: &Settings) -> Option<String> {
+    configured_device(device_serial, settings)
+        .and_then(|device| device.strip_prefix(REMOTE_PREFIX).map(String::from))
+}
+
+/// Start of the name macOS gives a Playdate's serial device, followed by its serial number.
+#[cfg(target_os = "macos")]
+const MACOS_SERIAL_PREFIX: &str = "cu.usbmodemPDU1";
+
+/// The entries of `dir` whose names start with `prefix`, in name order.
+#[cfg(unix)]
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn devices_named(dir: &Path, prefix: &str) -> Vec<PathBuf> {
+    let mut devices: Vec<PathBuf> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(prefix))
+        .map(|entry| entry.path())
+        .collect();
+    devices.sort();
+    devices
+}
+
+/// Path of the Playdate's serial device: the configured device if there is one, then a
+/// platform-specific guess.
+#[cfg(unix)]
+pub fn serial_device_path(device_serial: Option<&str>, settings: &Settings) -> PathBuf {
+    let configured_device = configured_device(device_serial, settings);
+    #[cfg(target_os = "macos")]
+    let modem_path = PathBuf::from(
+        configured_device
+            // The device is named after the Playdate's serial number
+            .or_else(|| {
+                let devices = devices_named(Path::new("/dev"), MACOS_SERIAL_PREFIX);
+                devices
+                    .first()
+                    .map(|path| path.to_string_lossy().into_owned())
+            })
+            .unwrap_or(String::from("/dev/cu.usbmodemPDU1_Y0005491")),
+    );
+    #[cfg(target_os = "linux")]
+    let modem_path = PathBuf::from(
+        configured_device
+            // On Linux, we can use named symlinks to find the device in most cases
+            .unwrap_or_else(|| find_serial_device().unwrap_or(String::from("/dev/ttyACM0"))),
+    );
+    #[cfg(all(not(target_os = "linux"), not(target_os = "macos")))]
+    let modem_path = PathBuf::from(configured_device.unwrap_or(String::from("/dev/ttyACM0")));
+    modem_path
+}
+
+/// The folder games are installed into unless another one is chosen.
+pub const DEFAULT_GAMES_DIR: &str = "/Games";
+
+/// The folder on the device to install into: `--device-games-dir`, then the configured
+/// `[device] games_dir`, then `/Games`. The launcher only looks for games in `/Games`, so the
+/// folder has to be it or one of its subfolders. Returns the path as the device's `run`
+/// command expects it, such as `/Games/Dev`.
+pub fn games_dir(device_games_dir: Option<&str>, settings: &Settings) -> Result<String, Error> {
+    let dir = device_games_dir
+        .or(settings.device.games_dir.as_deref())
+        .unwrap_or(DEFAULT_GAMES_DIR);
+    let components: Vec<&str> = dir.split('/').filter(|part| !part.is_empty()).collect();
+    if components.first() != Some(&"Games") {
+        bail!("The device games folder {:?} is not inside /Games", dir);
+    }
+    if let Some(part) = components
+        .iter()
+        .find(|part| matches!(**part, "." | "..") || part.contains('\\'))
+    {
+        bail!(
+            "The device games folder {:?} contains the invalid part {:?}",
+            dir,
+            part
+        );
+    }
+    Ok(format!("/{}", components.join("/")))
+}
+
+/// Name the Playdate gives its data disk.
+#[cfg(unix)]
+const DATA_VOLUME_NAME: &str = "PLAYDATE";
+
+/// Whether a volume is named like the Playdate's data disk, which the system numbers, as in
+/// `PLAYDATE 1` or `PLAYDATE1`, when another volume already has the name.
+#[cfg(unix)]
+fn is_data_volume_name(name: &str) -> bool {
+    name.strip_prefix(DATA_VOLUME_NAME).is_some_and(|rest| {
+        let number = rest.strip_prefix(' ').unwrap_or(rest);
+        rest.is_empty() || (!number.is_empty() && number.chars().all(|c| c.is_ascii_digit()))
+    })
+}
+
+/// The mounted Playdate data disks in `volumes_dir`, such as `/Volumes`: the volumes named
+/// like one that have a `Games` folder, in name order.
+#[cfg(unix)]
+pub fn data_volumes(volumes_dir: &Path) -> Vec<PathBuf> {
+    let mut volumes: Vec<PathBuf> = fs::read_dir(volumes_dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| is_data_volume_name(&entry.file_name().to_string_lossy()))
+        .map(|entry| entry.path())
+        .filter(|path| path.join("Games").is_dir())
+        .collect();
+    volumes.sort();
+    volumes
+}
+
+/// How long to wait for a data disk that wasn't mounted before the device entered data disk
+/// mode, before settling for one that already was.
+#[cfg(unix)]
+pub const NEW_DATA_VOLUME_TIMEOUT: time::Duration = time::Duration::from_secs(20);
+
+/// The data disks that may belong to the device just put in data disk mode: those that weren't
+/// mounted `before` it was. Only once `waited_out`, with none new, all of them, as the device
+/// may have been in data disk mode already.
+#[cfg(unix)]
+pub fn data_volume_candidates(
+    mounted: &[PathBuf],
+    before: &[PathBuf],
+    waited_out: bool,
+) -> Vec<PathBuf> {
+    let new: Vec<PathBuf> = mounted
+        .iter()
+        .filter(|volume| !before.contains(volume))
+        .cloned()
+        .collect();
+    if new.is_empty() && waited_out {
+        mounted.to_vec()
+    } else {
+        new
+    }
+}
+
+/// Waits up to `timeout` for the Playdate's serial device to show up, or for a remote device to
+/// accept a connection. Returns `None` on platforms where crank can't tell whether a local
+/// device is connected.
+pub fn wait_for_device(
+    device_serial: Option<&str>,
+    settings: &Settings,
+    timeout: time::Duration,
+) -> Option<bool> {
+    if let Some(address) = remote_address(device_serial, settings) {
+        let reachable = connect(&address, timeout).is_ok();
+        info!("remote device at {} reachable: {}", address, reachable);
+        return Some(reachable);
+    }
+    #[cfg(unix)]
+    {
+        let poll_interval = time::Duration::from_millis(100);
+        let start = time::Instant::now();
+        loop {
+            let modem_path = serial_device_path(device_serial, settings);
+            if modem_path.exists() {
+                info!("found device at {:?}", modem_path);
+                return Some(true);
+            }
+            if start.elapsed() >= timeout {
+                info!("no device at {:?} after {:?}", modem_path, timeout);
+                return Some(false);
+            }
+            thread::sleep(poll_interval);
+        }
+    }
+    #[cfg(windows)]
+    {
+        None
+    }
+}
+
+/// Line settings of a local serial device, from `[device]` or the Playdate's defaults.
+#[derive(Debug, PartialEq)]
+pub struct SerialConfig {
+    pub baud_rate: u32,
+    pub data_bits: u8,
+    pub parity: Parity,
+    pub stop_bits: u8,
+}
+
+impl SerialConfig {
+    pub fn from_settings(device: &DeviceSettings) -> Result<SerialConfig, Error> {
+        let config = SerialConfig {
+            baud_rate: device.baud_rate.unwrap_or(settings::DEFAULT_BAUD_RATE),
+            data_bits: device.data_bits.unwrap_or(settings::DEFAULT_DATA_BITS),
+            parity: device.parity.unwrap_or_default(),
+            stop_bits: device.stop_bits.unwrap_or(settings::DEFAULT_STOP_BITS),
+        };
+        if config.baud_rate == 0 {
+            bail!("[device] baud_rate must be greater than 0");
+        }
+        if !(5..=8).contains(&config.data_bits) {
+            bail!(
+                "[device] data_bits must be from 5 to 8, not {}",
+                config.data_bits
+            );
+        }
+        if !(1..=2).contains(&config.stop_bits) {
+            bail!(
+                "[device] stop_bits must be 1 or 2, not {}",
+                config.stop_bits
+            );
+        }
+        Ok(config)
+    }
+
+    /// Arguments for `stty` that set the line up and make it raw. Replies can contain binary
+    /// data, such as the frame buffer, which the terminal would otherwise translate or buffer
+    /// by line.
+    #[cfg_attr(windows, allow(dead_code))]
+    fn stty_args(&self) -> Vec<String> {
+        let parity: &[&str] = match self.parity {
+            Parity::None => &["-parenb"],
+            Parity::Even => &["parenb", "-parodd"],
+            Parity::Odd => &["parenb", "parodd"],
+        };
+        let mut args = vec![self.baud_rate.to_string(), format!("cs{}", self.data_bits)];
+        args.extend(parity.iter().map(|arg| arg.to_string()));
+        args.push(
+            if self.stop_bits == 2 {
+                "cstopb"
+            } else {
+                "-cstopb"
+            }
+            .to_string(),
+        );
+        args.extend(["raw", "-echo"].iter().map(|arg| arg.to_string()));
+        args
+    }
+
+    /// Arguments for Windows' `mode.com` that set up `port`, given as `COM3`, `COM3:` or
+    /// `\\.\COM3`. `mode` wants the port name alone, followed by a colon.
+    #[cfg_attr(unix, allow(dead_code))]
+    fn mode_args(&self, port: &str) -> Vec<String> {
+        let parity = match self.parity {
+            Parity::None => "N",
+            Parity::Even => "E",
+            Parity::Odd => "O",
+        };
+        let port = port.trim_start_matches(r"\\.\").trim_end_matches(':');
+        vec![
+            format!("{}:", port.to_uppercase()),
+            format!("BAUD={}", self.baud_rate),
+            format!("PARITY={}", parity),
+            format!("DATA={}", self.data_bits),
+            format!("STOP={}", self.stop_bits),
+        ]
+    }
+}
+
+impl fmt::Display for SerialConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parity = match self.parity {
+            Parity::None => 'N',
+            Parity::Even => 'E',
+            Parity::Odd => 'O',
+        };
+        write!(
+            f,
+            "{} baud, {}{}{}",
+            self.baud_rate, self.data_bits, parity, self.stop_bits
+        )
+    }
+}
+
+/// A connection to the Playdate's serial console, which accepts one text command per line.
+pub trait Console: Read + Write {}
+
+impl<T: Read + Write> Console for T {}
+
+const CONNECT_TIMEOUT: time::Duration = time::Duration::from_secs(5);
+const READ_TIMEOUT: time::Duration = time::Duration::from_secs(10);
+
+fn connect(address: &str, timeout: time::Duration) -> Result<TcpStream, Error> {
+    let socket_address = address
+        .to_socket_addrs()
+        .with_context(|| format!("Resolving {}", address))?
+        .next()
+        .ok_or_else(|| anyhow!("{} did not resolve to an address", address))?;
+    TcpStream::connect_timeout(&socket_address, timeout)
+        .with_context(|| format!("Connecting to {}", address))
+}
+
+/// Opens the serial console of the chosen device, either the local device node or a
+/// `tcp://host:port` bridge.
+pub fn open_console(
+    device_serial: Option<&str>,
+    settings: &Settings,
+) -> Result<Box<dyn Console>, Error> {
+    if let Some(address) = remote_address(device_serial, settings) {
+        info!("connecting to remote device at {}", address);
+        let stream = connect(&address, CONNECT_TIMEOUT)?;
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        return Ok(Box::new(stream));
+    }
+    #[cfg(unix)]
+    let path = serial_device_path(device_serial, settings);
+    #[cfg(windows)]
+    let path = PathBuf::from(configured_device(device_serial, settings).ok_or_else(|| {
+        anyhow!("Set PLAYDATE_SERIAL_DEVICE or --device-serial to the Playdate's COM port")
+    })?);
+    info!("opening serial device {:?}", path);
+    let config = SerialConfig::from_settings(&settings.device)?;
+    #[cfg(unix)]
+    let mut command = {
+        let flag = if cfg!(target_os = "macos") {
+            "-f"
+        } else {
+            "-F"
+        };
+        let mut command = Command::new("stty");
+        command.arg(flag).arg(&path).args(config.stty_args());
+        command
+    };
+    #[cfg(windows)]
+    let mut command = {
+        let mut command = Command::new("mode.com");
+        command.args(config.mode_args(&path.to_string_lossy()));
+        command
+    };
+    info!("serial setup: {:?}", command);
+    let status = command.status();
+    if !matches!(status, Ok(status) if status.success()) {
+        eprintln!(
+            "Warning: could not set up {:?} for {}, replies may be garbled.",
+            path, config
+        );
+    }
+    let file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("Opening serial device {:?}", path))?;
+    Ok(Box::new(file))
+}
+
+/// Sends one command, such as `run /Games/Game.pdx`, to the console.
+pub fn send_command(console: &mut dyn Console, command: &str) -> Result<(), Error> {
+    info!("serial command: {}", command);
+    console.write_all(format!("{}\n", command).as_bytes())?;
+    console.flush()?;
+    Ok(())
+}
+
+/// The console command that reboots the device.
+const RESET_COMMAND: &str = "reset";
+/// How long a local device takes to drop off the bus after it is told to reboot.
+const RESET_DISCONNECT_TIMEOUT: time::Duration = time::Duration::from_secs(5);
+
+/// Reboots the device and waits up to `timeout` for it to come back, so that a game that
+/// crashed or hung before doesn't affect the next run.
+pub fn reset_device(
+    device_serial: Option<&str>,
+    settings: &Settings,
+    timeout: time::Duration,
+) -> Result<(), Error> {
+    let mut console = open_console(device_serial, settings)?;
+    send_command(&mut *console, RESET_COMMAND)?;
+    drop(console);
+
+    #[cfg(unix)]
+    if remote_address(device_serial, settings).is_none() {
+        let modem_path = serial_device_path(device_serial, settings);
+        let start = time::Instant::now();
+        while modem_path.exists() && start.elapsed() < RESET_DISCONNECT_TIMEOUT {
+            thread::sleep(time::Duration::from_millis(100));
+        }
+        info!("device gone after {:?}", start.elapsed());
+    }
+    match wait_for_device(device_serial, settings, timeout) {
+        Some(true) => Ok(()),
+        Some(false) => bail!(
+            "The device did not come back within {:?} of resetting",
+            timeout
+        ),
+        None => {
+            // Without a way to see the device, give it the time it usually takes.
+            thread::sleep(RESET_DISCONNECT_TIMEOUT);
+            Ok(())
+        }
+    }
+}
+
+/// The line of the reply to `version` that holds the firmware's SDK version.
+const VERSION_PREFIX: &str = "SDK=";
+/// How much output to read while waiting for the version before deciding the device is not a
+/// Playdate.
+const MAX_VERSION_REPLY: usize = 16 * 1024;
+
+/// Sends `version` and returns the firmware version the device reports, failing if the reply
+/// doesn't look like a Playdate's.
+pub fn read_version(console: &mut dyn Console) -> Result<String, Error> {
+    send_command(console, "version")?;
+    let mut line = Vec::new();
+    let mut byte = [0u8; 1];
+    for _ in 0..MAX_VERSION_REPLY {
+        if console.read(&mut byte)? == 0 {
+            break;
+        }
+        if byte[0] != b'\n' {
+            line.push(byte[0]);
+            continue;
+        }
+        let text = String::from_utf8_lossy(&line);
+        info!("version reply: {}", text.trim());
+        if let Some(version) = text.trim().strip_prefix(VERSION_PREFIX) {
+            return Ok(version.to_string());
+        }
+        line.clear();
+    }
+    bail!("The device did not answer the version command like a Playdate");
+}
+
+/// Checks that the chosen serial device is a responsive Playdate before crank puts it in
+/// data disk mode, returning its firmware version. Reading a local device node can't time
+/// out on its own, so the check runs on its own thread.
+pub fn touch_device(
+    device_serial: Option<&str>,
+    settings: &Settings,
+    timeout: time::Duration,
+) -> Result<String, Error> {
+    let device_serial = device_serial.map(String::from);
+    let settings = settings.clone();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let result = open_console(device_serial.as_deref(), &settings)
+            .and_then(|mut console| read_version(&mut *console));
+        let _ = sender.send(result);
+    });
+    receiver.recv_timeout(timeout).map_err(|_| {
+        anyhow!(
+            "The device did not respond like a Playdate within {:?}",
+            timeout
+        )
+    })?
+}
+
+#[cfg(target_os = "linux")]
+/// Finds the canonical (resolved) path for the Playdate serial device.  If multiple Playdate devices are
+/// found, warns and returns the first.  If none is found, returns None.  If any error occurs,
+/// returns None.
+pub fn find_serial_device() -> Option<String> {
+    // Walk through this directory to find Playdate device filenames
+    let directory = "/dev/serial/by-id";
+    let filename_prefix = "usb-Panic_Inc_Playdate_PDU1-";
+
+    let walker = WalkDir::new(directory)
+        .min_depth(1)
+        .max_depth(1)
+        // Don't follow links (yet) because we want file_name to give us the name in this directory
+        .follow_links(false)
+        // If there are multiple, we let the user know and take the first; sort so it's consistent.
+        // If the user wants a different one, they can set PLAYDATE_SERIAL_DEVICE.
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| {
+            e.file_name()
+                .to_str()
+                .map(|s| s.starts_with(filename_prefix))
+                .unwrap_or(false)
+        })
+        .filter_map(|e| e.ok());
+
+    // See what we found
+    let mut result: Option<PathBuf> = None;
+    for entry in walker {
+        match result {
+            // If there are multiple matches, let the user know, and return the first
+            Some(ref existing) => {
+                println!(
+                    "Found multiple Playdate devices in {}, using first: {}",
+                    directory,
+                    existing.display()
+                );
+                break;
+            }
+            None => {
+                result = Some(entry.into_path());
+            }
+        }
+    }
+
+    if let Some(path) = result {
+        // Fully resolve the link, which should result in something like "/dev/ttyACM0"
+        let resolved = fs::canonicalize(path).ok()?;
+        // Quick check that it did what we expected
+        if resolved
+            .to_str()
+            .map(|s| s.contains("tty"))
+            .unwrap_or(false)
+        {
+            println!("Resolved Playdate serial device to: {}", resolved.display());
+            // Other code expects String paths
+            return Some(resolved.to_string_lossy().into_owned());
+        } else {
+            eprintln!(
+                "Warning: found a device at '{}' but it's not named like we expect.  Using the default.",
+                resolved.display()
+            );
+            return None;
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(unix)]
+    use crate::test_dir::TestDir;
+    use std::{io::BufRead, io::BufReader, net::TcpListener};
+
+    #[test]
+    fn sends_commands_to_remote_device() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let device_serial = format!("tcp://{}", listener.local_addr().unwrap());
+        let settings = Settings::default();
+        assert_eq!(
+            remote_address(Some(&device_serial), &settings),
+            Some(listener.local_addr().unwrap().to_string())
+        );
+
+        let mut console = open_console(Some(&device_serial), &settings).unwrap();
+        send_command(&mut *console, "run /Games/Game.pdx").unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line).unwrap();
+        assert_eq!(line, "run /Games/Game.pdx\n");
+    }
+
+    #[test]
+    fn normalizes_games_dir() {
+        let mut settings = Settings::default();
+        assert_eq!(games_dir(None, &settings).unwrap(), "/Games");
+        assert_eq!(
+            games_dir(Some("Games/Dev/"), &settings).unwrap(),
+            "/Games/Dev"
+        );
+        settings.device.games_dir = Some("/Games//Beta".to_string());
+        assert_eq!(games_dir(None, &settings).unwrap(), "/Games/Beta");
+        assert!(games_dir(Some("/Data"), &settings).is_err());
+        assert!(games_dir(Some("/Games/../System"), &settings).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn finds_devices_by_prefix() {
+        let dir = TestDir::new("devices");
+        for name in [
+            "cu.usbmodemPDU1_Y0009",
+            "cu.Bluetooth",
+            "cu.usbmodemPDU1_Y0002",
+        ]
+        .iter()
+        {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        assert_eq!(
+            devices_named(&dir, "cu.usbmodemPDU1"),
+            vec![
+                dir.join("cu.usbmodemPDU1_Y0002"),
+                dir.join("cu.usbmodemPDU1_Y0009")
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn finds_data_volumes() {
+        let dir = TestDir::new("volumes");
+        for name in [
+            "PLAYDATE",
+            "PLAYDATE 1",
+            "PLAYDATE2",
+            "PLAYDATE 3",
+            "PLAYDATEX",
+            "Data",
+        ]
+        .iter()
+        {
+            fs::create_dir_all(dir.join(name)).unwrap();
+        }
+        for name in ["PLAYDATE", "PLAYDATE 1", "PLAYDATE2", "PLAYDATEX", "Data"].iter() {
+            fs::create_dir_all(dir.join(name).join("Games")).unwrap();
+        }
+        let volumes = data_volumes(&dir);
+        assert_eq!(
+            volumes,
+            vec![
+                dir.join("PLAYDATE"),
+                dir.join("PLAYDATE 1"),
+                dir.join("PLAYDATE2")
+            ]
+        );
+
+        let before = vec![dir.join("PLAYDATE")];
+        assert_eq!(
+            data_volume_candidates(&volumes[..2], &before, false),
+            vec![dir.join("PLAYDATE 1")]
+        );
+        // Another Playdate's data disk, mounted before, is not taken for the new one until
+        // the wait for a new one is over.
+        assert!(data_volume_candidates(&before, &before, false).is_empty());
+        assert_eq!(data_volume_candidates(&before, &before, true), before);
+    }
+
+    #[test]
+    fn serial_config_from_settings() {
+        let mut device = DeviceSettings::default();
+        let config = SerialConfig::from_settings(&device).unwrap();
+        assert_eq!(config.to_string(), "115200 baud, 8N1");
+        assert_eq!(
+            config.stty_args(),
+            vec!["115200", "cs8", "-parenb", "-cstopb", "raw", "-echo"]
+        );
+
+        device.baud_rate = Some(9600);
+        device.parity = Some(Parity::Odd);
+        device.stop_bits = Some(2);
+        let config = SerialConfig::from_settings(&device).unwrap();
+        assert_eq!(config.to_string(), "9600 baud, 8O2");
+        assert_eq!(
+            config.mode_args("com3"),
+            vec!["COM3:", "BAUD=9600", "PARITY=O", "DATA=8", "STOP=2"]
+        );
+        assert_eq!(config.mode_args(r"\\.\COM12")[0], "COM12:");
+        assert_eq!(config.mode_args("COM3:")[0], "COM3:");
+
+        device.data_bits = Some(9);
+        assert!(SerialConfig::from_settings(&device).is_err());
+    }
+
+    #[test]
+    fn resets_remote_device() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let device_serial = format!("tcp://{}", listener.local_addr().unwrap());
+        let device = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut line = String::new();
+            BufReader::new(stream).read_line(&mut line).unwrap();
+            assert_eq!(line, "reset\n");
+            // Accept the connection that checks the device is back.
+            let _ = listener.accept().unwrap();
+        });
+
+        reset_device(
+            Some(&device_serial),
+            &Settings::default(),
+            time::Duration::from_secs(5),
+        )
+        .unwrap();
+        device.join().unwrap();
+    }
+
+    #[test]
+    fn touches_remote_device() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let device_serial = format!("tcp://{}", listener.local_addr().unwrap());
+        let device = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut line = String::new();
+            BufReader::new(&stream).read_line(&mut line).unwrap();
+            assert_eq!(line, "version\n");
+            stream
+                .write_all(b"~version:\ntarget=DVT1\nbuild=abc\nSDK=2.5.0\n")
+                .unwrap();
+        });
+
+        let version = touch_device(
+            Some(&device_serial),
+            &Settings::default(),
+            time::Duration::from_secs(5),
+        )
+        .unwrap();
+        assert_eq!(version, "2.5.0");
+        device.join().unwrap();
+    }
+}
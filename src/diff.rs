@@ -0,0 +1,163 @@
+use crate::archive::{ArchivedFile, PdxContents};
+
+/// How a file differs between two pdx archives.
+#[derive(Debug, PartialEq)]
+pub enum FileChange {
+    Added(ArchivedFile),
+    Removed(ArchivedFile),
+    Changed {
+        old: ArchivedFile,
+        new: ArchivedFile,
+    },
+}
+
+/// A `pdxinfo` key whose value differs, with `None` where the key is absent.
+#[derive(Debug, PartialEq)]
+pub struct MetadataChange {
+    pub key: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// What changed between two pdx archives.
+#[derive(Debug, Default, PartialEq)]
+pub struct PdxDiff {
+    /// Changed files by path, in path order.
+    pub files: Vec<(String, FileChange)>,
+    pub unchanged: usize,
+    /// Changed `pdxinfo` keys, in the order they first appear in the old then the new file.
+    pub metadata: Vec<MetadataChange>,
+}
+
+/// Reads the `key=value` lines of a `pdxinfo`.
+fn pdxinfo_entries(pdxinfo: Option<&str>) -> Vec<(String, String)> {
+    pdxinfo
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+/// Compares two pdx archives file by file, by size and CRC-32, and key by key in `pdxinfo`.
+/// `pdxinfo` itself is left out of the files, since its changes are listed key by key.
+pub fn diff(old: &PdxContents, new: &PdxContents) -> PdxDiff {
+    let mut diff = PdxDiff::default();
+    let mut paths: Vec<&String> = old.files.keys().chain(new.files.keys()).collect();
+    paths.sort();
+    paths.dedup();
+    for path in paths.into_iter().filter(|path| *path != "pdxinfo") {
+        let change = match (old.files.get(path), new.files.get(path)) {
+            (Some(old), Some(new)) if old == new => {
+                diff.unchanged += 1;
+                continue;
+            }
+            (Some(&old), Some(&new)) => FileChange::Changed { old, new },
+            (Some(&old), None) => FileChange::Removed(old),
+            (None, Some(&new)) => FileChange::Added(new),
+            (None, None) => continue,
+        };
+        diff.files.push((path.clone(), change));
+    }
+
+    let old_info = pdxinfo_entries(old.pdxinfo.as_deref());
+    let new_info = pdxinfo_entries(new.pdxinfo.as_deref());
+    let value = |entries: &[(String, String)], key: &str| {
+        entries
+            .iter()
+            .find(|(entry_key, _)| entry_key == key)
+            .map(|(_, value)| value.clone())
+    };
+    let mut keys: Vec<&str> = Vec::new();
+    for (key, _) in old_info.iter().chain(new_info.iter()) {
+        if !keys.contains(&key.as_str()) {
+            keys.push(key);
+        }
+    }
+    for key in keys {
+        let (old, new) = (value(&old_info, key), value(&new_info, key));
+        if old != new {
+            diff.metadata.push(MetadataChange {
+                key: key.to_string(),
+                old,
+                new,
+            });
+        }
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contents(files: &[(&str, u64, u32)], pdxinfo: &str) -> PdxContents {
+        PdxContents {
+            files: files
+                .iter()
+                .map(|&(path, size, crc32)| (path.to_string(), ArchivedFile { size, crc32 }))
+                .collect(),
+            pdxinfo: Some(pdxinfo.to_string()),
+        }
+    }
+
+    #[test]
+    fn lists_file_and_metadata_changes() {
+        let file = |size, crc32| ArchivedFile { size, crc32 };
+        let old = contents(
+            &[
+                ("pdex.bin", 100, 1),
+                ("pdxinfo", 30, 2),
+                ("images/old.pdi", 10, 3),
+                ("sounds/jump.pda", 20, 4),
+            ],
+            "name=Game\nversion=1.0\nbuildNumber=3\n",
+        );
+        let new = contents(
+            &[
+                ("pdex.bin", 120, 5),
+                ("pdxinfo", 31, 6),
+                ("images/new.pdi", 11, 7),
+                ("sounds/jump.pda", 20, 4),
+            ],
+            "name=Game\nversion=1.1\ncontentWarning=Flashing lights\n",
+        );
+        assert_eq!(
+            diff(&old, &new),
+            PdxDiff {
+                files: vec![
+                    ("images/new.pdi".to_string(), FileChange::Added(file(11, 7))),
+                    (
+                        "images/old.pdi".to_string(),
+                        FileChange::Removed(file(10, 3))
+                    ),
+                    (
+                        "pdex.bin".to_string(),
+                        FileChange::Changed {
+                            old: file(100, 1),
+                            new: file(120, 5),
+                        }
+                    ),
+                ],
+                unchanged: 1,
+                metadata: vec![
+                    MetadataChange {
+                        key: "version".to_string(),
+                        old: Some("1.0".to_string()),
+                        new: Some("1.1".to_string()),
+                    },
+                    MetadataChange {
+                        key: "buildNumber".to_string(),
+                        old: Some("3".to_string()),
+                        new: None,
+                    },
+                    MetadataChange {
+                        key: "contentWarning".to_string(),
+                        old: None,
+                        new: Some("Flashing lights".to_string()),
+                    },
+                ],
+            }
+        );
+    }
+}
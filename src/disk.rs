@@ -0,0 +1,73 @@
+use std::path::Path;
+
+/// Free space assumed to be enough for a build when `[build] min_free_space_mb` isn't set.
+/// Device builds with `-Zbuild-std`, the simulator build and the staging directory together
+/// can take several hundred megabytes.
+pub const DEFAULT_MIN_FREE_SPACE_MB: u64 = 1024;
+
+/// The space available to this user on the file system holding `path`, or on that of its
+/// closest existing ancestor if it doesn't exist yet. `None` if it can't be found out.
+pub fn available_space(path: &Path) -> Option<u64> {
+    let existing = path.ancestors().find(|ancestor| ancestor.exists())?;
+    available_space_of(existing)
+}
+
+#[cfg(unix)]
+fn available_space_of(path: &Path) -> Option<u64> {
+    use std::{ffi::CString, mem::MaybeUninit, os::unix::ffi::OsStrExt};
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: path is a valid C string, and statvfs only writes to stat.
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    // SAFETY: statvfs succeeded, so it filled in stat.
+    let stat = unsafe { stat.assume_init() };
+    #[allow(clippy::unnecessary_cast)]
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(windows)]
+fn available_space_of(path: &Path) -> Option<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    extern "system" {
+        fn GetDiskFreeSpaceExW(
+            directory_name: *const u16,
+            free_bytes_available: *mut u64,
+            total_bytes: *mut u64,
+            total_free_bytes: *mut u64,
+        ) -> i32;
+    }
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut available = 0u64;
+    // SAFETY: wide is NUL-terminated, and the totals it isn't asked for may be null.
+    let ok = unsafe {
+        GetDiskFreeSpaceExW(
+            wide.as_ptr(),
+            &mut available,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    };
+    if ok == 0 {
+        None
+    } else {
+        Some(available)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_space_of_missing_directory() {
+        let dir = std::env::temp_dir();
+        let space = available_space(&dir).expect("space of the temp dir");
+        assert_eq!(
+            available_space(&dir.join("crank-not-created").join("target")).map(|_| ()),
+            Some(())
+        );
+        assert!(space > 0);
+    }
+}
@@ -0,0 +1,78 @@
+use anyhow::{Context, Error};
+use std::{env, fs, path::Path};
+
+/// Whether a variable in `.env` is one crank reads, so that the rest of a `.env` shared with
+/// other tools doesn't leak into cargo, gcc and pdc.
+pub fn is_relevant(name: &str) -> bool {
+    name.starts_with("PLAYDATE_") || name.starts_with("CRANK_") || name == "RUST_LOG"
+}
+
+/// Reads the `NAME=value` lines of a `.env` file. Blank lines and `#` comments are skipped, a
+/// leading `export ` is allowed, and a value wrapped in matching single or double quotes is
+/// unquoted.
+pub fn parse(text: &str) -> Vec<(String, String)> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let (name, value) = line.split_once('=')?;
+            let value = value.trim();
+            let unquoted = ['"', '\'']
+                .iter()
+                .find_map(|&quote| value.strip_prefix(quote)?.strip_suffix(quote))
+                .unwrap_or(value);
+            Some((name.trim().to_string(), unquoted.to_string()))
+        })
+        .collect()
+}
+
+/// Sets the crank variables from `dir/.env` that aren't already set, so the real environment
+/// wins over the file. Returns the names that were set.
+pub fn load(dir: &Path) -> Result<Vec<String>, Error> {
+    let path = dir.join(".env");
+    if !path.is_file() {
+        return Ok(Vec::new());
+    }
+    let text = fs::read_to_string(&path).with_context(|| format!("Reading {:?}", path))?;
+    let mut loaded = Vec::new();
+    for (name, value) in parse(&text) {
+        if is_relevant(&name) && env::var_os(&name).is_none() {
+            env::set_var(&name, value);
+            loaded.push(name);
+        }
+    }
+    Ok(loaded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_env_file() {
+        let pairs = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|&(name, value)| (name.to_string(), value.to_string()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            parse(
+                "# Playdate setup\n\
+                 PLAYDATE_SDK_PATH=/opt/PlaydateSDK\n\
+                 \n\
+                 export CRANK_PDC_PATH = \"/opt/pdc with spaces\"\n\
+                 PLAYDATE_SERIAL_DEVICE='/dev/ttyACM0'\n\
+                 not a variable\n"
+            ),
+            pairs(&[
+                ("PLAYDATE_SDK_PATH", "/opt/PlaydateSDK"),
+                ("CRANK_PDC_PATH", "/opt/pdc with spaces"),
+                ("PLAYDATE_SERIAL_DEVICE", "/dev/ttyACM0"),
+            ])
+        );
+        assert!(is_relevant("PLAYDATE_MOUNT_POINT"));
+        assert!(!is_relevant("DATABASE_URL"));
+    }
+}
@@ -0,0 +1,174 @@
+use anyhow::{anyhow, bail, Context, Error};
+use std::{convert::TryInto, fs, path::Path};
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+const ELFCLASS32: u8 = 1;
+const ELFDATA2LSB: u8 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SECTION_HEADER_SIZE: usize = 40;
+const SYMBOL_SIZE: usize = 16;
+/// Section index of symbols that are referenced but not defined.
+const SHN_UNDEF: u16 = 0;
+
+/// The symbols the device binary can't work without: the entry point crank links with, from
+/// the SDK's setup.c, and the event handler it calls, which the game provides.
+pub const REQUIRED_SYMBOLS: &[&str] = &["eventHandlerShim", "eventHandler"];
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+/// A symbol in the symbol table of an ELF file.
+#[derive(Debug, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub defined: bool,
+}
+
+/// Reads the symbol table of a 32-bit little-endian ELF file, the format of the device
+/// binary. Returns an empty list if the file has been stripped of its symbols.
+pub fn symbols(data: &[u8]) -> Result<Vec<Symbol>, Error> {
+    if !data.starts_with(ELF_MAGIC) || data.len() < 52 {
+        bail!("Not an ELF file");
+    }
+    if data[4] != ELFCLASS32 || data[5] != ELFDATA2LSB {
+        bail!("Not a 32-bit little-endian ELF file");
+    }
+    let truncated = || anyhow!("Truncated ELF file");
+    let section_headers = read_u32(data, 32).ok_or_else(truncated)? as usize;
+    let section_count = read_u16(data, 48).ok_or_else(truncated)? as usize;
+    let section = |index: usize| section_headers + index * SECTION_HEADER_SIZE;
+
+    let mut symbols = Vec::new();
+    for index in 0..section_count {
+        let header = section(index);
+        if read_u32(data, header + 4).ok_or_else(truncated)? != SHT_SYMTAB {
+            continue;
+        }
+        let offset = read_u32(data, header + 16).ok_or_else(truncated)? as usize;
+        let size = read_u32(data, header + 20).ok_or_else(truncated)? as usize;
+        let strings_header = section(read_u32(data, header + 24).ok_or_else(truncated)? as usize);
+        let strings = read_u32(data, strings_header + 16).ok_or_else(truncated)? as usize;
+
+        for symbol in (offset..offset + size).step_by(SYMBOL_SIZE) {
+            let name_offset = strings + read_u32(data, symbol).ok_or_else(truncated)? as usize;
+            let name = data
+                .get(name_offset..)
+                .and_then(|rest| rest.split(|&b| b == 0).next())
+                .ok_or_else(truncated)?;
+            if name.is_empty() {
+                continue;
+            }
+            symbols.push(Symbol {
+                name: String::from_utf8_lossy(name).into_owned(),
+                defined: read_u16(data, symbol + 14).ok_or_else(truncated)? != SHN_UNDEF,
+            });
+        }
+    }
+    Ok(symbols)
+}
+
+/// Checks that the linked binary defines every required symbol, returning a description of
+/// each one that is missing or left undefined.
+pub fn check_required_symbols(path: &Path) -> Result<Vec<String>, Error> {
+    let data = fs::read(path).with_context(|| format!("Reading {:?}", path))?;
+    let symbols = symbols(&data).with_context(|| format!("Reading symbols of {:?}", path))?;
+    if symbols.is_empty() {
+        return Ok(Vec::new());
+    }
+    Ok(REQUIRED_SYMBOLS
+        .iter()
+        .filter_map(
+            |&required| match symbols.iter().find(|symbol| symbol.name == required) {
+                Some(Symbol { defined: true, .. }) => None,
+                Some(_) => Some(format!("{} is referenced but never defined", required)),
+                None => Some(format!("{} is missing", required)),
+            },
+        )
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    /// Builds a minimal ELF file with only a symbol table and its string table.
+    fn elf_with_symbols(symbols: &[(&str, u16)]) -> Vec<u8> {
+        let mut strings = vec![0u8];
+        let mut table = vec![0u8; SYMBOL_SIZE];
+        for (name, section) in symbols {
+            let mut symbol = vec![0u8; SYMBOL_SIZE];
+            symbol[0..4].copy_from_slice(&(strings.len() as u32).to_le_bytes());
+            symbol[14..16].copy_from_slice(&section.to_le_bytes());
+            table.extend(symbol);
+            strings.extend(name.as_bytes());
+            strings.push(0);
+        }
+
+        let mut data = vec![0u8; 52];
+        data[..4].copy_from_slice(ELF_MAGIC);
+        data[4] = ELFCLASS32;
+        data[5] = ELFDATA2LSB;
+        let table_offset = data.len();
+        data.extend(&table);
+        let strings_offset = data.len();
+        data.extend(&strings);
+        let headers = data.len();
+        data[32..36].copy_from_slice(&(headers as u32).to_le_bytes());
+        data[48..50].copy_from_slice(&3u16.to_le_bytes());
+
+        let mut section = |kind: u32, offset: usize, size: usize, link: u32| {
+            let mut header = vec![0u8; SECTION_HEADER_SIZE];
+            header[4..8].copy_from_slice(&kind.to_le_bytes());
+            header[16..20].copy_from_slice(&(offset as u32).to_le_bytes());
+            header[20..24].copy_from_slice(&(size as u32).to_le_bytes());
+            header[24..28].copy_from_slice(&link.to_le_bytes());
+            data.extend(header);
+        };
+        section(0, 0, 0, 0);
+        section(SHT_SYMTAB, table_offset, table.len(), 2);
+        section(3, strings_offset, strings.len(), 0);
+        data
+    }
+
+    #[test]
+    fn finds_missing_symbols() {
+        let data = elf_with_symbols(&[("eventHandlerShim", 1), ("eventHandler", SHN_UNDEF)]);
+        assert_eq!(
+            symbols(&data).unwrap(),
+            vec![
+                Symbol {
+                    name: "eventHandlerShim".to_string(),
+                    defined: true,
+                },
+                Symbol {
+                    name: "eventHandler".to_string(),
+                    defined: false,
+                },
+            ]
+        );
+
+        let dir = TestDir::new("elf");
+        let path = dir.join("game.elf");
+        fs::write(&path, &data).unwrap();
+        assert_eq!(
+            check_required_symbols(&path).unwrap(),
+            vec!["eventHandler is referenced but never defined"]
+        );
+        fs::write(&path, elf_with_symbols(&[("eventHandler", 2)])).unwrap();
+        assert_eq!(
+            check_required_symbols(&path).unwrap(),
+            vec!["eventHandlerShim is missing"]
+        );
+        assert!(symbols(b"not an elf").is_err());
+    }
+}
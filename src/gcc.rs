@@ -0,0 +1,119 @@
+use anyhow::{anyhow, Context, Error};
+use log::info;
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// The oldest arm-none-eabi-gcc known to build working games. Older toolchains, such as the
+/// GNU Arm Embedded 9-2019 releases, have caused path and link problems.
+pub const MIN_VERSION: (u32, u32) = (10, 3);
+
+/// Reads the version from the first line of `gcc --version`, such as
+/// `arm-none-eabi-gcc (GNU Arm Embedded Toolchain 10.3-2021.10) 10.3.1 20210824 (release)`:
+/// the first word made of dot-separated numbers only.
+pub fn parse_version(output: &str) -> Option<Vec<u32>> {
+    output.lines().next()?.split_whitespace().find_map(|word| {
+        let parts = word
+            .split('.')
+            .map(|part| part.parse::<u32>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        Some(parts).filter(|parts| parts.len() >= 2)
+    })
+}
+
+/// Runs `gcc --version` and returns the version it reports.
+pub fn version(gcc: &Path) -> Result<Vec<u32>, Error> {
+    let output = Command::new(gcc)
+        .arg("--version")
+        .output()
+        .with_context(|| format!("Running {:?} --version", gcc))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    info!(
+        "gcc --version: {}",
+        stdout.lines().next().unwrap_or_default()
+    );
+    parse_version(&stdout).ok_or_else(|| anyhow!("{:?} did not report a version", gcc))
+}
+
+pub fn format_version(version: &[u32]) -> String {
+    version
+        .iter()
+        .map(|part| part.to_string())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Every file called `program` in the directories of `PATH`, in search order.
+fn find_all_in_path(program: &Path) -> Vec<PathBuf> {
+    let mut found: Vec<PathBuf> = Vec::new();
+    for dir in env::split_paths(&env::var_os("PATH").unwrap_or_default()) {
+        let path = dir.join(program);
+        let resolved = path.canonicalize().unwrap_or_else(|_| path.clone());
+        let already_found = found
+            .iter()
+            .any(|other| other.canonicalize().unwrap_or_else(|_| other.clone()) == resolved);
+        if path.is_file() && !already_found {
+            found.push(path);
+        }
+    }
+    found
+}
+
+/// Checks the gcc crank links with, given the `version` it reported, returning a warning for
+/// a version older than `MIN_VERSION` and for a bare program name that more than one install
+/// on `PATH` answers to. A gcc that can't be run is left for the link step to report.
+pub fn check(gcc: &Path, version: Option<&[u32]>) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if gcc.components().count() == 1 {
+        let installs = find_all_in_path(gcc);
+        if installs.len() > 1 {
+            warnings.push(format!(
+                "found {} installs of {} on PATH, using {}: {}",
+                installs.len(),
+                gcc.display(),
+                installs[0].display(),
+                installs
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+    }
+    if let Some(version) = version {
+        if (version[0], version[1]) < MIN_VERSION {
+            warnings.push(format!(
+                "{} is version {}, older than {}.{}, the oldest known to work. \
+                Install a newer Arm GNU Toolchain if linking fails.",
+                gcc.display(),
+                format_version(version),
+                MIN_VERSION.0,
+                MIN_VERSION.1
+            ));
+        }
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_version_output() {
+        assert_eq!(
+            parse_version(
+                "arm-none-eabi-gcc (GNU Arm Embedded Toolchain 9-2019-q4-major) 9.2.1 20191025 \
+                (release) [ARM/arm-9-branch revision 277599]\nCopyright (C) 2019"
+            ),
+            Some(vec![9, 2, 1])
+        );
+        assert_eq!(
+            parse_version("arm-none-eabi-gcc (15:10.3-2021.07-4) 10.3.1 20210621 (release)"),
+            Some(vec![10, 3, 1])
+        );
+        assert_eq!(parse_version("gcc: command not found"), None);
+    }
+}
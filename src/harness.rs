@@ -0,0 +1,114 @@
+use anyhow::{Context, Error};
+use log::info;
+use std::{
+    io::{BufRead, BufReader, Read},
+    process::{Command, ExitStatus, Stdio},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// The line a test harness prints, with `System->logToConsole` or `println!`, once every test
+/// passed.
+pub const PASSED_MARKER: &str = "TESTS PASSED";
+/// The line a test harness prints once it is done and at least one test failed.
+pub const FAILED_MARKER: &str = "TESTS FAILED";
+
+/// How a test run ended.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Passed,
+    Failed,
+    /// The simulator exited without the harness reporting a result.
+    Exited(ExitStatus),
+    TimedOut,
+}
+
+/// The result a line of console output reports, if any. The simulator may put a prefix such
+/// as a timestamp in front of the game's output, so only the end of the line is compared.
+pub fn outcome_of_line(line: &str) -> Option<Outcome> {
+    let line = line.trim_end();
+    if line.ends_with(PASSED_MARKER) {
+        Some(Outcome::Passed)
+    } else if line.ends_with(FAILED_MARKER) {
+        Some(Outcome::Failed)
+    } else {
+        None
+    }
+}
+
+fn forward_lines(stream: impl Read + Send + 'static, sender: mpsc::Sender<String>) {
+    thread::spawn(move || {
+        for line in BufReader::new(stream).lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Starts `command`, echoes its output and waits up to `timeout` for a line that reports the
+/// result, then stops it.
+pub fn run(command: &mut Command, timeout: Duration) -> Result<Outcome, Error> {
+    info!("harness command: {:?}", command);
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Starting {:?}", command.get_program()))?;
+    let (sender, receiver) = mpsc::channel();
+    forward_lines(child.stdout.take().expect("stdout"), sender.clone());
+    forward_lines(child.stderr.take().expect("stderr"), sender);
+
+    let deadline = Instant::now() + timeout;
+    let outcome = loop {
+        match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(line) => {
+                println!("{}", line);
+                if let Some(outcome) = outcome_of_line(&line) {
+                    break outcome;
+                }
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => break Outcome::TimedOut,
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                return Ok(Outcome::Exited(child.wait()?));
+            }
+        }
+    };
+    let _ = child.kill();
+    let _ = child.wait();
+    Ok(outcome)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_result_from_output() {
+        assert_eq!(
+            outcome_of_line("12:00:01 TESTS PASSED\r"),
+            Some(Outcome::Passed)
+        );
+        assert_eq!(outcome_of_line("TESTS FAILED"), Some(Outcome::Failed));
+        assert_eq!(outcome_of_line("TESTS PASSED: 3 of 4"), None);
+
+        let shell = |script: &str| {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(script);
+            command
+        };
+        let timeout = Duration::from_secs(10);
+        let outcome = run(&mut shell("echo one; echo TESTS PASSED; sleep 30"), timeout);
+        assert_eq!(outcome.unwrap(), Outcome::Passed);
+        let outcome = run(&mut shell("echo TESTS FAILED >&2; sleep 30"), timeout);
+        assert_eq!(outcome.unwrap(), Outcome::Failed);
+        match run(&mut shell("exit 3"), timeout).unwrap() {
+            Outcome::Exited(status) => assert_eq!(status.code(), Some(3)),
+            outcome => panic!("unexpected {:?}", outcome),
+        }
+        let outcome = run(&mut shell("sleep 30"), Duration::from_millis(200));
+        assert_eq!(outcome.unwrap(), Outcome::TimedOut);
+    }
+}
@@ -1,139 +1,422 @@
-use anyhow::{anyhow, bail, Error};
-use inflector::cases::titlecase::to_title_case;
-use log::{debug, info};
-use serde_derive::Deserialize;
+use anyhow::{anyhow, bail, Context, Error};
+use log::{debug, info, LevelFilter};
 use std::{
     collections::HashMap,
     env,
     fs::{self},
-    io::Write,
-    path::{Path, PathBuf},
-    process::{Command, ExitStatus, Stdio},
+    io::{BufReader, Write},
+    path::{Component, Path, PathBuf},
+    process::{Command, Stdio},
+    sync::OnceLock,
     thread, time,
 };
 use structopt::StructOpt;
-use zip::{write::FileOptions, CompressionMethod};
-use zip_extensions::zip_create_from_directory_with_options;
 
-#[cfg(unix)]
-use anyhow::Context;
-
-#[cfg(target_os = "linux")]
-use walkdir::WalkDir;
+#[cfg(all(unix, not(target_os = "macos")))]
+use std::process::ExitStatus;
 
+mod abi;
+mod affected;
+mod archive;
+mod assets;
+mod build_info;
+mod build_std;
+mod cache;
 mod config;
+mod device;
+mod diff;
+mod disk;
+mod dotenv;
+mod elf;
+mod gcc;
+mod harness;
+mod manifest;
+mod notify;
+mod priority;
+mod profile;
+mod record;
+mod retry;
+mod screenshot;
+mod settings;
+mod sim_log;
+#[cfg(unix)]
+mod sync;
+#[cfg(test)]
+mod test_dir;
+mod title;
+mod ui;
+
+use cache::BuildKey;
+use manifest::{load_manifest, Manifest, Metadata, Target};
+use pretty_env_logger::env_logger::WriteStyle;
+use settings::{Compression, Settings, ZipRoot};
 
 #[cfg(target_os = "macos")]
-const GCC_PATH_STR: &'static str = "/usr/local/bin/arm-none-eabi-gcc";
+const GCC_PATH_STR: &str = "/usr/local/bin/arm-none-eabi-gcc";
 #[cfg(all(unix, not(target_os = "macos")))]
-const GCC_PATH_STR: &'static str = "arm-none-eabi-gcc";
+const GCC_PATH_STR: &str = "arm-none-eabi-gcc";
 #[cfg(windows)]
-const GCC_PATH_STR: &'static str = "arm-none-eabi-gcc.exe";
+const GCC_PATH_STR: &str = "arm-none-eabi-gcc.exe";
 
 #[cfg(unix)]
 #[allow(unused)]
-const PDUTIL_NAME: &'static str = "pdutil";
+const PDUTIL_NAME: &str = "pdutil";
 #[cfg(windows)]
-const PDUTIL_NAME: &'static str = "PDUTIL.EXE";
+const PDUTIL_NAME: &str = "PDUTIL.EXE";
 
 #[cfg(unix)]
-const PDC_NAME: &'static str = "pdc";
+const PDC_NAME: &str = "pdc";
+#[cfg(windows)]
+const PDC_NAME: &str = "PDC.EXE";
+
+/// Device builds need `-Zbuild-std`, which is only available on nightly.
+const DEVICE_TOOLCHAIN: &str = "nightly";
+/// Whether `rustup` runs, checked the first time a device build needs to know.
+static HAS_RUSTUP: OnceLock<bool> = OnceLock::new();
+/// Set once the gcc for device builds has been checked, so that is done once per run.
+static GCC_CHECKED: OnceLock<()> = OnceLock::new();
+
+/// How long `--fallback-simulator` waits for a device before using the simulator.
+const FALLBACK_DEVICE_TIMEOUT: time::Duration = time::Duration::from_secs(3);
+/// How long `--wait-for-device` waits without a value, and how often it says it's waiting.
+const WAIT_FOR_DEVICE_TIMEOUT: time::Duration = time::Duration::from_secs(60);
+const WAIT_FOR_DEVICE_INTERVAL: time::Duration = time::Duration::from_secs(5);
+/// How long `--profile-device` reads the console without a value.
+const PROFILE_DEVICE_DURATION: time::Duration = time::Duration::from_secs(30);
+/// How long `--touch-device` waits for the device to answer.
+const TOUCH_DEVICE_TIMEOUT: time::Duration = time::Duration::from_secs(5);
+/// How long to wait before running a build again after a transient failure.
+const RETRY_BUILD_DELAY: time::Duration = time::Duration::from_secs(2);
+
+#[cfg(target_os = "macos")]
+const SIMULATOR_NAME: &str = "Playdate Simulator.app";
+#[cfg(all(unix, not(target_os = "macos")))]
+const SIMULATOR_NAME: &str = "PlaydateSimulator";
 #[cfg(windows)]
-const PDC_NAME: &'static str = "PDC.EXE";
+const SIMULATOR_NAME: &str = "PlaydateSimulator.exe";
 
 #[cfg(unix)]
-const SDK_DIR: &'static str = "Developer";
+const SDK_DIR: &str = "Developer";
 #[cfg(windows)]
-const SDK_DIR: &'static str = "Documents";
+const SDK_DIR: &str = "Documents";
+
+/// The home directory, which holds the Playdate SDK config and the SDK's default location.
+fn home_dir() -> Result<PathBuf, Error> {
+    dirs::home_dir().ok_or_else(|| {
+        anyhow!(
+            "Can't find the home directory, which crank needs to read the Playdate SDK config \
+            and find the SDK in its default location. Set HOME, or set PLAYDATE_SDK_PATH to \
+            the SDK's path."
+        )
+    })
+}
 
 fn playdate_sdk_cfg() -> Result<config::SdkCfg, Error> {
-    let cfg_path = dirs::home_dir()
-        .ok_or(anyhow!("Can't find home dir"))?
-        .join(config::CFG_DIR)
-        .join(config::CFG_FILENAME);
+    let cfg_path = home_dir()?.join(config::CFG_DIR).join(config::CFG_FILENAME);
     fs::read_to_string(cfg_path)?.parse()
 }
 
-fn playdate_sdk_path() -> Result<PathBuf, Error> {
+fn playdate_sdk_path(settings: &Settings) -> Result<PathBuf, Error> {
+    let default_sdk_path = playdate_sdk_path_unversioned(settings)?;
+    let sdk_version = match settings.toolchain.sdk_version.as_ref() {
+        Some(sdk_version) => sdk_version,
+        None => return Ok(default_sdk_path),
+    };
+    let installed = installed_sdks(&default_sdk_path);
+    match installed
+        .iter()
+        .find(|(version, _)| version.as_deref() == Some(sdk_version.as_str()))
+    {
+        Some((_, path)) => Ok(path.clone()),
+        None => bail!(
+            "Playdate SDK {} not found next to {:?}; installed versions: {}",
+            sdk_version,
+            default_sdk_path,
+            describe_sdk_versions(&installed)
+        ),
+    }
+}
+
+fn playdate_sdk_path_unversioned(settings: &Settings) -> Result<PathBuf, Error> {
+    Ok(locate_sdk(settings)?.0)
+}
+
+/// Finds the default SDK, along with a description of where the path came from. Like the
+/// other environment variables crank reads, `PLAYDATE_SDK_PATH` wins over the config.
+fn locate_sdk(settings: &Settings) -> Result<(PathBuf, &'static str), Error> {
+    if let Some(sdk_path) = env::var_os("PLAYDATE_SDK_PATH") {
+        return Ok((PathBuf::from(sdk_path), "PLAYDATE_SDK_PATH"));
+    }
+    if let Some(sdk_path) = settings.toolchain.sdk_path.as_ref() {
+        return Ok((sdk_path.clone(), "[toolchain] sdk_path"));
+    }
     match playdate_sdk_cfg() {
         Err(_) => {
             debug!("Unable to read PlaydateSDK config from home dir, so using default.");
             playdate_sdk_path_default()
         }
-        Ok(cfg) => cfg.sdk_path().map(|p| Ok(p)).unwrap_or_else(|| {
-            debug!("Unable to determine PlaydateSDK path by config, so using default.");
-            playdate_sdk_path_default()
-        }),
+        Ok(cfg) => match cfg.sdk_path() {
+            Some(sdk_path) => Ok((sdk_path, "Playdate SDK config (~/.Playdate/config)")),
+            None => {
+                debug!("Unable to determine PlaydateSDK path by config, so using default.");
+                playdate_sdk_path_default()
+            }
+        },
     }
 }
 
-fn playdate_sdk_path_default() -> Result<PathBuf, Error> {
-    let sdk_location = match env::var("PLAYDATE_SDK_PATH") {
-        Ok(path) => PathBuf::from(path),
-        Err(_) => {
-            // couldn't find the expected env variable, try defaulting to their home directory
-            let home_dir = dirs::home_dir().ok_or(anyhow!("Can't find home dir"))?;
-            home_dir.join(SDK_DIR).join("PlaydateSDK")
+fn playdate_sdk_path_default() -> Result<(PathBuf, &'static str), Error> {
+    Ok((
+        home_dir()?.join(SDK_DIR).join("PlaydateSDK"),
+        "default location",
+    ))
+}
+
+/// Finds the file a program name would run from `PATH`, as a shell would.
+fn find_in_path(program: &Path) -> Option<PathBuf> {
+    if program.components().count() > 1 {
+        return Some(program.to_path_buf()).filter(|path| path.is_file());
+    }
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|path| path.is_file())
+}
+
+/// Reads the version an SDK reports in its `VERSION.txt`.
+fn sdk_version(sdk_path: &Path) -> Option<String> {
+    let version = fs::read_to_string(sdk_path.join("VERSION.txt")).ok()?;
+    Some(version.trim().to_string()).filter(|version| !version.is_empty())
+}
+
+/// Finds the SDKs installed alongside `default_sdk_path`: the default SDK itself and any
+/// sibling `PlaydateSDK-<version>` directories, as `(version, path)` pairs sorted by path.
+fn installed_sdks(default_sdk_path: &Path) -> Vec<(Option<String>, PathBuf)> {
+    let mut sdks = Vec::new();
+    if default_sdk_path.is_dir() {
+        sdks.push((
+            sdk_version(default_sdk_path),
+            default_sdk_path.to_path_buf(),
+        ));
+    }
+    if let Some(entries) = default_sdk_path
+        .parent()
+        .and_then(|parent| fs::read_dir(parent).ok())
+    {
+        for entry in entries.flatten() {
+            let file_name = entry.file_name();
+            let file_name = file_name.to_string_lossy();
+            if let Some(version) = file_name.strip_prefix("PlaydateSDK-") {
+                let path = entry.path();
+                if path.is_dir() && path != default_sdk_path {
+                    let version = sdk_version(&path).unwrap_or_else(|| version.to_string());
+                    sdks.push((Some(version), path));
+                }
+            }
         }
-    };
-    Ok(sdk_location)
+    }
+    sdks.sort_by(|(_, a), (_, b)| a.cmp(b));
+    sdks
 }
 
-fn playdate_c_api_path() -> Result<PathBuf, Error> {
-    Ok(playdate_sdk_path()?.join("C_API"))
+fn describe_sdk_versions(sdks: &[(Option<String>, PathBuf)]) -> String {
+    if sdks.is_empty() {
+        return "none".to_string();
+    }
+    sdks.iter()
+        .map(|(version, _)| version.as_deref().unwrap_or("unknown"))
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
-type Assets = Vec<String>;
+const GCC_COMPILE_STATIC_ARGS: &str = "-g3 -c -mthumb -mfloat-abi=hard \
+    -D__FPU_USED=1 -O2 -falign-functions=16 -fomit-frame-pointer \
+    -gdwarf-2 -Wall -Wno-unused -Wstrict-prototypes -Wno-unknown-pragmas -fverbose-asm \
+    -Wdouble-promotion -mword-relocations -fno-common \
+    -ffunction-sections -fdata-sections -DTARGET_PLAYDATE=1 -DTARGET_EXTENSION=1 -fno-exceptions";
 
-#[derive(Clone, Debug, Default, Deserialize)]
-struct Metadata {
-    name: Option<String>,
-    author: Option<String>,
-    description: Option<String>,
-    bundle_id: Option<String>,
-    version: Option<String>,
-    build_number: Option<u64>,
-    image_path: Option<String>,
-    launch_sound_path: Option<String>,
+/// What separates the flags in `CARGO_ENCODED_RUSTFLAGS`.
+const RUSTFLAGS_SEPARATOR: char = '\x1f';
+
+/// The `RUSTFLAGS` for device builds.
+fn device_rustflags(settings: &Settings) -> Vec<String> {
+    let mut rustflags = vec![
+        format!("-Ctarget-cpu={}", settings.toolchain.target_cpu()),
+        "-Ctarget-feature=-fp64".to_string(), // Rev A hardware seems to not have 64-bit floating point support
+        format!("-Clink-args={}", settings.build.rustc_link_args()),
+        "-Crelocation-model=pic".to_string(),
+        "-Cpanic=abort".to_string(),
+    ];
+    rustflags.extend(settings.build.rustflags.iter().flatten().cloned());
+    rustflags
 }
 
-#[derive(Clone, Debug, Default, Deserialize)]
-struct Target {
-    name: String,
-    assets: Option<Assets>,
-    metadata: Option<Metadata>,
+/// Refuses to build when the rustflags and the gcc flags, after the overrides in the
+/// settings, disagree on the CPU or floating point, which links a binary with a broken ABI.
+fn check_abi(settings: &Settings) -> Result<(), Error> {
+    let rustflags = device_rustflags(settings);
+    let rustc = abi::RustcAbi::parse(rustflags.iter().map(String::as_str));
+    let cpu_args = gcc_cpu_args(settings);
+    let compile_flags = GCC_COMPILE_STATIC_ARGS
+        .split(' ')
+        .chain(cpu_args.iter().map(String::as_str))
+        .chain(
+            settings
+                .toolchain
+                .gcc_compile_args
+                .iter()
+                .flatten()
+                .map(String::as_str),
+        );
+    let link_flags = settings
+        .build
+        .gcc_link_args()
+        .split_whitespace()
+        .chain(cpu_args.iter().map(String::as_str));
+    let mut mismatches = abi::mismatches(
+        &rustc,
+        &abi::GccAbi::parse(compile_flags),
+        "compiles setup.c",
+    );
+    mismatches.extend(abi::mismatches(
+        &rustc,
+        &abi::GccAbi::parse(link_flags),
+        "links",
+    ));
+    if !mismatches.is_empty() {
+        bail!(
+            "The rustc and gcc flags for the device are incompatible:\n{}\n\
+            Make [build] rustflags and the gcc settings agree.",
+            mismatches.join("\n")
+        );
+    }
+    Ok(())
 }
 
-#[derive(Clone, Debug, Default, Deserialize)]
-pub struct Manifest {
-    #[serde(default, alias = "target")]
-    targets: Vec<Target>,
+/// The `-mcpu`/`-mfpu` flags for gcc, kept in sync with the `-Ctarget-cpu` given to rustc.
+fn gcc_cpu_args(settings: &Settings) -> [String; 2] {
+    [
+        format!("-mcpu={}", settings.toolchain.target_cpu()),
+        format!("-mfpu={}", settings.toolchain.fpu()),
+    ]
 }
 
-impl Manifest {
-    fn get_target(&self, target_name: &str) -> Option<&Target> {
-        self.targets
-            .iter()
-            .find(|target| &target.name == target_name)
+fn has_rustup() -> bool {
+    *HAS_RUSTUP.get_or_init(|| {
+        Command::new("rustup")
+            .arg("--version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    })
+}
+
+/// The C API gcc compiles and links against: `[toolchain] c_api_path`, relative to the
+/// project directory, or the SDK's own.
+fn playdate_c_api_path(settings: &Settings, project_path: &Path) -> Result<PathBuf, Error> {
+    match settings.toolchain.c_api_path.as_ref() {
+        Some(c_api_path) => {
+            let path = project_path.join(c_api_path);
+            if !path.is_dir() {
+                bail!("The [toolchain] c_api_path {:?} does not exist", path);
+            }
+            Ok(path)
+        }
+        None => Ok(playdate_sdk_path(settings)?.join("C_API")),
+    }
+}
+
+/// Path of an SDK tool: the `env_var` environment variable if set, then the configured path,
+/// then the tool in the SDK's `bin` directory. An explicitly chosen path must exist.
+fn sdk_tool_path(
+    settings: &Settings,
+    env_var: &str,
+    configured: Option<&PathBuf>,
+    name: &str,
+) -> Result<PathBuf, Error> {
+    let chosen = env::var_os(env_var)
+        .map(PathBuf::from)
+        .or_else(|| configured.cloned());
+    match chosen {
+        Some(path) if !path.is_file() => bail!("{} {:?} does not exist", name, path),
+        Some(path) => Ok(path),
+        None => Ok(playdate_sdk_path(settings)?.join("bin").join(name)),
     }
 }
 
-pub fn load_manifest(manifest_path: &Option<PathBuf>) -> Result<Manifest, Error> {
-    let cwd: PathBuf = if let Some(actual_manifest_path) = manifest_path.as_ref() {
-        actual_manifest_path
-            .parent()
-            .expect("manifest_path parent")
-            .to_path_buf()
+fn pdc_path(settings: &Settings) -> Result<PathBuf, Error> {
+    sdk_tool_path(
+        settings,
+        "CRANK_PDC_PATH",
+        settings.toolchain.pdc_path.as_ref(),
+        PDC_NAME,
+    )
+}
+
+fn pdutil_path(settings: &Settings) -> Result<PathBuf, Error> {
+    sdk_tool_path(
+        settings,
+        "CRANK_PDUTIL_PATH",
+        settings.toolchain.pdutil_path.as_ref(),
+        PDUTIL_NAME,
+    )
+}
+
+/// Quotes an argument for a POSIX shell if it contains anything that needs it.
+fn shell_quote(arg: &str) -> String {
+    if arg.contains(RUSTFLAGS_SEPARATOR) {
+        // Bash's `$'...'` quoting is the only way to write the separator on a command line.
+        let escaped = arg
+            .replace('\\', "\\\\")
+            .replace('\'', "\\'")
+            .replace(RUSTFLAGS_SEPARATOR, "\\x1f");
+        return format!("$'{}'", escaped);
+    }
+    if !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_=+,./:@%".contains(c))
+    {
+        arg.to_string()
     } else {
-        std::env::current_dir()?
-    };
-    let manifest_path = cwd.join("Crank.toml");
-    if !manifest_path.exists() {
-        return Ok(Manifest::default());
+        format!("'{}'", arg.replace('\'', "'\\''"))
     }
-    let manifest_contents = fs::read_to_string(manifest_path)?;
-    let manifest = toml::from_str(&manifest_contents)?;
-    Ok(manifest)
+}
+
+/// Formats a command, with the environment variables it sets, so it can be pasted into a
+/// shell to run it by hand.
+fn command_line(command: &Command) -> String {
+    let envs = command.get_envs().filter_map(|(key, value)| {
+        value.map(|value| {
+            format!(
+                "{}={}",
+                key.to_string_lossy(),
+                shell_quote(&value.to_string_lossy())
+            )
+        })
+    });
+    let program = std::iter::once(shell_quote(&command.get_program().to_string_lossy()));
+    let args = command
+        .get_args()
+        .map(|arg| shell_quote(&arg.to_string_lossy()));
+    envs.chain(program)
+        .chain(args)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn gcc_path(settings: &Settings) -> PathBuf {
+    settings
+        .toolchain
+        .gcc_path
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(GCC_PATH_STR))
+}
+
+/// Warnings about the gcc device builds link with, from `gcc::check`.
+fn check_gcc(settings: &Settings) -> Vec<String> {
+    let gcc = gcc_path(settings);
+    gcc::check(&gcc, gcc::version(&gcc).ok().as_deref())
 }
 
 #[derive(Debug, StructOpt)]
@@ -145,9 +428,80 @@ enum CrankCommand {
     Run(Build),
     /// Make a pdx file for both device and simulator and compress it.
     Package(Package),
+    /// Only run the cargo build, without linking or producing a pdx
+    Verify(Build),
+    /// Build, then run a command with CRANK_PDX_PATH, CRANK_PDX_ZIP and CRANK_GAME_TITLE set
+    Exec(Exec),
+    /// Build test harnesses and run them in the simulator, failing unless they all pass
+    Test(Test),
+    /// Print the SDK, tools, devices and directories crank would use
+    Env(EnvCommand),
+    /// Save the current screen of a connected Playdate as a PNG
+    Screenshot(Screenshot),
+    /// List the Playdate SDK versions installed side by side
+    ListSdks(ListSdks),
+    /// List the cargo targets crank can build and the Crank.toml targets that apply to them
+    ListTargets(ListTargets),
+    /// List the files and pdxinfo keys that differ between two .pdx.zip archives
+    Diff(Diff),
+    /// Open an already built .pdx in the simulator, without building anything
+    Open(Open),
+    /// Copy several .pdx folders to the device in a single data disk session
+    Deploy(Deploy),
+    /// Print the commands a build runs as a shell script, without running them
+    Explain(Build),
+    /// Print the pdxinfo, files and binaries of a .pdx or .pdx.zip
+    Info(Info),
+}
+
+/// The pdx a build produced.
+struct BuiltPdx {
+    path: PathBuf,
+    /// File name of the pdx without the extension, derived from the title.
+    name: String,
+    game_title: String,
+    /// The linked device binary, for device builds.
+    elf: Option<PathBuf>,
+}
+
+/// What a cargo build produced, and where.
+struct BuildTarget {
+    project_path: PathBuf,
+    /// Cargo's target directory, which is the workspace's rather than the package's.
+    target_directory: PathBuf,
+    package_id: Option<cargo_metadata::PackageId>,
+    target_name: String,
+    /// Files cargo reported for the target, filled in once the build has run.
+    artifacts: Vec<PathBuf>,
 }
 
-#[derive(Debug, StructOpt, Clone)]
+impl BuildTarget {
+    /// Finds the artifact cargo produced with the given extension, such as the `.a` static
+    /// library for the device or the dynamic library for the simulator.
+    fn artifact(&self, extension: &str) -> Result<&Path, Error> {
+        self.artifacts
+            .iter()
+            .find(|path| path.extension().is_some_and(|ext| ext == extension))
+            .map(|path| path.as_path())
+            .ok_or_else(|| {
+                anyhow!(
+                    "cargo did not report a .{} file for {}; artifacts: {:?}",
+                    extension,
+                    self.target_name,
+                    self.artifacts
+                )
+            })
+    }
+
+    fn is_artifact_of(&self, artifact: &cargo_metadata::Artifact) -> bool {
+        self.package_id
+            .as_ref()
+            .is_none_or(|package_id| package_id == &artifact.package_id)
+            && artifact.target.name.replace('-', "_") == self.target_name.replace('-', "_")
+    }
+}
+
+#[derive(Debug, Default, StructOpt, Clone)]
 struct Build {
     /// Build for the Playdate device.
     #[structopt(long)]
@@ -161,116 +515,465 @@ struct Build {
     #[structopt(long)]
     features: Vec<String>,
 
-    /// Build a specific example from the examples/ dir.
+    /// Name the .pdx after this instead of the game title, which stays the name shown in the
+    /// launcher.
+    #[structopt(long)]
+    pdx_name: Option<String>,
+
+    /// Build without the package's default features.
     #[structopt(long)]
+    no_default_features: bool,
+
+    /// Build with the package's default features even if the Crank.toml target turns them off.
+    #[structopt(long, conflicts_with = "no-default-features")]
+    default_features: bool,
+
+    /// Build a specific example from the examples/ dir. Can be given more than once, and
+    /// accepts globs such as 'level*' to build each matching example into its own pdx.
+    #[structopt(long = "example")]
+    examples: Vec<String>,
+
+    /// Build the example whose source is this file, or which lives in this directory, for
+    /// examples declared with a custom `path` in Cargo.toml. Can be given more than once.
+    #[structopt(long = "example-path")]
+    example_paths: Vec<PathBuf>,
+
+    /// The one example this build is for, picked from `examples` by `expand_examples`.
+    #[structopt(skip)]
     example: Option<String>,
 
+    /// Only build the examples, or without --example the package, that changes since this git
+    /// ref affect, directly or through the workspace packages they depend on.
+    #[structopt(long, value_name = "base-ref")]
+    affected: Option<String>,
+
     /// Run.
     #[structopt(long)]
     run: bool,
+
+    /// When running on the device and no Playdate is connected, build and run in the
+    /// simulator instead.
+    #[structopt(long)]
+    fallback_simulator: bool,
+
+    /// Install the pdx into the simulator's Games folder instead of launching it.
+    #[structopt(long, conflicts_with = "device")]
+    install_simulator: bool,
+
+    /// When running in the simulator, print what the simulator writes to its log until it
+    /// exits, or on macOS, where it keeps running in the background, until Ctrl-C.
+    #[structopt(long, conflicts_with_all = &["device", "install-simulator"])]
+    tail_logs: bool,
+
+    /// When running in the simulator, open the simulator's log in the default viewer.
+    #[structopt(long, conflicts_with_all = &["device", "install-simulator", "tail-logs"])]
+    open_logs: bool,
+
+    /// Copy the unstripped device .elf to this path after linking, for symbolicating crashes.
+    #[structopt(long)]
+    elf_output: Option<PathBuf>,
+
+    /// Replaces `{suffix}` in the bundle id. Defaults to `.debug` for debug builds and
+    /// nothing for release builds.
+    #[structopt(long)]
+    bundle_suffix: Option<String>,
+
+    /// Serial device of the Playdate, or `tcp://host:port` for one behind a serial-over-network
+    /// bridge. Overrides PLAYDATE_SERIAL_DEVICE.
+    #[structopt(long)]
+    device_serial: Option<String>,
+
+    /// Number of parallel jobs for cargo, which defaults to the number of CPUs.
+    #[structopt(short, long)]
+    jobs: Option<u32>,
+
+    /// Rustup toolchain to build with, such as nightly-2024-01-01. Device builds use nightly by
+    /// default.
+    #[structopt(long)]
+    toolchain: Option<String>,
+
+    /// Relink and rerun pdc for a device build even if none of their inputs changed.
+    #[structopt(long)]
+    force: bool,
+
+    /// Fail the build if pdc prints any warnings.
+    #[structopt(long)]
+    deny_pdc_warnings: bool,
+
+    /// Run cargo clippy, with the same target, features and toolchain, before building.
+    #[structopt(long)]
+    clippy: bool,
+
+    /// Have clippy fail on warnings, as with -Dwarnings.
+    #[structopt(long, requires = "clippy")]
+    deny_warnings: bool,
+
+    /// Have pdc strip debug information from the Lua it compiles, which is the default for
+    /// release builds.
+    #[structopt(long, conflicts_with = "no-strip")]
+    strip: bool,
+
+    /// Keep pdc's debug information in the compiled Lua, which is the default for debug
+    /// builds.
+    #[structopt(long)]
+    no_strip: bool,
+
+    /// Replace the whole pdx on the device instead of copying only the files that changed.
+    #[structopt(long)]
+    full_copy: bool,
+
+    /// Before putting the device in data disk mode, check that it answers like a Playdate and
+    /// print its firmware version.
+    #[structopt(long)]
+    touch_device: bool,
+
+    /// Before running on the device, wait for a Playdate to be connected, for up to the given
+    /// number of seconds or a minute.
+    #[structopt(long, value_name = "seconds")]
+    wait_for_device: Option<Option<u64>>,
+
+    /// After starting the game on the device, read the profiling lines it prints to the
+    /// console for the given number of seconds, or 30, and summarize the frame times and CPU
+    /// use.
+    #[structopt(long, value_name = "seconds", requires = "device")]
+    profile_device: Option<Option<u64>>,
+
+    /// When running, save the device console, or the simulator's log, into a new directory
+    /// inside this one named after the pdx and the time, until the game exits or crank is
+    /// interrupted.
+    #[structopt(
+        long,
+        value_name = "dir",
+        conflicts_with_all = &["install-simulator", "profile-device"]
+    )]
+    record: Option<PathBuf>,
+
+    /// With --record on the device, also save a screenshot every this many seconds.
+    #[structopt(long, value_name = "seconds", requires_all = &["record", "device"])]
+    record_screenshots: Option<u64>,
+
+    /// Reboot the device before running on it, and wait for it to come back.
+    #[structopt(long, requires = "device")]
+    reset: bool,
+
+    /// Folder on the device to install into, /Games or one of its subfolders. /Games by
+    /// default.
+    #[structopt(long, value_name = "folder")]
+    device_games_dir: Option<String>,
+
+    /// Run the cargo build again, up to this many times, when it fails with what looks like a
+    /// transient filesystem error rather than a compile error.
+    #[structopt(long, value_name = "n")]
+    retry_build: Option<u32>,
+
+    /// Directory to stage the pdx contents and keep setup.o and the linked elf in, such as a
+    /// RAM disk, instead of the target directory. The pdx itself still goes to the target
+    /// directory.
+    #[structopt(long, parse(from_os_str))]
+    staging_dir: Option<PathBuf>,
+
+    /// Copy setup.o, the linked elf and the pdex.elf given to pdc into this directory after
+    /// a device build, for inspecting with objdump or readelf.
+    #[structopt(long, parse(from_os_str), value_name = "dir")]
+    keep_intermediates: Option<PathBuf>,
+
+    /// Only copy the assets modified since they were last copied, skipping the files of
+    /// directories that weren't modified since then.
+    #[structopt(long)]
+    since_last_build: bool,
+
+    /// Don't copy any assets, leaving those staged by an earlier build as they are.
+    #[structopt(long, conflicts_with = "since-last-build")]
+    no_assets: bool,
+
+    /// Write the SHA-256 of every file in the pdx to `<pdx name>.checksums.json` next to it.
+    #[structopt(long)]
+    emit_checksums: bool,
+
+    /// Remove the core and alloc that -Zbuild-std built for the device before building, so
+    /// they are compiled again, without cleaning anything else.
+    #[structopt(long)]
+    force_rebuild_std: bool,
+
+    /// A linked device binary to add to a simulator build, so that the pdx runs both on the
+    /// device and in the simulator.
+    #[structopt(skip)]
+    device_elf: Option<PathBuf>,
+
+    /// Write the build info module even without `emit_version_module`, as packaging does so
+    /// that the packaged game's `GIT_COMMIT` is the commit it reports.
+    #[structopt(skip)]
+    emit_version_module: bool,
 }
 
 impl Build {
-    fn setup_path() -> Result<PathBuf, Error> {
-        let playdate_c_api_path = playdate_c_api_path()?;
+    fn setup_path(settings: &Settings, project_path: &Path) -> Result<PathBuf, Error> {
+        let playdate_c_api_path = playdate_c_api_path(settings, project_path)?;
         Ok(playdate_c_api_path.join("buildsupport").join("setup.c"))
     }
 
-    fn get_target_name(&self, opt: &Opt) -> Result<Option<String>, Error> {
+    /// The linker script: `[toolchain] link_map`, relative to the project directory, or the
+    /// one in the C API.
+    fn link_map_path(settings: &Settings, project_path: &Path) -> Result<PathBuf, Error> {
+        match settings.toolchain.link_map.as_ref() {
+            Some(link_map) => {
+                let path = project_path.join(link_map);
+                if !path.is_file() {
+                    bail!("The [toolchain] link_map {:?} does not exist", path);
+                }
+                Ok(path)
+            }
+            None => Ok(playdate_c_api_path(settings, project_path)?
+                .join("buildsupport")
+                .join("link_map.ld")),
+        }
+    }
+
+    fn cargo_metadata(opt: &Opt) -> Result<cargo_metadata::Metadata, Error> {
         let mut cmd = cargo_metadata::MetadataCommand::new();
         if let Some(manifest_path) = &opt.manifest_path {
             cmd.manifest_path(manifest_path);
         }
         cmd.no_deps();
+        Ok(cmd.exec()?)
+    }
+
+    fn get_target_name(&self, metadata: &cargo_metadata::Metadata) -> Option<String> {
         let static_lib: String = "staticlib".to_string();
         let cdylib: String = "cdylib".to_string();
-        let metadata = cmd.exec()?;
-        for package in metadata.packages {
+        for package in &metadata.packages {
             if let Some(lib_target) = package
                 .targets
                 .iter()
-                .filter(|target| target.kind.contains(&static_lib) && target.kind.contains(&cdylib))
-                .nth(0)
+                .find(|target| target.kind.contains(&static_lib) && target.kind.contains(&cdylib))
             {
-                return Ok(Some(lib_target.name.clone()));
+                return Some(lib_target.name.clone());
+            }
+        }
+        None
+    }
+
+    /// Finds the package that owns the target being built, either the requested example or
+    /// the first library that can be built for the Playdate.
+    fn target_package<'a>(
+        &self,
+        metadata: &'a cargo_metadata::Metadata,
+    ) -> Option<&'a cargo_metadata::Package> {
+        let static_lib: String = "staticlib".to_string();
+        let cdylib: String = "cdylib".to_string();
+        let example_kind: String = "example".to_string();
+        metadata.packages.iter().find(|package| {
+            package.targets.iter().any(|target| {
+                if let Some(example) = self.example.as_ref() {
+                    target.kind.contains(&example_kind) && &target.name == example
+                } else {
+                    target.kind.contains(&static_lib) && target.kind.contains(&cdylib)
+                }
+            })
+        })
+    }
+
+    /// Splits, dedupes and validates the requested features, along with those of the chosen
+    /// flavor, against the features the target package declares, so that a typo fails before
+    /// cargo starts building.
+    fn resolve_features(
+        &self,
+        metadata: &cargo_metadata::Metadata,
+        crank_manifest: &Manifest,
+    ) -> Result<Vec<String>, Error> {
+        let flavor_features = crank_manifest
+            .flavor
+            .iter()
+            .flat_map(|flavor| flavor.features.iter().flatten());
+        let mut features: Vec<String> = Vec::new();
+        for feature in self
+            .features
+            .iter()
+            .chain(flavor_features)
+            .flat_map(|features| features.split(|c: char| c == ',' || c.is_whitespace()))
+            .filter(|feature| !feature.is_empty())
+        {
+            if !features.iter().any(|existing| existing == feature) {
+                features.push(feature.to_string());
+            }
+        }
+
+        let package = match self.target_package(metadata) {
+            Some(package) => package,
+            None => return Ok(features),
+        };
+        let mut available: Vec<&str> = package
+            .features
+            .keys()
+            .map(|feature| feature.as_str())
+            .chain(
+                package
+                    .dependencies
+                    .iter()
+                    .filter(|dependency| dependency.optional)
+                    .map(|dependency| dependency.rename.as_ref().unwrap_or(&dependency.name))
+                    .map(|name| name.as_str()),
+            )
+            .collect();
+        available.sort_unstable();
+        available.dedup();
+
+        for feature in &features {
+            // `dep/feature` syntax enables a feature of a dependency, which cargo checks itself.
+            if feature == "default" || feature.contains('/') {
+                continue;
+            }
+            if !available.contains(&feature.as_str()) {
+                bail!(
+                    "unknown feature '{}'; available: {}",
+                    feature,
+                    if available.is_empty() {
+                        "(none)".to_string()
+                    } else {
+                        available.join(", ")
+                    }
+                );
             }
         }
-        Ok(None)
+        Ok(features)
+    }
+
+    /// Device builds are compiled with `-Cpanic=abort` and `panic_immediate_abort`, so a cargo
+    /// profile that asks for unwinding conflicts with them and tends to fail deep inside
+    /// build-std. Warn about it up front instead.
+    fn check_panic_strategy(&self, metadata: &cargo_metadata::Metadata) {
+        let profile = if self.release { "release" } else { "dev" };
+        let env_key = format!("CARGO_PROFILE_{}_PANIC", profile.to_uppercase());
+        let strategy = env::var(&env_key).ok().or_else(|| {
+            let cargo_toml = metadata.workspace_root.join("Cargo.toml");
+            let manifest: toml::Value = fs::read_to_string(cargo_toml).ok()?.parse().ok()?;
+            manifest
+                .get("profile")?
+                .get(profile)?
+                .get("panic")?
+                .as_str()
+                .map(String::from)
+        });
+        debug!("panic strategy for profile {}: {:?}", profile, strategy);
+        if strategy.as_deref() == Some("unwind") {
+            eprintln!(
+                "Warning: the {} profile sets panic = \"unwind\", but device builds require \
+                panic = \"abort\". crank builds core and alloc with panic_immediate_abort, \
+                so unwinding is not available on the Playdate and the build may fail inside \
+                build-std. Set panic = \"abort\" for this profile.",
+                profile
+            );
+        }
     }
 
-    fn compile_setup(&self, target_dir: &PathBuf) -> Result<(), Error> {
-        let gcc_compile_static_args = "-g3 -c -mthumb -mcpu=cortex-m7 -mfloat-abi=hard \
-        -mfpu=fpv5-sp-d16 -D__FPU_USED=1 -O2 -falign-functions=16 -fomit-frame-pointer \
-        -gdwarf-2 -Wall -Wno-unused -Wstrict-prototypes -Wno-unknown-pragmas -fverbose-asm \
-        -Wdouble-promotion -mword-relocations -fno-common \
-        -ffunction-sections -fdata-sections -DTARGET_PLAYDATE=1 -DTARGET_EXTENSION=1 -fno-exceptions";
-        let args_iter = gcc_compile_static_args.split(" ");
-        let playdate_c_api_path = playdate_c_api_path()?;
-        let setup_path = Self::setup_path()?;
-        let mut command = Command::new(GCC_PATH_STR);
+    /// The gcc command that compiles the SDK's setup.c into `setup.o` in `target_dir`.
+    fn setup_command(
+        settings: &Settings,
+        project_path: &Path,
+        target_dir: &Path,
+    ) -> Result<Command, Error> {
+        let args_iter = GCC_COMPILE_STATIC_ARGS.split(" ");
+        let playdate_c_api_path = playdate_c_api_path(settings, project_path)?;
+        let setup_path = Self::setup_path(settings, project_path)?;
+        let mut command = Command::new(gcc_path(settings));
+        priority::apply(&mut command);
         command
-            .stdout(Stdio::null())
-            .stderr(Stdio::inherit())
             .args(args_iter)
+            .args(gcc_cpu_args(settings))
+            .args(settings.toolchain.gcc_compile_args.iter().flatten())
             .arg(setup_path)
             .arg("-I")
-            .arg(playdate_c_api_path)
-            .arg("-o")
-            .arg(target_dir.join("setup.o"));
-        info!("compile_setup: {:?}", command);
-        let status = command.status()?;
+            .arg(playdate_c_api_path);
+        for include_dir in settings.toolchain.gcc_include_dirs.iter().flatten() {
+            command.arg("-I").arg(project_path.join(include_dir));
+        }
+        command.arg("-o").arg(target_dir.join("setup.o"));
+        Ok(command)
+    }
+
+    fn compile_setup(
+        &self,
+        settings: &Settings,
+        project_path: &Path,
+        target_dir: &Path,
+    ) -> Result<(), Error> {
+        let mut command = Self::setup_command(settings, project_path, target_dir)?;
+        info!("compile_setup: {}", command_line(&command));
+        let status = ui::run(&mut command)?;
         if !status.success() {
             bail!("gcc failed with error {:?}", status);
         }
         Ok(())
     }
 
-    fn link_binary(
-        &self,
-        target_dir: &PathBuf,
+    /// The gcc command that links `setup.o` and the static library into `<example_name>.elf`
+    /// in `target_dir`.
+    fn link_command(
+        settings: &Settings,
+        project_path: &Path,
+        target_dir: &Path,
         example_name: &str,
-        lib_path: &PathBuf,
-    ) -> Result<(), Error> {
-        let gcc_link_static_args = "-nostartfiles -mthumb -mcpu=cortex-m7 -mfloat-abi=hard \
-        -mfpu=fpv5-sp-d16 -D__FPU_USED=1 -Wl,--cref,--gc-sections,--no-warn-mismatch,--emit-relocs -fno-exceptions";
-
-        let mut cmd = Command::new(GCC_PATH_STR);
-        cmd.stdout(Stdio::null()).stderr(Stdio::inherit());
+        lib_path: &Path,
+    ) -> Result<Command, Error> {
+        let mut cmd = Command::new(gcc_path(settings));
+        priority::apply(&mut cmd);
         let setup_obj_path = target_dir.join("setup.o");
         cmd.arg(setup_obj_path);
         cmd.arg(lib_path);
 
-        let args_iter = gcc_link_static_args.split(" ");
-        cmd.args(args_iter);
-
-        let playdate_c_api_path = playdate_c_api_path()?;
-        let link_map_path = playdate_c_api_path.join("buildsupport").join("link_map.ld");
+        cmd.args(settings.build.gcc_link_args().split_whitespace());
+        cmd.args(gcc_cpu_args(settings));
 
+        let link_map_path = Self::link_map_path(settings, project_path)?;
         cmd.arg("-T");
         cmd.arg(link_map_path);
 
         let target_path = target_dir.join(format!("{}.elf", example_name));
         cmd.arg("-o");
-        cmd.arg(target_path);
+        cmd.arg(&target_path);
 
         cmd.arg("--entry");
         cmd.arg("eventHandlerShim"); // declared in setup.c
+        Ok(cmd)
+    }
 
+    fn link_binary(
+        &self,
+        settings: &Settings,
+        project_path: &Path,
+        target_dir: &Path,
+        example_name: &str,
+        lib_path: &Path,
+    ) -> Result<(), Error> {
+        let mut cmd =
+            Self::link_command(settings, project_path, target_dir, example_name, lib_path)?;
+        let target_path = target_dir.join(format!("{}.elf", example_name));
         info!("link_binary: {:?}", cmd);
 
-        let status = cmd.status()?;
+        let status = ui::run(&mut cmd)?;
         if !status.success() {
             bail!("gcc failed with error {:?}", status);
         }
 
+        // The linker only warns about a missing entry point, and the binary then crashes as
+        // soon as the device loads it.
+        for problem in elf::check_required_symbols(&target_path)? {
+            eprintln!(
+                "Warning: {} in {}, so the game will crash on launch. Check that the game \
+                uses crankstart's crankstart_game! macro.",
+                problem,
+                target_path.display()
+            );
+        }
+
         Ok(())
     }
 
     fn make_binary(
         &self,
-        target_dir: &PathBuf,
+        target_dir: &Path,
         example_name: &str,
-        source_dir: &PathBuf,
+        source_dir: &Path,
     ) -> Result<(), Error> {
         let source_path = target_dir.join(format!("{}.elf", example_name));
         let source_dir_path = source_dir.join("pdex.elf");
@@ -281,112 +984,379 @@ impl Build {
         Ok(())
     }
 
+    fn copy_elf_output(
+        &self,
+        target_dir: &Path,
+        example_name: &str,
+        elf_output: &Path,
+    ) -> Result<(), Error> {
+        let elf_path = target_dir.join(format!("{}.elf", example_name));
+        let output_path = if elf_output.is_dir() {
+            elf_output.join(format!("{}.elf", example_name))
+        } else {
+            if let Some(output_parent) = elf_output.parent() {
+                fs::create_dir_all(output_parent)?;
+            }
+            elf_output.to_path_buf()
+        };
+        info!("copy_elf_output {:?} -> {:?}", elf_path, output_path);
+        fs::copy(&elf_path, &output_path)
+            .with_context(|| format!("Copying {:?} to {:?}", elf_path, output_path))?;
+        Ok(())
+    }
+
+    /// Copies the inputs and outputs of the device link into `dir`, where later builds and
+    /// pdc leave them alone.
+    fn keep_intermediates(
+        &self,
+        target_dir: &Path,
+        example_name: &str,
+        source_dir: &Path,
+        dir: &Path,
+    ) -> Result<(), Error> {
+        fs::create_dir_all(dir).with_context(|| format!("Creating {:?}", dir))?;
+        let elf_name = format!("{}.elf", example_name);
+        for (path, name) in [
+            (target_dir.join("setup.o"), "setup.o"),
+            (target_dir.join(&elf_name), elf_name.as_str()),
+            (source_dir.join("pdex.elf"), "pdex.elf"),
+        ]
+        .iter()
+        {
+            if !path.is_file() {
+                eprintln!("Warning: {:?} is missing, so it can't be kept.", path);
+                continue;
+            }
+            let output_path = dir.join(name);
+            info!("keep_intermediates {:?} -> {:?}", path, output_path);
+            fs::copy(path, &output_path)
+                .with_context(|| format!("Copying {:?} to {:?}", path, output_path))?;
+        }
+        println!("Kept the link intermediates in {}.", dir.display());
+        Ok(())
+    }
+
     fn make_source_dir(
         &self,
-        overall_target_dir: &PathBuf,
+        overall_target_dir: &Path,
         example_title: &str,
     ) -> Result<PathBuf, Error> {
         info!("make_source_dir");
+        // Titles are sanitized with title::file_name, but joining anything else could place
+        // the staging directory, and the files removed from it, outside the target directory.
+        let mut components = Path::new(example_title).components();
+        if !matches!(
+            (components.next(), components.next()),
+            (Some(Component::Normal(_)), None)
+        ) {
+            bail!(
+                "Game title {:?} can't be used as a directory name",
+                example_title
+            );
+        }
         let pdx_path = overall_target_dir.join(example_title);
-        fs::create_dir_all(&pdx_path)?;
+        if pdx_path.exists() && !pdx_path.is_dir() {
+            eprintln!(
+                "Warning: removing {:?}, which is in the way of the staging directory",
+                pdx_path
+            );
+            fs::remove_file(&pdx_path).with_context(|| format!("Removing {:?}", pdx_path))?;
+        }
+        fs::create_dir_all(&pdx_path)
+            .with_context(|| format!("Creating staging directory {:?}", pdx_path))?;
 
         Ok(pdx_path)
     }
 
+    /// Copies the target's assets, which are relative to the directory of the Crank.toml, into
+    /// the staging directory.
+    ///
+    /// A stamp next to the staging directory records when its assets were last copied, and a
+    /// snapshot next to it the directories they were copied from, so that `--since-last-build`
+    /// can copy only what changed after that. They are only trusted if they were written for
+    /// the same asset list and every asset is still staged.
     fn copy_assets(
         &self,
         target_name: &str,
-        source_dir: &Path,
         crank_manifest: &Manifest,
-        dest_dir: &PathBuf,
+        dest_dir: &Path,
     ) -> Result<(), Error> {
         info!("copy_assets");
-        let target = crank_manifest.get_target(target_name);
-        if let Some(Target {
-            assets: Some(assets),
-            ..
-        }) = target
-        {
-            for asset in assets {
-                let src_path = source_dir.join(asset);
-                let dst_path = dest_dir.join(asset);
-                info!("copy {:?} to {:?}", src_path, dst_path);
-                if let Some(dst_parent) = dst_path.parent() {
-                    fs::create_dir_all(&dst_parent)?;
+        if self.no_assets {
+            eprintln!(
+                "Warning: --no-assets skips copying assets, so the pdx has whatever assets an \
+                earlier build staged, which may be stale or missing."
+            );
+            return Ok(());
+        }
+        let stamp_path = dest_dir.with_file_name(format!(
+            "{}.crank-assets",
+            dest_dir.file_name().unwrap_or_default().to_string_lossy()
+        ));
+        let snapshot_path = stamp_path.with_extension("crank-assets-dirs");
+        if let Some(assets) = crank_manifest.assets_for(target_name, self.release) {
+            let mut key = BuildKey::new();
+            key.add_str("assets", &format!("{:?}", assets));
+            key.add_str("source", &crank_manifest.dir.to_string_lossy());
+            let source_extensions = crank_manifest.settings.build.source_extensions();
+            key.add_str("source_extensions", &source_extensions.join(","));
+            let key = key.finish();
+            let since = if self.since_last_build {
+                let staged = assets
+                    .iter()
+                    .all(|asset| dest_dir.join(asset.destination()).exists());
+                let since = cache::read_stamp(&stamp_path, &key)
+                    .filter(|_| staged)
+                    .map(|time| assets::LastCopy {
+                        time,
+                        dirs: assets::read_snapshot(&snapshot_path),
+                    });
+                if since.is_none() {
+                    println!("No earlier copy of these assets to build on, copying all of them.");
                 }
-                fs::copy(&src_path, &dst_path)?;
-            }
+                since
+            } else {
+                None
+            };
+            let started = time::SystemTime::now();
+            let dirs = assets::copy_assets(
+                &assets,
+                &crank_manifest.dir,
+                dest_dir,
+                since.as_ref(),
+                &source_extensions,
+            )?;
+            assets::store_snapshot(&snapshot_path, &dirs)?;
+            cache::store_stamp(&stamp_path, &key, started)?;
+        }
+        let font_problems = assets::check_fonts(dest_dir)?;
+        if !font_problems.is_empty() {
+            bail!(
+                "Incomplete fonts in the assets:\n{}",
+                font_problems.join("\n")
+            );
         }
         Ok(())
     }
 
+    /// Fingerprints the inputs of the device link, asset copy and pdc steps. Settings go in
+    /// whole, through `Settings::build_key`.
+    fn device_build_key(
+        &self,
+        crank_manifest: &Manifest,
+        build_target: &BuildTarget,
+        lib_file: &Path,
+    ) -> Result<String, Error> {
+        let settings = &crank_manifest.settings;
+        let mut key = BuildKey::new();
+        key.add_path("staticlib", lib_file)?;
+        let assets = crank_manifest.assets_for(&build_target.target_name, self.release);
+        for asset in assets.iter().flatten() {
+            key.add_str("asset", asset.destination());
+            key.add_path(asset.source(), &crank_manifest.dir.join(asset.source()))?;
+        }
+        key.add_str(
+            "metadata",
+            &format!(
+                "{:?}",
+                crank_manifest.metadata_for(&build_target.target_name, self.release)
+            ),
+        );
+        if let Some(pdxinfo_path) = crank_manifest
+            .get_target(&build_target.target_name)
+            .and_then(|target| target.pdxinfo_path.as_ref())
+        {
+            key.add_path("pdxinfo_path", &crank_manifest.dir.join(pdxinfo_path))?;
+        }
+        key.add_str("bundle_suffix", &self.bundle_suffix(crank_manifest));
+        key.add_str("settings", &settings.build_key());
+        // Settings only name these; what they resolve to and contain matters too.
+        key.add_str("gcc", &gcc_path(settings).to_string_lossy());
+        for include_dir in settings.toolchain.gcc_include_dirs.iter().flatten() {
+            key.add_path(
+                "gcc_include_dir",
+                &build_target.project_path.join(include_dir),
+            )?;
+        }
+        key.add_path(
+            "buildsupport",
+            &playdate_c_api_path(settings, &build_target.project_path)?.join("buildsupport"),
+        )?;
+        key.add_path(
+            "link_map",
+            &Self::link_map_path(settings, &build_target.project_path)?,
+        )?;
+        key.add_path("pdc", &pdc_path(settings)?)?;
+        key.add_str("strip", &self.strip().to_string());
+        // A build without assets must not let the next full build skip copying them.
+        key.add_str("no_assets", &self.no_assets.to_string());
+        Ok(key.finish())
+    }
+
+    fn remove_pdx(dest_path: &Path, key_path: &Path) {
+        if dest_path.exists() {
+            fs::remove_dir_all(dest_path).unwrap_or(());
+        }
+        fs::remove_file(key_path).unwrap_or(());
+    }
+
+    /// Whether pdc strips debug symbols: `--strip` or `--no-strip`, and otherwise only for
+    /// release builds.
+    fn strip(&self) -> bool {
+        if self.strip || self.no_strip {
+            self.strip
+        } else {
+            self.release
+        }
+    }
+
+    /// `--bundle-suffix`, or the flavor's suffix followed by `.debug` in debug builds.
+    fn bundle_suffix(&self, crank_manifest: &Manifest) -> String {
+        if let Some(suffix) = self.bundle_suffix.as_ref() {
+            return suffix.clone();
+        }
+        let flavor_suffix = crank_manifest
+            .flavor
+            .as_ref()
+            .and_then(|flavor| flavor.bundle_suffix.as_deref())
+            .unwrap_or_default();
+        let profile_suffix = if self.release { "" } else { ".debug" };
+        format!("{}{}", flavor_suffix, profile_suffix)
+    }
+
+    /// The `key=value` lines of the pdxinfo generated from the target's metadata, if it has
+    /// any.
+    fn pdxinfo_lines(&self, crank_manifest: &Manifest, target_name: &str) -> Option<Vec<String>> {
+        let metadata = crank_manifest.metadata_for(target_name, self.release)?;
+        let bundle_id = metadata.resolved_bundle_id(&self.bundle_suffix(crank_manifest));
+        let build_number = metadata.build_number.map(|number| number.to_string());
+        Some(
+            [
+                ("name", metadata.name.as_ref()),
+                ("author", metadata.author.as_ref()),
+                ("description", metadata.description.as_ref()),
+                ("bundleID", bundle_id.as_ref()),
+                ("version", metadata.version.as_ref()),
+                ("buildNumber", build_number.as_ref()),
+                ("imagePath", metadata.image_path.as_ref()),
+                ("launchSoundPath", metadata.launch_sound_path.as_ref()),
+            ]
+            .iter()
+            .filter_map(|(key, value)| value.map(|value| format!("{}={}", key, value)))
+            .collect(),
+        )
+    }
+
     fn make_manifest(
         &self,
         crank_manifest: &Manifest,
         target_name: &str,
-        source_dir: &PathBuf,
+        source_dir: &Path,
     ) -> Result<(), Error> {
         info!("make_manifest");
-        let target = crank_manifest.get_target(target_name);
-        if let Some(Target {
-            metadata: Some(metadata),
-            ..
-        }) = target
+        if let Some(pdxinfo_path) = crank_manifest
+            .get_target(target_name)
+            .and_then(|target| target.pdxinfo_path.as_ref())
         {
+            let pdxinfo_path = crank_manifest.dir.join(pdxinfo_path);
+            fs::copy(&pdxinfo_path, source_dir.join("pdxinfo")).with_context(|| {
+                format!(
+                    "Copying the pdxinfo_path {:?} of target '{}'",
+                    pdxinfo_path, target_name
+                )
+            })?;
+            return Ok(());
+        }
+        if let Some(lines) = self.pdxinfo_lines(crank_manifest, target_name) {
             let pdx_info_path = source_dir.join("pdxinfo");
             let mut pdx_info = fs::File::create(&pdx_info_path)?;
-
-            if let Some(name) = &metadata.name {
-                writeln!(pdx_info, "name={}", name)?;
-            }
-            if let Some(author) = &metadata.author {
-                writeln!(pdx_info, "author={}", author)?;
-            }
-            if let Some(description) = &metadata.description {
-                writeln!(pdx_info, "description={}", description)?;
-            }
-            if let Some(bundle_id) = &metadata.bundle_id {
-                writeln!(pdx_info, "bundleID={}", bundle_id)?;
-            }
-            if let Some(version) = &metadata.version {
-                writeln!(pdx_info, "version={}", version)?;
-            }
-            if let Some(build_number) = &metadata.build_number {
-                writeln!(pdx_info, "buildNumber={}", build_number)?;
-            }
-            if let Some(image_path) = &metadata.image_path {
-                writeln!(pdx_info, "imagePath={}", image_path)?;
-            }
-            if let Some(launch_sound_path) = &metadata.launch_sound_path {
-                writeln!(pdx_info, "launchSoundPath={}", launch_sound_path)?;
+            for line in lines {
+                writeln!(pdx_info, "{}", line)?;
             }
         }
         Ok(())
     }
 
-    fn run_pdc(&self, source_dir: &PathBuf, dest_dir: &PathBuf) -> Result<(), Error> {
-        info!("run_pdc");
-        let pdc_path = playdate_sdk_path()?.join("bin").join(PDC_NAME);
+    /// The pdc command that compiles the staging directory `source_dir` into the pdx.
+    fn pdc_command(
+        &self,
+        settings: &Settings,
+        source_dir: &Path,
+        dest_dir: &Path,
+    ) -> Result<Command, Error> {
+        let pdc_path = pdc_path(settings)?;
         let mut cmd = Command::new(pdc_path);
-        cmd.arg("--strip");
+        priority::apply(&mut cmd);
+        if self.strip() {
+            cmd.arg("--strip");
+        }
         //   cmd.arg("--verbose");
         cmd.arg(source_dir);
         cmd.arg(dest_dir);
+        Ok(cmd)
+    }
 
+    fn run_pdc(
+        &self,
+        settings: &Settings,
+        source_dir: &Path,
+        dest_dir: &Path,
+    ) -> Result<(), Error> {
+        info!("run_pdc");
+        let mut cmd = self.pdc_command(settings, source_dir, dest_dir)?;
         debug!("{:?}", cmd);
 
-        let status = cmd
-            .stdout(std::process::Stdio::piped())
-            .stderr(std::process::Stdio::piped())
-            .status()?;
-        if !status.success() {
-            bail!("pdc failed with error {:?}", status);
+        // pdc can take a while on large asset sets, so say what is happening. Without summaries
+        // its own output is let through as it is written.
+        if !ui::summarized() {
+            println!("Compiling assets with pdc...");
+        }
+        if self.deny_pdc_warnings {
+            // The output has to be captured to look for warnings, so it is echoed afterwards.
+            let output = cmd.output()?;
+            std::io::stdout().write_all(&output.stdout)?;
+            std::io::stderr().write_all(&output.stderr)?;
+            if !output.status.success() {
+                bail!("pdc failed with error {:?}", output.status);
+            }
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let warnings: Vec<&str> = stdout
+                .lines()
+                .chain(stderr.lines())
+                .filter(|line| line.to_lowercase().contains("warning"))
+                .collect();
+            if !warnings.is_empty() {
+                bail!(
+                    "pdc reported {} warning(s) and --deny-pdc-warnings is set:\n{}",
+                    warnings.len(),
+                    warnings.join("\n")
+                );
+            }
+        } else {
+            let status = ui::run(&mut cmd)?;
+            if !status.success() {
+                bail!("pdc failed with error {:?}", status);
+            }
+        }
+
+        let missing = assets::missing_from_pdx(source_dir, dest_dir)?;
+        if !missing.is_empty() {
+            eprintln!(
+                "Warning: pdc left {} staged file(s) out of the pdx, likely because it doesn't \
+                support their type: {}",
+                missing.len(),
+                missing
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
         }
 
         Ok(())
     }
 
-    #[cfg(unix)]
     fn copy_directory(src: &Path, dst: &Path) -> Result<(), Error> {
         info!("copy_directory {:?} -> {:?}", src, dst);
         for entry in fs::read_dir(src).context("Reading source game directory")? {
@@ -404,18 +1374,151 @@ impl Build {
         Ok(())
     }
 
+    /// Polls for a connected Playdate until `timeout`, printing a reminder every few seconds.
+    fn wait_for_device(&self, settings: &Settings, timeout: time::Duration) -> Result<(), Error> {
+        let start = time::Instant::now();
+        loop {
+            let remaining = timeout.saturating_sub(start.elapsed());
+            match device::wait_for_device(
+                self.device_serial.as_deref(),
+                settings,
+                remaining.min(WAIT_FOR_DEVICE_INTERVAL),
+            ) {
+                Some(true) => return Ok(()),
+                None => {
+                    info!("can't detect a device on this platform, not waiting");
+                    return Ok(());
+                }
+                Some(false) if start.elapsed() >= timeout => {
+                    bail!("No Playdate was connected within {:?}", timeout)
+                }
+                Some(false) => println!("Waiting for device..."),
+            }
+        }
+    }
+
+    /// Collects the profiling samples the running game prints and prints their summary.
+    fn profile_device(&self, settings: &Settings, duration: time::Duration) -> Result<(), Error> {
+        println!(
+            "Profiling for {:?}, from the {} lines the game prints...",
+            duration,
+            profile::MARKER
+        );
+        let samples = profile::collect(self.device_serial.as_deref(), settings, duration)?;
+        if let Some(report) = profile::summarize(&samples) {
+            for line in report.lines() {
+                println!("{}", line);
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the checksums of the files in `pdx_dir` to `<pdx_name>.checksums.json` in
+    /// `output_dir`.
+    fn emit_checksums(output_dir: &Path, pdx_name: &str, pdx_dir: &Path) -> Result<(), Error> {
+        let checksums_path = output_dir.join(format!("{}.checksums.json", pdx_name));
+        archive::write_checksums(&checksums_path, pdx_dir)?;
+        println!("Wrote checksums to {}", checksums_path.display());
+        Ok(())
+    }
+
+    /// Echoes and records the console of the game running on the device into a new session
+    /// directory inside `parent`.
+    fn record_device(
+        &self,
+        settings: &Settings,
+        parent: &Path,
+        pdx_name: &str,
+    ) -> Result<(), Error> {
+        let mut session = record::Session::create(parent, pdx_name, "device")?;
+        println!(
+            "Recording the console to {}, press Ctrl-C to stop",
+            session.dir().display()
+        );
+        let console = device::open_console(self.device_serial.as_deref(), settings)?;
+        record::record_device(
+            console,
+            &mut session,
+            self.record_screenshots.map(time::Duration::from_secs),
+        )
+    }
+
+    /// Runs the game on a device behind a serial-over-network bridge. The data disk can't be
+    /// mounted over the bridge, so the pdx has to be installed on the device already.
+    fn run_remote(&self, settings: &Settings, address: &str, pdx_name: &str) -> Result<(), Error> {
+        info!("run_remote");
+        let games_dir = device::games_dir(self.device_games_dir.as_deref(), settings)?;
+        eprintln!(
+            "Warning: {} is a remote device, so the pdx can't be copied to it. \
+            Running {}/{}.pdx as already installed on the device.",
+            address, games_dir, pdx_name
+        );
+        let mut console = device::open_console(self.device_serial.as_deref(), settings)?;
+        device::send_command(
+            &mut *console,
+            &format!("run {}/{}.pdx", games_dir, pdx_name),
+        )
+    }
+
+    /// Where the OS mounts removable disks such as the Playdate's data disk.
+    #[cfg(unix)]
+    fn volumes_dir() -> Result<PathBuf, Error> {
+        if cfg!(target_os = "macos") {
+            return Ok(PathBuf::from("/Volumes"));
+        }
+        match env::var("USER") {
+            Ok(user) => Ok(PathBuf::from("/run/media").join(user)),
+            Err(_) => bail!(
+                "USER is not set, so the Playdate's data disk can't be found in /run/media; \
+                set PLAYDATE_MOUNT_POINT to where it is mounted"
+            ),
+        }
+    }
+
+    /// Installs each of `pdx_dirs` with `pdutil install`, then runs the one whose folder name
+    /// is `launch`, if any.
     #[cfg(windows)]
-    fn run_target(&self, pdx_dir: &PathBuf, example_title: &str) -> Result<(), Error> {
-        info!("run_target");
-        let pdutil_path = playdate_sdk_path()?.join("bin").join(PDUTIL_NAME);
-        let device_path = format!("/Games/{}.pdx", example_title);
+    fn install_on_device(
+        &self,
+        settings: &Settings,
+        pdx_dirs: &[PathBuf],
+        launch: Option<&str>,
+    ) -> Result<(), Error> {
+        info!("install_on_device");
+        let pdutil_path = pdutil_path(settings)?;
+        let games_dir = device::games_dir(self.device_games_dir.as_deref(), settings)?;
+        if games_dir != device::DEFAULT_GAMES_DIR {
+            eprintln!(
+                "Warning: pdutil install always installs into {}, so {} is not used on Windows.",
+                device::DEFAULT_GAMES_DIR,
+                games_dir
+            );
+        }
         let duration = time::Duration::from_millis(100);
+        if self.full_copy {
+            info!("pdutil install always copies the whole pdx");
+        }
+        if self.touch_device {
+            let version = device::touch_device(
+                self.device_serial.as_deref(),
+                settings,
+                TOUCH_DEVICE_TIMEOUT,
+            )
+            .context("Checking the device")?;
+            println!("Found a Playdate running firmware {}.", version);
+        }
 
-        let _ = Command::new(&pdutil_path)
-            .arg("install")
-            .arg(pdx_dir)
-            .status()?;
+        for pdx_dir in pdx_dirs {
+            let _ = Command::new(&pdutil_path)
+                .arg("install")
+                .arg(pdx_dir)
+                .status()?;
+        }
 
+        let device_path = match launch {
+            Some(game_device_dir) => format!("{}/{}", device::DEFAULT_GAMES_DIR, game_device_dir),
+            None => return Ok(()),
+        };
         thread::sleep(duration * 5);
 
         let _ = Command::new(&pdutil_path)
@@ -425,40 +1528,102 @@ impl Build {
         Ok(())
     }
 
+    /// Waits for the Playdate's data disk to be mounted in `volumes_dir`, under whichever name
+    /// the system gave it, and returns it. Only the volumes that are new since `before` are
+    /// considered until `device::NEW_DATA_VOLUME_TIMEOUT` has passed.
     #[cfg(unix)]
-    fn run_target(&self, pdx_dir: &PathBuf, example_title: &str) -> Result<(), Error> {
+    fn wait_for_data_volume(
+        volumes_dir: &Path,
+        before: &[PathBuf],
+        duration: time::Duration,
+    ) -> PathBuf {
+        let start = time::Instant::now();
+        let candidates = loop {
+            let candidates = device::data_volume_candidates(
+                &device::data_volumes(volumes_dir),
+                before,
+                start.elapsed() >= device::NEW_DATA_VOLUME_TIMEOUT,
+            );
+            if !candidates.is_empty() {
+                break candidates;
+            }
+            thread::sleep(duration);
+        };
+        if candidates.len() > 1 {
+            eprintln!(
+                "Warning: found {} Playdate data disks, using {}: {}. Set PLAYDATE_MOUNT_POINT \
+                to choose another.",
+                candidates.len(),
+                candidates[0].display(),
+                candidates
+                    .iter()
+                    .map(|volume| volume.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        info!("data volume {:?}", candidates[0]);
+        candidates[0].clone()
+    }
+
+    fn run_target(
+        &self,
+        settings: &Settings,
+        pdx_dir: &Path,
+        example_title: &str,
+    ) -> Result<(), Error> {
         info!("run_target");
+        self.install_on_device(
+            settings,
+            &[pdx_dir.to_path_buf()],
+            Some(&format!("{}.pdx", example_title)),
+        )
+    }
 
-        let pdutil_path = playdate_sdk_path()?.join("bin").join(PDUTIL_NAME);
-        #[cfg(target_os = "macos")]
-        let modem_path = PathBuf::from(
-            env::var("PLAYDATE_SERIAL_DEVICE")
-                .unwrap_or(String::from("/dev/cu.usbmodemPDU1_Y0005491")),
-        );
-        #[cfg(target_os = "linux")]
-        let modem_path = PathBuf::from(
-            env::var("PLAYDATE_SERIAL_DEVICE")
-                // On Linux, we can use named symlinks to find the device in most cases
-                .unwrap_or(find_serial_device().unwrap_or(String::from("/dev/ttyACM0"))),
-        );
-        #[cfg(all(not(target_os = "linux"), not(target_os = "macos")))]
-        let modem_path = PathBuf::from(
-            env::var("PLAYDATE_SERIAL_DEVICE").unwrap_or(String::from("/dev/ttyACM0")),
-        );
-        #[cfg(target_os = "macos")]
-        let data_path = PathBuf::from(
-            env::var("PLAYDATE_MOUNT_POINT").unwrap_or(String::from("/Volumes/PLAYDATE")),
-        );
-        #[cfg(not(target_os = "macos"))]
-        let data_path = PathBuf::from(env::var("PLAYDATE_MOUNT_POINT").unwrap_or(format!(
-            "/run/media/{}/PLAYDATE",
-            env::var("USER").expect("user")
-        )));
+    /// Copies each of `pdx_dirs` into the games folder during a single data disk session,
+    /// then runs the one whose folder name is `launch`, if any.
+    #[cfg(unix)]
+    fn install_on_device(
+        &self,
+        settings: &Settings,
+        pdx_dirs: &[PathBuf],
+        launch: Option<&str>,
+    ) -> Result<(), Error> {
+        info!("install_on_device");
+
+        let pdutil_path = pdutil_path(settings)?;
+        let device_games_dir = device::games_dir(self.device_games_dir.as_deref(), settings)?;
+        let modem_path = device::serial_device_path(self.device_serial.as_deref(), settings);
+        let mount_point = env::var_os("PLAYDATE_MOUNT_POINT").map(PathBuf::from);
+        // A data disk already mounted before the device is put in data disk mode, such as
+        // another Playdate's, isn't the one to install to.
+        let volumes = match mount_point {
+            Some(_) => None,
+            None => {
+                let volumes_dir = Self::volumes_dir()?;
+                let volumes_before = device::data_volumes(&volumes_dir);
+                Some((volumes_dir, volumes_before))
+            }
+        };
 
         let duration = time::Duration::from_millis(100);
+        if self.touch_device {
+            if !modem_path.exists() {
+                bail!("No Playdate found at {:?}", modem_path);
+            }
+            let version = device::touch_device(
+                self.device_serial.as_deref(),
+                settings,
+                TOUCH_DEVICE_TIMEOUT,
+            )
+            .with_context(|| format!("Checking the device at {:?}", modem_path))?;
+            println!("Found a Playdate running firmware {}.", version);
+        }
         if modem_path.exists() {
             let mut cmd = Command::new(&pdutil_path);
-            cmd.arg(modem_path.clone()).arg("datadisk").arg(pdx_dir);
+            cmd.arg(modem_path.clone())
+                .arg("datadisk")
+                .args(pdx_dirs.first());
             info!("datadisk cmd: {:#?}", cmd);
             let _ = cmd.status()?;
 
@@ -473,23 +1638,48 @@ impl Build {
         #[cfg(target_os = "linux")]
         println!("If your OS does not automatically mount your Playdate, please do so now.");
 
-        while !data_path.exists() {
-            thread::sleep(duration);
-        }
+        let data_path = match (mount_point, volumes) {
+            (Some(data_path), _) => {
+                while !data_path.exists() {
+                    thread::sleep(duration);
+                }
+
+                // This prevents issues that occur when the PLAYDATE volume is mounted
+                // but not all of the inner folders are available yet.
+                while !data_path.join("Games").exists() {
+                    thread::sleep(duration);
+                }
+                data_path
+            }
+            (None, Some((volumes_dir, volumes_before))) => {
+                Self::wait_for_data_volume(&volumes_dir, &volumes_before, duration)
+            }
+            (None, None) => unreachable!("volumes are listed without a mount point"),
+        };
 
-        let games_dir = data_path.join("Games");
+        let games_dir = data_path.join(device_games_dir.trim_start_matches('/'));
+        fs::create_dir_all(&games_dir)
+            .with_context(|| format!("Creating {} on the device", device_games_dir))?;
 
-        // This prevents issues that occur when the PLAYDATE volume is mounted
-        // but not all of the inner folders are available yet.
-        while !games_dir.exists() {
-            thread::sleep(duration);
+        for pdx_dir in pdx_dirs {
+            let game_device_dir = pdx_dir.file_name().expect("pdx folder name");
+            let games_target_dir = games_dir.join(game_device_dir);
+            if self.full_copy && games_target_dir.exists() {
+                fs::remove_dir_all(&games_target_dir)
+                    .with_context(|| format!("Removing previous install {:?}", games_target_dir))?;
+            }
+            let record_path =
+                pdx_dir.with_file_name(format!("{}.crank-sync", game_device_dir.to_string_lossy()));
+            let stats = sync::sync_directory(pdx_dir, &games_target_dir, &record_path)?;
+            if pdx_dirs.len() > 1 {
+                print!("{}: ", game_device_dir.to_string_lossy());
+            }
+            println!(
+                "Copied {} files to the device ({} unchanged, {} removed).",
+                stats.copied, stats.unchanged, stats.removed
+            );
         }
 
-        let game_device_dir = format!("{}.pdx", example_title);
-        let games_target_dir = games_dir.join(&game_device_dir);
-        fs::create_dir(&games_target_dir).ok();
-        Self::copy_directory(&pdx_dir, &games_target_dir)?;
-
         #[cfg(target_os = "macos")]
         {
             let mut cmd = Command::new("diskutil");
@@ -509,6 +1699,10 @@ impl Build {
         #[cfg(target_os = "linux")]
         println!("Please press 'A' on the Playdate to exit Data Disk mode.");
 
+        let game_device_dir = match launch {
+            Some(game_device_dir) => game_device_dir,
+            None => return Ok(()),
+        };
         while !modem_path.exists() {
             thread::sleep(duration);
         }
@@ -521,7 +1715,7 @@ impl Build {
         let mut cmd = Command::new(&pdutil_path);
         cmd.arg(modem_path)
             .arg("run")
-            .arg(format!("/Games/{}", game_device_dir));
+            .arg(format!("{}/{}", device_games_dir, game_device_dir));
         info!("run cmd: {:#?}", cmd);
         let _ = cmd.status()?;
 
@@ -530,44 +1724,109 @@ impl Build {
 
     fn link_dylib(
         &self,
-        target_dir: &PathBuf,
-        example_name: &str,
-        source_dir: &PathBuf,
+        settings: &Settings,
+        lib_target_path: &Path,
+        source_dir: &Path,
     ) -> Result<(), Error> {
         info!("link_dylib");
 
-        let (lib_target_path, source_dir_path) = if cfg!(target_os = "macos") {
-            let lib_target_path = target_dir.join(format!("lib{}.dylib", example_name));
-            let source_dir_path = source_dir.join("pdex.dylib");
-            (lib_target_path, source_dir_path)
-        } else if cfg!(unix) {
-            let lib_target_path = target_dir.join(format!("lib{}.so", example_name));
-            let source_dir_path = source_dir.join("pdex.so");
-            (lib_target_path, source_dir_path)
-        } else if cfg!(windows) {
-            let lib_target_path = target_dir.join(format!("{}.dll", example_name));
-            let source_dir_path = source_dir.join("pdex.dll");
-            (lib_target_path, source_dir_path)
-        } else {
-            unreachable!("platform not supported")
-        };
+        let source_dir_path = source_dir.join(format!("pdex.{}", env::consts::DLL_EXTENSION));
         debug!("copy: {:?} -> {:?}", lib_target_path, source_dir_path);
-        fs::copy(&lib_target_path, &source_dir_path)?;
+        fs::copy(lib_target_path, &source_dir_path)?;
 
+        // Simulator builds get an empty pdex.bin, as they always have, unless turned off. A
+        // universal pdx gets the real pdex.bin from pdc instead.
+        let create_stub =
+            self.device_elf.is_none() && settings.simulator.create_pdex_bin_stub.unwrap_or(true);
         let pdx_bin_path = source_dir.join("pdex.bin");
-        if !pdx_bin_path.exists() {
+        if create_stub && !pdx_bin_path.exists() {
             fs::File::create(&pdx_bin_path)?;
         }
 
+        // Device and simulator builds share the staging directory, so a pdex.elf left by an
+        // earlier device build is replaced by the one asked for, or removed.
+        let elf_path = source_dir.join("pdex.elf");
+        match self.device_elf.as_ref() {
+            Some(device_elf) => {
+                debug!("copy: {:?} -> {:?}", device_elf, elf_path);
+                fs::copy(device_elf, &elf_path)
+                    .with_context(|| format!("Copying {:?} to {:?}", device_elf, elf_path))?;
+            }
+            None if elf_path.exists() => {
+                fs::remove_file(&elf_path).with_context(|| format!("Removing {:?}", elf_path))?;
+            }
+            None => {}
+        }
+
+        Ok(())
+    }
+
+    /// Copies the pdx into the Games folder of the simulator's data disk, which lives in the
+    /// SDK's `Disk` directory on every platform, so it shows up in the simulator's own list.
+    fn install_simulator(
+        &self,
+        settings: &Settings,
+        pdx_path: &Path,
+        example_title: &str,
+    ) -> Result<(), Error> {
+        info!("install_simulator");
+        let games_dir = playdate_sdk_path(settings)?.join("Disk").join("Games");
+        let game_dir = games_dir.join(format!("{}.pdx", example_title));
+        if game_dir.exists() {
+            fs::remove_dir_all(&game_dir)
+                .with_context(|| format!("Removing previous install {:?}", game_dir))?;
+        }
+        fs::create_dir_all(&game_dir)
+            .with_context(|| format!("Creating simulator game directory {:?}", game_dir))?;
+        Self::copy_directory(pdx_path, &game_dir)?;
+        println!("Installed {} into {}", example_title, games_dir.display());
         Ok(())
     }
 
-    fn run_simulator(&self, pdx_path: &PathBuf) -> Result<(), Error> {
+    /// Whether a Playdate Simulator process is already running. There is no way to tell a
+    /// running simulator to load a new pdx, so launching again opens a second instance.
+    #[cfg(not(target_os = "macos"))]
+    fn simulator_running() -> bool {
+        #[cfg(windows)]
+        let output = Command::new("tasklist")
+            .arg("/FI")
+            .arg(format!("IMAGENAME eq {}", SIMULATOR_NAME))
+            .arg("/NH")
+            .output();
+        // The process name is truncated to 15 characters on Linux, so match the command line.
+        #[cfg(unix)]
+        let output = Command::new("pgrep")
+            .arg("-f")
+            .arg(format!("(^|/){}( |$)", SIMULATOR_NAME))
+            .output();
+        match output {
+            Ok(output) => String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .any(|line| !line.trim().is_empty() && !line.starts_with("INFO:")),
+            Err(err) => {
+                debug!("unable to check for a running simulator: {}", err);
+                false
+            }
+        }
+    }
+
+    #[cfg_attr(any(windows, target_os = "macos"), allow(unused_variables))]
+    fn run_simulator(&self, settings: &Settings, pdx_path: &Path) -> Result<(), Error> {
         info!("run_simulator");
+
+        // On macOS, `open` hands the pdx to the running simulator instead of starting another.
+        #[cfg(not(target_os = "macos"))]
+        if Self::simulator_running() {
+            eprintln!(
+                "Warning: the Playdate Simulator is already running, this opens another \
+                instance. Use --install-simulator to refresh the running simulator's library \
+                instead."
+            );
+        }
         #[cfg(windows)]
         let status = {
-            let mut cmd = Command::new("PlaydateSimulator.exe");
-            cmd.arg(&pdx_path);
+            let mut cmd = Command::new(SIMULATOR_NAME);
+            cmd.arg(pdx_path);
             cmd.status()?
         };
 
@@ -576,18 +1835,22 @@ impl Build {
             let mut cmd = Command::new("open");
             cmd.arg("-a");
             cmd.arg("Playdate Simulator");
-            cmd.arg(&pdx_path);
+            cmd.arg(pdx_path);
             cmd.status()?
         };
 
         #[cfg(all(unix, not(target_os = "macos")))]
         let status = {
-            let mut cmd = Command::new("PlaydateSimulator");
-            cmd.arg(&pdx_path);
+            let mut cmd = Command::new(SIMULATOR_NAME);
+            cmd.arg(pdx_path);
             cmd.status().or_else(|_| -> Result<ExitStatus, Error> {
                 info!("falling back on SDK path");
-                cmd = Command::new(playdate_sdk_path()?.join("bin").join("PlaydateSimulator"));
-                cmd.arg(&pdx_path);
+                cmd = Command::new(
+                    playdate_sdk_path(settings)?
+                        .join("bin")
+                        .join(SIMULATOR_NAME),
+                );
+                cmd.arg(pdx_path);
                 Ok(cmd.status()?)
             })?
         };
@@ -599,325 +1862,2537 @@ impl Build {
         Ok(())
     }
 
-    pub fn execute(
+    /// The rustup toolchain to build with: `--toolchain`, then the configured toolchain for
+    /// the device or simulator, with device builds defaulting to nightly for `-Zbuild-std`.
+    /// `+toolchain` is a rustup feature, so without rustup device builds fall back on the
+    /// default `cargo`; a toolchain that was asked for is always passed on.
+    fn toolchain(&self, settings: &Settings) -> Option<String> {
+        let configured = if self.device {
+            settings.build.device_toolchain.as_ref()
+        } else {
+            settings.build.simulator_toolchain.as_ref()
+        };
+        let chosen = self.toolchain.clone().or_else(|| configured.cloned());
+        if chosen.is_some() || !self.device {
+            return chosen;
+        }
+        if has_rustup() {
+            Some(DEVICE_TOOLCHAIN.to_string())
+        } else {
+            eprintln!(
+                "Warning: rustup not found, building with the default cargo instead of the {} toolchain.",
+                DEVICE_TOOLCHAIN
+            );
+            None
+        }
+    }
+
+    /// Builds the cargo command for `subcommand` (such as `build`) with the device or
+    /// simulator arguments and environment, along with the target it will build.
+    /// The directory of the package being built, which relative paths in the settings are
+    /// resolved against.
+    fn project_path(opt: &Opt) -> Result<PathBuf, Error> {
+        Ok(match opt.manifest_path.as_ref() {
+            Some(manifest_path) => manifest_path.parent().expect("parent").to_path_buf(),
+            None => env::current_dir()?,
+        })
+    }
+
+    fn cargo_command(
         &self,
+        subcommand: &str,
         opt: &Opt,
         crank_manifest: &Manifest,
-    ) -> Result<(PathBuf, String), Error> {
-        info!("building");
+        metadata: &cargo_metadata::Metadata,
+    ) -> Result<(Command, BuildTarget), Error> {
+        let mut args: Vec<String> = Vec::new();
+        let toolchain = self.toolchain(&crank_manifest.settings);
+        if let Some(toolchain) = toolchain.as_ref() {
+            args.push(format!("+{}", toolchain));
+        }
+        args.push(subcommand.to_string());
 
-        let current_dir = std::env::current_dir()?;
-        let manifest_path_str;
-        let mut args = if self.device {
-            vec!["+nightly", "build"]
-        } else {
-            vec!["build"]
-        };
+        if let Some(manifest_path) = opt.manifest_path.as_ref() {
+            args.push("--manifest-path".to_string());
+            args.push(manifest_path.to_string_lossy().into_owned());
+        }
+        let project_path = Self::project_path(opt)?;
 
-        let project_path = if let Some(manifest_path) = opt.manifest_path.as_ref() {
-            args.push("--manifest-path");
-            manifest_path_str = manifest_path.to_string_lossy();
-            args.push(&manifest_path_str);
-            manifest_path.parent().expect("parent")
-        } else {
-            current_dir.as_path()
-        };
+        // In a workspace the target may belong to a member rather than the root package, so
+        // name the owning package explicitly.
+        let package = self.target_package(metadata);
+        if let Some(package) = package {
+            args.push("-p".to_string());
+            args.push(package.name.clone());
+        }
 
-        let (target_name, target_path) = if let Some(example) = self.example.as_ref() {
-            args.push("--example");
-            args.push(example);
-            (example.clone(), format!("examples/"))
+        let target_name = if let Some(example) = self.example.as_ref() {
+            args.push("--example".to_string());
+            args.push(example.clone());
+            example.clone()
         } else {
-            args.push("--lib");
-            if let Some(target_name) = self.get_target_name(&opt)? {
-                (target_name.clone(), "".to_string())
+            args.push("--lib".to_string());
+            if let Some(target_name) = self.get_target_name(metadata) {
+                target_name
             } else {
                 bail!("Could not find compatible target");
             }
         };
 
         if self.release {
-            args.push("--release");
+            args.push("--release".to_string());
+        }
+
+        if let Some(jobs) = self.jobs {
+            args.push(format!("--jobs={}", jobs));
         }
 
-        let features;
-        if !self.features.is_empty() {
-            features = format!("--features={}", self.features.join(","));
-            args.push(&features);
+        let requested_features = self.resolve_features(metadata, crank_manifest)?;
+        if !requested_features.is_empty() {
+            args.push(format!("--features={}", requested_features.join(",")));
+        }
+        let default_features = self.default_features
+            || (!self.no_default_features
+                && crank_manifest
+                    .get_target(&target_name)
+                    .and_then(|target| target.default_features)
+                    .unwrap_or(true));
+        if !default_features {
+            args.push("--no-default-features".to_string());
         }
 
         if self.device {
-            args.push("--target");
-            args.push("thumbv7em-none-eabihf");
+            self.check_panic_strategy(metadata);
+            check_abi(&crank_manifest.settings)?;
 
-            args.push("-Zbuild-std=core,alloc");
-            args.push("-Zbuild-std-features=panic_immediate_abort");
+            args.push("--target".to_string());
+            args.push("thumbv7em-none-eabihf".to_string());
+
+            args.push("-Zbuild-std=core,alloc".to_string());
+            args.push("-Zbuild-std-features=panic_immediate_abort".to_string());
         }
 
-        let envs = if self.device {
+        let mut envs = if self.device {
             let mut map = HashMap::new();
+            // Unlike RUSTFLAGS, the encoded form keeps flags with spaces, such as
+            // `-Clink-args=...`, in one piece.
             map.insert(
-                "RUSTFLAGS",
-                [
-                    "-Ctarget-cpu=cortex-m7",
-                    "-Ctarget-feature=-fp64", // Rev A hardware seems to not have 64-bit floating point support
-                    "-Clink-args=--emit-relocs",
-                    "-Crelocation-model=pic",
-                    "-Cpanic=abort",
-                ]
-                .join(" "),
+                "CARGO_ENCODED_RUSTFLAGS",
+                device_rustflags(&crank_manifest.settings).join(&RUSTFLAGS_SEPARATOR.to_string()),
             );
             map
         } else {
             Default::default()
         };
 
+        if self.emit_version_module
+            || crank_manifest.settings.build.emit_version_module == Some(true)
+        {
+            let build_info_path = metadata
+                .target_directory
+                .join("crank")
+                .join("crank_build_info.rs");
+            let version = package
+                .map(|package| package.version.to_string())
+                .unwrap_or_default();
+            build_info::write(&build_info_path, &project_path, &version)?;
+            envs.insert(
+                build_info::BUILD_INFO_ENV,
+                build_info_path.to_string_lossy().into_owned(),
+            );
+        }
+
         let mut command = Command::new("cargo");
         command.args(args);
         command.envs(envs);
-        info!("build command: {:?}", command);
-
-        let status = command.status()?;
-        if !status.success() {
-            bail!("cargo failed with error {:?}", status);
-        }
+        priority::apply(&mut command);
 
-        let overall_target_dir = project_path.join("target");
-        let game_title = crank_manifest
-            .get_target(&target_name)
-            .and_then(|target| target.metadata.as_ref())
-            .and_then(|metadata| metadata.name.clone())
-            .unwrap_or(to_title_case(&target_name));
-        let package_name = target_name.replace('-', "_");
-        let source_path = self.make_source_dir(&overall_target_dir, &game_title)?;
-        let dest_path = overall_target_dir.join(format!("{}.pdx", &game_title));
-        if dest_path.exists() {
-            fs::remove_dir_all(&dest_path).unwrap_or_else(|_err| ());
+        let mut recipe = vec![
+            format!(
+                "target: {}",
+                if self.device {
+                    "thumbv7em-none-eabihf"
+                } else {
+                    "host"
+                }
+            ),
+            format!("toolchain: {}", toolchain.as_deref().unwrap_or("default")),
+        ];
+        if let Some(flavor) = crank_manifest.flavor.as_ref() {
+            recipe.push(format!("flavor: {}", flavor.name));
         }
-        let mut target_dir = project_path.join("target");
-        let dir_name = if self.release { "release" } else { "debug" };
         if self.device {
-            target_dir = target_dir.join("thumbv7em-none-eabihf").join(dir_name);
-            let lib_file = target_dir.join(format!("{}lib{}.a", target_path, package_name));
-            self.compile_setup(&target_dir)?;
-            self.link_binary(&target_dir, &package_name, &lib_file)?;
-            self.make_binary(&target_dir, &package_name, &source_path)?;
-            self.copy_assets(&target_name, &project_path, &crank_manifest, &source_path)?;
-            self.make_manifest(&crank_manifest, &target_name, &source_path)?;
-            self.run_pdc(&source_path, &dest_path)?;
-            if self.run {
-                self.run_target(&dest_path, &game_title)?;
-            }
-        } else {
-            target_dir = target_dir.join(dir_name).join(target_path);
-            self.link_dylib(&target_dir, &package_name, &source_path)?;
-            self.copy_assets(&target_name, &project_path, &crank_manifest, &source_path)?;
-            self.make_manifest(&crank_manifest, &target_name, &source_path)?;
-            self.run_pdc(&source_path, &dest_path)?;
-            if self.run {
-                self.run_simulator(&dest_path)?;
-            }
+            let settings = &crank_manifest.settings;
+            recipe.push(format!(
+                "cpu: {}, fpu: {}",
+                settings.toolchain.target_cpu(),
+                settings.toolchain.fpu()
+            ));
+            recipe.push("build-std: core,alloc (panic_immediate_abort)".to_string());
+        }
+        for (key, value) in command.get_envs() {
+            let value = value.unwrap_or_default().to_string_lossy();
+            let value = if value.contains(RUSTFLAGS_SEPARATOR) {
+                format!("{:?}", value.split(RUSTFLAGS_SEPARATOR).collect::<Vec<_>>())
+            } else {
+                value.into_owned()
+            };
+            recipe.push(format!("{}: {}", key.to_string_lossy(), value));
         }
+        recipe.push(format!(
+            "features: {}{}",
+            if requested_features.is_empty() {
+                "none requested".to_string()
+            } else {
+                requested_features.join(", ")
+            },
+            if default_features {
+                " (with default features)"
+            } else {
+                " (without default features)"
+            }
+        ));
+        recipe.push(format!("reproduce with: {}", command_line(&command)));
+        info!("build recipe:\n  {}", recipe.join("\n  "));
 
-        Ok((dest_path, game_title))
+        Ok((
+            command,
+            BuildTarget {
+                project_path,
+                target_directory: metadata.target_directory.clone(),
+                package_id: package.map(|package| package.id.clone()),
+                target_name,
+                artifacts: Vec::new(),
+            },
+        ))
     }
-}
-
-#[cfg(target_os = "linux")]
-/// Finds the canonical (resolved) path for the Playdate serial device.  If multiple Playdate devices are
-/// found, warns and returns the first.  If none is found, returns None.  If any error occurs,
-/// returns None.
-fn find_serial_device() -> Option<String> {
-    // Walk through this directory to find Playdate device filenames
-    let directory = "/dev/serial/by-id";
-    let filename_prefix = "usb-Panic_Inc_Playdate_PDU1-";
 
-    let walker = WalkDir::new(directory)
-        .min_depth(1)
-        .max_depth(1)
-        // Don't follow links (yet) because we want file_name to give us the name in this directory
-        .follow_links(false)
-        // If there are multiple, we let the user know and take the first; sort so it's consistent.
-        // If the user wants a different one, they can set PLAYDATE_SERIAL_DEVICE.
-        .sort_by_file_name()
-        .into_iter()
-        .filter_entry(|e| {
-            e.file_name()
-                .to_str()
-                .map(|s| s.starts_with(filename_prefix))
-                .unwrap_or(false)
-        })
-        .filter_map(|e| e.ok());
+    /// Checks the free space where the build writes before starting it, since running out
+    /// halfway fails with a confusing link or copy error and leaves partial artifacts.
+    fn check_free_space(
+        &self,
+        settings: &Settings,
+        project_path: &Path,
+        target_dir: &Path,
+    ) -> Result<(), Error> {
+        let min_mb = settings
+            .build
+            .min_free_space_mb
+            .unwrap_or(disk::DEFAULT_MIN_FREE_SPACE_MB);
+        let staging_dir = self.staging_dir(settings, project_path);
+        let dirs = std::iter::once(target_dir).chain(staging_dir.as_deref());
+        for dir in dirs {
+            let available_mb = match disk::available_space(dir) {
+                Some(available) => available / (1024 * 1024),
+                None => {
+                    info!("can't tell the free space at {:?}", dir);
+                    continue;
+                }
+            };
+            info!("{} MB free at {:?}", available_mb, dir);
+            if available_mb >= min_mb {
+                continue;
+            }
+            let message = format!(
+                "insufficient disk space: {} MB free at {}, below the minimum of {} MB",
+                available_mb,
+                dir.display(),
+                min_mb
+            );
+            if settings.build.min_free_space_mb.is_some() {
+                bail!(
+                    "{}. Free some space or lower [build] min_free_space_mb.",
+                    message
+                );
+            }
+            eprintln!("Warning: {}.", message);
+        }
+        Ok(())
+    }
 
-    // See what we found
-    let mut result: Option<PathBuf> = None;
-    for entry in walker {
-        match result {
-            // If there are multiple matches, let the user know, and return the first
-            Some(ref existing) => {
+    fn cargo_build(&self, opt: &Opt, crank_manifest: &Manifest) -> Result<BuildTarget, Error> {
+        let metadata = Self::cargo_metadata(opt)?;
+        self.check_free_space(
+            &crank_manifest.settings,
+            &Self::project_path(opt)?,
+            &metadata.target_directory,
+        )?;
+        if self.force_rebuild_std {
+            if self.device {
+                let profile_dir = metadata
+                    .target_directory
+                    .join("thumbv7em-none-eabihf")
+                    .join(if self.release { "release" } else { "debug" });
+                let removed = build_std::clear(&profile_dir)?;
                 println!(
-                    "Found multiple Playdate devices in {}, using first: {}",
-                    directory,
-                    existing.display()
+                    "Removed {} build-std artifacts from {}, core and alloc will be rebuilt.",
+                    removed,
+                    profile_dir.display()
+                );
+            } else {
+                eprintln!(
+                    "Warning: --force-rebuild-std only applies to device builds, ignoring it."
                 );
-                break;
-            }
-            None => {
-                result = Some(entry.into_path());
             }
         }
-    }
+        let retries = self.retry_build.unwrap_or(0);
+        let mut attempt = 0;
+        loop {
+            let (mut command, mut build_target) =
+                self.cargo_command("build", opt, crank_manifest, &metadata)?;
+            // Diagnostics are still rendered to stderr; stdout carries the artifact messages
+            // that say where the libraries actually ended up.
+            command
+                .arg("--message-format=json-render-diagnostics")
+                .stdout(Stdio::piped());
+            // Telling a transient failure from a compile error needs cargo's rendered output,
+            // which summaries also hold back unless the build fails.
+            let can_retry = attempt < retries;
+            let summarized = ui::summarized();
+            if can_retry || summarized {
+                command.stderr(Stdio::piped());
+                if ui::color() {
+                    command.arg("--color=always");
+                }
+            }
+            info!("build command: {:?}", command);
 
-    if let Some(path) = result {
-        // Fully resolve the link, which should result in something like "/dev/ttyACM0"
-        let resolved = fs::canonicalize(path).ok()?;
-        // Quick check that it did what we expected
-        if resolved
-            .to_str()
-            .map(|s| s.contains("tty"))
-            .unwrap_or(false)
-        {
-            println!("Resolved Playdate serial device to: {}", resolved.display());
-            // Other code expects String paths
-            return Some(resolved.to_string_lossy().into_owned());
-        } else {
+            let mut child = command.spawn()?;
+            let stderr = child
+                .stderr
+                .take()
+                .map(|stderr| retry::read_lines(stderr, !summarized));
+            let stdout = child.stdout.take().expect("stdout");
+            for message in cargo_metadata::Message::parse_stream(BufReader::new(stdout)) {
+                if let cargo_metadata::Message::CompilerArtifact(artifact) = message? {
+                    if build_target.is_artifact_of(&artifact) {
+                        debug!("artifact: {:?}", artifact.filenames);
+                        build_target.artifacts.extend(artifact.filenames);
+                    }
+                }
+            }
+            let status = child.wait()?;
+            let output = stderr
+                .map(|lines| lines.join().unwrap_or_default())
+                .unwrap_or_default();
+            if status.success() {
+                // Warnings are worth seeing even when the build works.
+                if summarized {
+                    for line in retry::diagnostics(&output) {
+                        eprintln!("{}", line);
+                    }
+                }
+                return Ok(build_target);
+            }
+            if summarized {
+                for line in &output {
+                    eprintln!("{}", line);
+                }
+            }
+            if !(can_retry && retry::is_transient_failure(&output)) {
+                bail!("cargo failed with error {:?}", status);
+            }
+            attempt += 1;
             eprintln!(
-                "Warning: found a device at '{}' but it's not named like we expect.  Using the default.",
-                resolved.display()
+                "Warning: cargo failed with what looks like a transient error, retrying ({} of {}).",
+                attempt, retries
             );
-            return None;
+            thread::sleep(RETRY_BUILD_DELAY);
         }
     }
 
-    None
-}
-
-#[derive(Debug, StructOpt)]
-struct Package {
-    /// Build a specific example from the examples/ dir.
-    #[structopt(long)]
-    example: Option<String>,
+    /// Turns the `--example` names and globs into one build per matching example, or returns
+    /// this build alone if no example was asked for, keeping only the affected ones with
+    /// `--affected`.
+    fn expand_examples(&self, opt: &Opt) -> Result<Vec<Build>, Error> {
+        let builds = self.matching_examples(opt)?;
+        match self.affected.as_ref() {
+            Some(base) => Self::only_affected(opt, base, builds),
+            None => Ok(builds),
+        }
+    }
 
-    /// Enable build feature flags.
-    #[structopt(long)]
-    features: Vec<String>,
+    /// Drops the builds that no file changed since `base` affects.
+    fn only_affected(opt: &Opt, base: &str, builds: Vec<Build>) -> Result<Vec<Build>, Error> {
+        // Unlike `cargo_metadata`, this needs the dependency graph.
+        let mut cmd = cargo_metadata::MetadataCommand::new();
+        if let Some(manifest_path) = opt.manifest_path.as_ref() {
+            cmd.manifest_path(manifest_path);
+        }
+        let project_path = Self::project_path(opt)?;
+        let metadata = cmd.exec()?;
+        let changed = affected::changed_files(&project_path, base)?;
+        info!("changed since {}: {:?}", base, changed);
+        let affected = affected::Affected::new(&metadata, &changed);
+        let (builds, skipped): (Vec<Build>, Vec<Build>) = builds
+            .into_iter()
+            .partition(|build| affected.includes(&metadata, build.example.as_deref()));
+        if !skipped.is_empty() {
+            let names: Vec<&str> = skipped
+                .iter()
+                .map(|build| build.example.as_deref().unwrap_or("the package"))
+                .collect();
+            println!(
+                "Skipping what changes since {} don't affect: {}",
+                base,
+                names.join(", ")
+            );
+        }
+        Ok(builds)
+    }
 
-    /// clean before building
-    #[structopt(long)]
-    clean: bool,
+    /// The builds for each example the `--example` names and globs match, or this build alone
+    /// if no example was asked for.
+    fn matching_examples(&self, opt: &Opt) -> Result<Vec<Build>, Error> {
+        if self.examples.is_empty() && self.example_paths.is_empty() {
+            return Ok(vec![self.clone()]);
+        }
+        let metadata = Self::cargo_metadata(opt)?;
+        let from_paths = self
+            .example_paths
+            .iter()
+            .map(|path| Self::example_for_path(&metadata, path))
+            .collect::<Result<Vec<_>, _>>()?;
+        let example_kind: String = "example".to_string();
+        let available: Vec<&str> = metadata
+            .packages
+            .iter()
+            .flat_map(|package| package.targets.iter())
+            .filter(|target| target.kind.contains(&example_kind))
+            .map(|target| target.name.as_str())
+            .collect();
 
-    /// Reveal the resulting archive in the Finder/Exporer
-    #[structopt(long)]
-    reveal: bool,
-}
+        let mut examples: Vec<&str> = Vec::new();
+        for pattern in &self.examples {
+            let glob = glob::Pattern::new(pattern)
+                .with_context(|| format!("Invalid example pattern '{}'", pattern))?;
+            let matches: Vec<&str> = available
+                .iter()
+                .copied()
+                .filter(|name| glob.matches(name))
+                .collect();
+            if matches.is_empty() {
+                bail!(
+                    "no example matches '{}'; available: {}",
+                    pattern,
+                    available.join(", ")
+                );
+            }
+            for name in matches {
+                if !examples.contains(&name) {
+                    examples.push(name);
+                }
+            }
+        }
+        for name in &from_paths {
+            if !examples.contains(&name.as_str()) {
+                examples.push(name);
+            }
+        }
+        info!("examples: {:?}", examples);
+        if self.pdx_name.is_some() && examples.len() > 1 {
+            bail!(
+                "--pdx-name would give {} examples the same pdx: {}",
+                examples.len(),
+                examples.join(", ")
+            );
+        }
+        Ok(examples
+            .into_iter()
+            .map(|name| Build {
+                examples: Vec::new(),
+                example_paths: Vec::new(),
+                example: Some(name.to_string()),
+                ..self.clone()
+            })
+            .collect())
+    }
+
+    /// Finds the example whose source file is `path`, or whose source is directly inside the
+    /// directory `path`, using the source paths cargo reports rather than assuming the
+    /// examples/ layout.
+    fn example_for_path(metadata: &cargo_metadata::Metadata, path: &Path) -> Result<String, Error> {
+        let wanted = path
+            .canonicalize()
+            .with_context(|| format!("Example path {:?} does not exist", path))?;
+        let example_kind: String = "example".to_string();
+        metadata
+            .packages
+            .iter()
+            .flat_map(|package| package.targets.iter())
+            .filter(|target| target.kind.contains(&example_kind))
+            .find(|target| {
+                target.src_path.canonicalize().is_ok_and(|src_path| {
+                    src_path == wanted || src_path.parent() == Some(wanted.as_path())
+                })
+            })
+            .map(|target| target.name.clone())
+            .ok_or_else(|| anyhow!("No example has its source at {:?}", path))
+    }
+
+    /// Runs `cargo clippy` configured like the cargo build, so lints see the same target,
+    /// features and `RUSTFLAGS`.
+    fn run_clippy(&self, opt: &Opt, crank_manifest: &Manifest) -> Result<(), Error> {
+        let metadata = Self::cargo_metadata(opt)?;
+        let (mut command, _) = self.cargo_command("clippy", opt, crank_manifest, &metadata)?;
+        if self.deny_warnings {
+            command.arg("--").arg("-Dwarnings");
+        }
+        info!("clippy command: {}", command_line(&command));
+        // Unlike the other tools, clippy's output is the point of running it, so it is never
+        // held back.
+        let status = command.status().context("Running cargo clippy")?;
+        if !status.success() {
+            bail!("cargo clippy failed with error {:?}", status);
+        }
+        Ok(())
+    }
+
+    /// Only runs the cargo build, skipping linking, assets and pdc.
+    pub fn verify(&self, opt: &Opt, crank_manifest: &Manifest) -> Result<(), Error> {
+        info!("verifying");
+        let build_target = self.cargo_build(opt, crank_manifest)?;
+        println!(
+            "{} compiles for the {}.",
+            build_target.target_name,
+            if self.device { "device" } else { "simulator" }
+        );
+        Ok(())
+    }
+
+    /// The game's title, from the metadata or the target name, and the file name of its pdx.
+    fn game_names(&self, crank_manifest: &Manifest, target_name: &str) -> (String, String) {
+        let metadata = crank_manifest
+            .metadata_for(target_name, self.release)
+            .unwrap_or_default();
+        let title_style = crank_manifest
+            .settings
+            .build
+            .title_style
+            .unwrap_or_default();
+        let game_title = metadata
+            .name
+            .unwrap_or_else(|| title::from_target_name(target_name, title_style));
+        let pdx_name = title::file_name(
+            self.pdx_name
+                .as_deref()
+                .or(metadata.pdx_name.as_deref())
+                .unwrap_or(&game_title),
+        );
+        (game_title, pdx_name)
+    }
+
+    /// `--staging-dir` as given, or else `[build] staging_dir` relative to the project
+    /// directory.
+    fn staging_dir(&self, settings: &Settings, project_path: &Path) -> Option<PathBuf> {
+        self.staging_dir.clone().or_else(|| {
+            settings
+                .build
+                .staging_dir
+                .as_ref()
+                .map(|staging_dir| project_path.join(staging_dir))
+        })
+    }
+
+    /// Regenerates the `pdxinfo` of a pdx that was already built, writing it into the staging
+    /// directory and the pdx itself without building, copying assets or running pdc.
+    pub fn rewrite_manifest(
+        &self,
+        opt: &Opt,
+        crank_manifest: &Manifest,
+    ) -> Result<BuiltPdx, Error> {
+        info!("rewrite_manifest");
+        let metadata = Self::cargo_metadata(opt)?;
+        let (_, build_target) = self.cargo_command("build", opt, crank_manifest, &metadata)?;
+        let target_name = &build_target.target_name;
+        let (game_title, pdx_name) = self.game_names(crank_manifest, target_name);
+        let overall_target_dir = build_target.target_directory.as_path();
+        let dest_path = overall_target_dir.join(format!("{}.pdx", pdx_name));
+        let source_path = self
+            .staging_dir(&crank_manifest.settings, &build_target.project_path)
+            .as_deref()
+            .unwrap_or(overall_target_dir)
+            .join(&pdx_name);
+        if !dest_path.is_dir() || !source_path.is_dir() {
+            bail!(
+                "{:?} hasn't been built yet, so there is no pdxinfo to regenerate. Run crank \
+                package without --manifest-only first.",
+                dest_path
+            );
+        }
+        self.make_manifest(crank_manifest, target_name, &source_path)?;
+        let pdxinfo = source_path.join("pdxinfo");
+        if pdxinfo.is_file() {
+            fs::copy(&pdxinfo, dest_path.join("pdxinfo"))
+                .with_context(|| format!("Copying {:?} into {:?}", pdxinfo, dest_path))?;
+        }
+        // The pdx no longer matches what a device build would have written.
+        fs::remove_file(overall_target_dir.join(format!("{}.pdx.crank-key", pdx_name)))
+            .unwrap_or(());
+        println!("Rewrote the pdxinfo of {}", dest_path.display());
+        Ok(BuiltPdx {
+            path: dest_path,
+            name: pdx_name,
+            game_title,
+            elf: None,
+        })
+    }
+
+    /// Prints the commands a build runs, in order, as a shell script, without running any of
+    /// them. The cargo, gcc and pdc commands come from the builders the build itself uses, but
+    /// the rest is an approximation: the library cargo builds is only known once it has run,
+    /// so its usual location is assumed, and asset copies are shown whole.
+    pub fn explain(&self, opt: &Opt, crank_manifest: &Manifest) -> Result<(), Error> {
+        let settings = &crank_manifest.settings;
+        let metadata = Self::cargo_metadata(opt)?;
+        let (cargo, build_target) = self.cargo_command("build", opt, crank_manifest, &metadata)?;
+        let target_name = &build_target.target_name;
+        let package_name = target_name.replace('-', "_");
+        let (_, pdx_name) = self.game_names(crank_manifest, target_name);
+        let overall_target_dir = build_target.target_directory.as_path();
+        let mut profile_dir = overall_target_dir.to_path_buf();
+        if self.device {
+            profile_dir.push("thumbv7em-none-eabihf");
+        }
+        profile_dir.push(if self.release { "release" } else { "debug" });
+        if self.example.is_some() {
+            profile_dir.push("examples");
+        }
+        let source_path = self
+            .staging_dir(settings, &build_target.project_path)
+            .as_deref()
+            .unwrap_or(overall_target_dir)
+            .join(&pdx_name);
+        let dest_path = overall_target_dir.join(format!("{}.pdx", pdx_name));
+        let quote = |path: &Path| shell_quote(&path.to_string_lossy());
+
+        let mut script = vec![
+            // Device builds pass CARGO_ENCODED_RUSTFLAGS, which needs bash's `$'...'` quoting.
+            "#!/bin/bash".to_string(),
+            format!(
+                "# The commands crank runs for a {} build of {}.",
+                if self.device { "device" } else { "simulator" },
+                target_name
+            ),
+            "# Approximate: the library is assumed to be where cargo usually puts it, and each"
+                .to_string(),
+            "# asset is copied whole, without [build] source_extensions, --since-last-build or"
+                .to_string(),
+            "# the build fingerprint that lets crank skip unchanged steps.".to_string(),
+            "set -e".to_string(),
+            format!("cd {}", quote(&build_target.project_path)),
+        ];
+        let mut step = |comment: &str, commands: Vec<String>| {
+            script.push(String::new());
+            script.push(format!("# {}", comment));
+            script.extend(commands);
+        };
+        step("Build the game with cargo", vec![command_line(&cargo)]);
+        if self.device {
+            let lib_file = profile_dir.join(format!("lib{}.a", package_name));
+            let target_dir = self
+                .staging_dir(settings, &build_target.project_path)
+                .unwrap_or_else(|| profile_dir.clone());
+            step(
+                "Compile the SDK's setup.c",
+                vec![command_line(&Self::setup_command(
+                    settings,
+                    &build_target.project_path,
+                    &target_dir,
+                )?)],
+            );
+            step(
+                "Link it with the game's static library",
+                vec![command_line(&Self::link_command(
+                    settings,
+                    &build_target.project_path,
+                    &target_dir,
+                    &package_name,
+                    &lib_file,
+                )?)],
+            );
+            step(
+                "Stage the binary",
+                vec![
+                    format!("mkdir -p {}", quote(&source_path)),
+                    format!(
+                        "cp {} {}",
+                        quote(&target_dir.join(format!("{}.elf", package_name))),
+                        quote(&source_path.join("pdex.elf"))
+                    ),
+                ],
+            );
+        } else {
+            let dylib = format!(
+                "{}{}.{}",
+                env::consts::DLL_PREFIX,
+                package_name,
+                env::consts::DLL_EXTENSION
+            );
+            step(
+                "Stage the library",
+                vec![
+                    format!("mkdir -p {}", quote(&source_path)),
+                    format!(
+                        "cp {} {}",
+                        quote(&profile_dir.join(dylib)),
+                        quote(&source_path.join(format!("pdex.{}", env::consts::DLL_EXTENSION)))
+                    ),
+                ],
+            );
+        }
+
+        if !self.device && settings.simulator.create_pdex_bin_stub.unwrap_or(true) {
+            step(
+                "Add an empty pdex.bin",
+                vec![format!("touch {}", quote(&source_path.join("pdex.bin")))],
+            );
+        }
+
+        let target = crank_manifest.get_target(target_name);
+        let mut commands = Vec::new();
+        let assets = if self.no_assets {
+            Vec::new()
+        } else {
+            crank_manifest
+                .assets_for(target_name, self.release)
+                .unwrap_or_default()
+        };
+        for asset in assets {
+            let src_path = crank_manifest.dir.join(asset.source());
+            let dst_path = source_path.join(asset.destination());
+            if src_path.is_dir() {
+                commands.push(format!("mkdir -p {}", quote(&dst_path)));
+                commands.push(format!("cp -R {}/. {}", quote(&src_path), quote(&dst_path)));
+            } else {
+                commands.push(format!(
+                    "mkdir -p {}",
+                    quote(dst_path.parent().expect("parent"))
+                ));
+                commands.push(format!("cp {} {}", quote(&src_path), quote(&dst_path)));
+            }
+        }
+        if !commands.is_empty() {
+            step("Copy the assets", commands);
+        }
+        let pdxinfo = source_path.join("pdxinfo");
+        if let Some(pdxinfo_path) = target.and_then(|target| target.pdxinfo_path.as_ref()) {
+            step(
+                "Copy the pdxinfo",
+                vec![format!(
+                    "cp {} {}",
+                    quote(&crank_manifest.dir.join(pdxinfo_path)),
+                    quote(&pdxinfo)
+                )],
+            );
+        } else if let Some(lines) = self.pdxinfo_lines(crank_manifest, target_name) {
+            let lines: Vec<String> = lines.iter().map(|line| shell_quote(line)).collect();
+            step(
+                "Write the pdxinfo",
+                vec![format!(
+                    "printf '%s\\n' {} > {}",
+                    lines.join(" "),
+                    quote(&pdxinfo)
+                )],
+            );
+        }
+        step(
+            "Compile the pdx",
+            vec![command_line(&self.pdc_command(
+                settings,
+                &source_path,
+                &dest_path,
+            )?)],
+        );
+        println!("{}", script.join("\n"));
+        Ok(())
+    }
+
+    pub fn execute(&self, opt: &Opt, crank_manifest: &Manifest) -> Result<BuiltPdx, Error> {
+        info!("building");
+
+        if self.device && self.run && self.fallback_simulator {
+            match device::wait_for_device(
+                self.device_serial.as_deref(),
+                &crank_manifest.settings,
+                FALLBACK_DEVICE_TIMEOUT,
+            ) {
+                Some(true) => info!("device found, running on the device"),
+                Some(false) => {
+                    println!(
+                        "No Playdate found after {:?}, falling back to the simulator.",
+                        FALLBACK_DEVICE_TIMEOUT
+                    );
+                    let simulator_build = Build {
+                        device: false,
+                        fallback_simulator: false,
+                        ..self.clone()
+                    };
+                    return simulator_build.execute(opt, crank_manifest);
+                }
+                None => info!("can't detect a device on this platform, running on the device"),
+            }
+        }
+
+        // The simulator half of `crank package` builds the same example again.
+        if let (Some(example), None) = (self.example.as_deref(), self.device_elf.as_ref()) {
+            if let Some(warning) = crank_manifest.untargeted_example_warning(example) {
+                eprintln!("Warning: {}", warning);
+            }
+        }
+
+        if self.clippy {
+            ui::phase("Running clippy", || self.run_clippy(opt, crank_manifest))?;
+        }
+
+        if self.device && GCC_CHECKED.set(()).is_ok() {
+            for warning in check_gcc(&crank_manifest.settings) {
+                eprintln!("Warning: {}", warning);
+            }
+        }
+
+        let build_target = ui::phase(
+            if self.device {
+                "Building for the device"
+            } else {
+                "Building for the simulator"
+            },
+            || self.cargo_build(opt, crank_manifest),
+        )?;
+        let project_path = build_target.project_path.as_path();
+        let target_name = &build_target.target_name;
+
+        let overall_target_dir = build_target.target_directory.as_path();
+        let (game_title, pdx_name) = self.game_names(crank_manifest, target_name);
+        let package_name = target_name.replace('-', "_");
+        let staging_dir = self.staging_dir(&crank_manifest.settings, project_path);
+        let staging_dir = staging_dir.as_deref();
+        let source_path =
+            self.make_source_dir(staging_dir.unwrap_or(overall_target_dir), &pdx_name)?;
+        let dest_path = overall_target_dir.join(format!("{}.pdx", pdx_name));
+        // Device and simulator builds write the same pdx, so the key lives next to it and is
+        // removed along with it by either kind of build.
+        let key_path = overall_target_dir.join(format!("{}.pdx.crank-key", pdx_name));
+        let device_elf = if self.device {
+            let lib_file = build_target.artifact("a")?;
+            // setup.o and the linked elf go next to the static library unless staged elsewhere.
+            let target_dir = staging_dir.unwrap_or_else(|| lib_file.parent().expect("parent"));
+            let build_key = self.device_build_key(crank_manifest, &build_target, lib_file)?;
+            if !self.force && dest_path.exists() && cache::is_fresh(&key_path, &build_key) {
+                println!("{} is up to date.", dest_path.display());
+            } else {
+                Self::remove_pdx(&dest_path, &key_path);
+                ui::phase("Linking", || {
+                    self.compile_setup(&crank_manifest.settings, project_path, target_dir)?;
+                    self.link_binary(
+                        &crank_manifest.settings,
+                        project_path,
+                        target_dir,
+                        &package_name,
+                        lib_file,
+                    )?;
+                    self.make_binary(target_dir, &package_name, &source_path)
+                })?;
+                ui::phase("Copying assets", || {
+                    self.copy_assets(target_name, crank_manifest, &source_path)?;
+                    self.make_manifest(crank_manifest, target_name, &source_path)
+                })?;
+                ui::phase("Compiling the pdx", || {
+                    self.run_pdc(&crank_manifest.settings, &source_path, &dest_path)
+                })?;
+                cache::store(&key_path, &build_key)?;
+            }
+            if self.emit_checksums {
+                Self::emit_checksums(overall_target_dir, &pdx_name, &dest_path)?;
+            }
+            if let Some(elf_output) = self.elf_output.as_ref() {
+                self.copy_elf_output(target_dir, &package_name, elf_output)?;
+            }
+            if let Some(dir) = self.keep_intermediates.as_ref() {
+                self.keep_intermediates(target_dir, &package_name, &source_path, dir)?;
+            }
+            if self.run {
+                if let Some(timeout) = self.wait_for_device {
+                    self.wait_for_device(
+                        &crank_manifest.settings,
+                        timeout.map_or(WAIT_FOR_DEVICE_TIMEOUT, time::Duration::from_secs),
+                    )?;
+                }
+                if self.reset {
+                    ui::phase("Resetting the device", || {
+                        device::reset_device(
+                            self.device_serial.as_deref(),
+                            &crank_manifest.settings,
+                            WAIT_FOR_DEVICE_TIMEOUT,
+                        )
+                    })?;
+                }
+                ui::phase("Running on the device", || {
+                    if let Some(address) = device::remote_address(
+                        self.device_serial.as_deref(),
+                        &crank_manifest.settings,
+                    ) {
+                        self.run_remote(&crank_manifest.settings, &address, &pdx_name)
+                    } else {
+                        self.run_target(&crank_manifest.settings, &dest_path, &pdx_name)
+                    }
+                })?;
+                if let Some(seconds) = self.profile_device {
+                    self.profile_device(
+                        &crank_manifest.settings,
+                        seconds.map_or(PROFILE_DEVICE_DURATION, time::Duration::from_secs),
+                    )?;
+                }
+                if let Some(record) = self.record.as_ref() {
+                    self.record_device(&crank_manifest.settings, record, &pdx_name)?;
+                }
+            } else if self.profile_device.is_some() {
+                eprintln!("Warning: --profile-device only applies when running, ignoring it.");
+            } else if self.record.is_some() {
+                eprintln!("Warning: --record only applies when running, ignoring it.");
+            }
+            Some(target_dir.join(format!("{}.elf", package_name)))
+        } else {
+            if self.elf_output.is_some() {
+                eprintln!("Warning: --elf-output only applies to device builds, ignoring it.");
+            }
+            if self.keep_intermediates.is_some() {
+                eprintln!(
+                    "Warning: --keep-intermediates only applies to device builds, ignoring it."
+                );
+            }
+            Self::remove_pdx(&dest_path, &key_path);
+            ui::phase("Copying the library", || {
+                self.link_dylib(
+                    &crank_manifest.settings,
+                    build_target.artifact(env::consts::DLL_EXTENSION)?,
+                    &source_path,
+                )
+            })?;
+            ui::phase("Copying assets", || {
+                self.copy_assets(target_name, crank_manifest, &source_path)?;
+                self.make_manifest(crank_manifest, target_name, &source_path)
+            })?;
+            ui::phase("Compiling the pdx", || {
+                self.run_pdc(&crank_manifest.settings, &source_path, &dest_path)
+            })?;
+            if self.emit_checksums {
+                Self::emit_checksums(overall_target_dir, &pdx_name, &dest_path)?;
+            }
+            if self.install_simulator {
+                ui::phase("Installing in the simulator", || {
+                    self.install_simulator(&crank_manifest.settings, &dest_path, &pdx_name)
+                })?;
+            } else if self.run {
+                let settings = &crank_manifest.settings;
+                let follow_log = self.tail_logs || self.record.is_some();
+                let log_path = if follow_log || self.open_logs {
+                    Some(sim_log::path(settings.simulator.log_path.as_ref())?)
+                } else {
+                    None
+                };
+                // Without the log, tailing it would wait for output that never comes.
+                if let Some(log_path) = log_path.as_ref().filter(|_| follow_log) {
+                    if !log_path.is_file() {
+                        bail!(
+                            "The simulator log {} does not exist. Run the simulator once so it \
+                            creates its log, or set [simulator] log_path to where it writes it.",
+                            log_path.display()
+                        );
+                    }
+                }
+                let mut session = match self.record.as_ref() {
+                    Some(record) => Some(record::Session::create(record, &pdx_name, "simulator")?),
+                    None => None,
+                };
+                let tail = log_path.as_ref().filter(|_| follow_log).map(|log_path| {
+                    println!("Showing the simulator log {}", log_path.display());
+                    if let Some(session) = session.as_ref() {
+                        println!("Recording it to {}", session.dir().display());
+                    }
+                    let mut session = session.take();
+                    sim_log::Tail::start(log_path.clone(), move |line| {
+                        println!("{}", line);
+                        if let Some(session) = session.as_mut() {
+                            let _ = session.log_line(line);
+                        }
+                    })
+                });
+                ui::phase("Starting the simulator", || {
+                    self.run_simulator(settings, &dest_path)
+                })?;
+                if let Some(log_path) = log_path.filter(|_| self.open_logs) {
+                    if log_path.is_file() {
+                        sim_log::open(&log_path)?;
+                    } else {
+                        eprintln!(
+                            "Warning: the simulator log {} does not exist. Set [simulator] \
+                            log_path if the simulator writes it elsewhere.",
+                            log_path.display()
+                        );
+                    }
+                }
+                if let Some(tail) = tail {
+                    // `open` returns as soon as the simulator starts, so follow the log until
+                    // interrupted.
+                    if cfg!(target_os = "macos") {
+                        println!("Press Ctrl-C to stop showing the log");
+                        tail.wait();
+                    } else {
+                        tail.stop();
+                    }
+                }
+            }
+            None
+        };
+
+        Ok(BuiltPdx {
+            path: dest_path,
+            name: pdx_name,
+            game_title,
+            elf: device_elf,
+        })
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct Package {
+    /// Build a specific example from the examples/ dir. Can be given more than once, and
+    /// accepts globs such as 'level*' to package each matching example.
+    #[structopt(long = "example")]
+    examples: Vec<String>,
+
+    /// Package the example whose source is this file, or which lives in this directory.
+    #[structopt(long = "example-path")]
+    example_paths: Vec<PathBuf>,
+
+    /// Only package the examples, or without --example the package, that changes since this
+    /// git ref affect, directly or through the workspace packages they depend on.
+    #[structopt(long, value_name = "base-ref")]
+    affected: Option<String>,
+
+    /// Enable build feature flags.
+    #[structopt(long)]
+    features: Vec<String>,
+
+    /// clean before building
+    #[structopt(long)]
+    clean: bool,
+
+    /// Reveal the resulting archive in the Finder/Exporer
+    #[structopt(long)]
+    reveal: bool,
+
+    /// Compression for the archive: stored, deflated, bzip2 or zstd.
+    #[structopt(long)]
+    compression: Option<Compression>,
+
+    /// What the root of the archive holds: none for the files of the pdx, or pdx for the
+    /// .pdx folder itself.
+    #[structopt(long, conflicts_with = "no-zip")]
+    zip_root: Option<ZipRoot>,
+
+    /// Number of parallel jobs for cargo, which defaults to the number of CPUs.
+    #[structopt(short, long)]
+    jobs: Option<u32>,
+
+    /// Rebuild the whole archive instead of only updating the entries that changed.
+    #[structopt(long)]
+    force: bool,
+
+    /// Name the .pdx and .pdx.zip after this instead of the game title.
+    #[structopt(long)]
+    pdx_name: Option<String>,
+
+    /// Refuse to package if the git working tree has uncommitted changes.
+    #[structopt(long)]
+    require_clean: bool,
+
+    /// Package even with uncommitted changes, overriding --require-clean and the
+    /// require_clean setting.
+    #[structopt(long)]
+    allow_dirty: bool,
+
+    /// Leave the .pdx folder instead of writing a .pdx.zip archive.
+    #[structopt(long)]
+    no_zip: bool,
+
+    /// Write the SHA-256 of every file in the pdx to `<pdx name>.checksums.json` next to the
+    /// .pdx.zip.
+    #[structopt(long)]
+    emit_checksums: bool,
+
+    /// Directory to write the archive, or the .pdx folder with --no-zip, to instead of the
+    /// target directory.
+    #[structopt(long)]
+    output_dir: Option<PathBuf>,
+
+    /// Only regenerate the pdxinfo of the pdx already built and package it again, skipping
+    /// the cargo builds, assets and pdc.
+    #[structopt(long, conflicts_with = "clean")]
+    manifest_only: bool,
+}
 
 impl Package {
+    /// With `--require-clean` or `[package] require_clean`, refuses to package a tree with
+    /// uncommitted changes, so the archive matches the commit it reports.
+    fn check_clean(&self, opt: &Opt, settings: &Settings) -> Result<(), Error> {
+        let project_path = match opt.manifest_path.as_ref() {
+            Some(manifest_path) => manifest_path.parent().expect("parent").to_path_buf(),
+            None => env::current_dir()?,
+        };
+        let require_clean = !self.allow_dirty
+            && (self.require_clean || settings.package.require_clean == Some(true));
+        if require_clean {
+            match build_info::uncommitted_changes(&project_path) {
+                None => bail!(
+                    "--require-clean needs a git repository, but {:?} isn't in one",
+                    project_path
+                ),
+                Some(changes) if !changes.is_empty() => bail!(
+                    "Refusing to package with uncommitted changes:\n{}\n\
+                    Commit or stash them, or pass --allow-dirty.",
+                    changes.join("\n")
+                ),
+                Some(_) => {}
+            }
+        }
+        let commit = build_info::git_commit(&project_path);
+        if !commit.is_empty() {
+            println!("Packaging commit {}", commit);
+        }
+        Ok(())
+    }
+
+    pub fn execute(&self, opt: &Opt, crank_manifest: &Manifest) -> Result<(), Error> {
+        if self.clean {
+            info!("cleaning");
+            let manifest_path_str;
+            let mut args = Vec::new();
+            if let Some(manifest_path) = opt.manifest_path.as_ref() {
+                args.push("--manifest-path");
+                manifest_path_str = manifest_path.to_string_lossy();
+                args.push(&manifest_path_str);
+            };
+
+            let status = Command::new("cargo").arg("clean").args(args).status()?;
+            if !status.success() {
+                bail!("cargo failed with error {:?}", status);
+            }
+        }
+        self.check_clean(opt, &crank_manifest.settings)?;
+        let builds = Build {
+            examples: self.examples.clone(),
+            example_paths: self.example_paths.clone(),
+            pdx_name: self.pdx_name.clone(),
+            affected: self.affected.clone(),
+            ..Default::default()
+        }
+        .expand_examples(opt)?;
+        for build in builds {
+            self.package(opt, crank_manifest, build.example)?;
+        }
+        Ok(())
+    }
+
+    fn package(
+        &self,
+        opt: &Opt,
+        crank_manifest: &Manifest,
+        example: Option<String>,
+    ) -> Result<(), Error> {
+        let BuiltPdx {
+            path: target_dir,
+            name: pdx_name,
+            ..
+        } = if self.manifest_only {
+            Build {
+                example,
+                release: true,
+                pdx_name: self.pdx_name.clone(),
+                ..Default::default()
+            }
+            .rewrite_manifest(opt, crank_manifest)?
+        } else {
+            self.build(opt, crank_manifest, example)?
+        };
+        let dylib = format!("pdex.{}", env::consts::DLL_EXTENSION);
+        for binary in ["pdex.bin", dylib.as_str()].iter() {
+            if !target_dir.join(binary).is_file() {
+                bail!(
+                    "{:?} has no {}, so it won't run on both the device and the simulator",
+                    target_dir,
+                    binary
+                );
+            }
+        }
+        let output_dir = match self.output_dir.as_ref() {
+            Some(output_dir) => {
+                fs::create_dir_all(output_dir)
+                    .with_context(|| format!("Creating output directory {:?}", output_dir))?;
+                output_dir.clone()
+            }
+            None => target_dir.parent().expect("parent").to_path_buf(),
+        };
+        info!("target_dir {:#?}", target_dir);
+
+        let output = if self.no_zip {
+            let output_pdx = output_dir.join(format!("{}.pdx", pdx_name));
+            // `--output-dir` may name the pdx's own directory by a relative path or through a
+            // symlink, and removing it then would delete the pdx being packaged.
+            let is_target_dir = match (output_pdx.canonicalize(), target_dir.canonicalize()) {
+                (Ok(output_pdx), Ok(target_dir)) => output_pdx == target_dir,
+                _ => output_pdx == target_dir,
+            };
+            if !is_target_dir {
+                if output_pdx.exists() {
+                    fs::remove_dir_all(&output_pdx)
+                        .with_context(|| format!("Removing previous {:?}", output_pdx))?;
+                }
+                fs::create_dir_all(&output_pdx)
+                    .with_context(|| format!("Creating {:?}", output_pdx))?;
+                Build::copy_directory(&target_dir, &output_pdx)?;
+            }
+            output_pdx
+        } else {
+            let target_archive = output_dir.join(format!("{}.pdx.zip", pdx_name));
+            info!("target_archive {:#?}", target_archive);
+            let compression = self
+                .compression
+                .or(crank_manifest.settings.package.compression)
+                .unwrap_or_default();
+            let zip_root = self
+                .zip_root
+                .or(crank_manifest.settings.package.zip_root)
+                .unwrap_or_default();
+            archive::write_archive(
+                &target_archive,
+                &target_dir,
+                compression.into(),
+                zip_root == ZipRoot::Pdx,
+                self.force,
+            )?;
+            archive::verify_archive(&target_archive)?;
+            target_archive
+        };
+        println!("Packaged {}", output.display());
+        if self.emit_checksums {
+            Build::emit_checksums(&output_dir, &pdx_name, &target_dir)?;
+        }
+        if self.reveal {
+            Self::reveal(&output)?;
+        }
+        Ok(())
+    }
+
+    /// Builds for the device, then for the simulator with the device binary, returning the
+    /// pdx that runs on both.
+    fn build(
+        &self,
+        opt: &Opt,
+        crank_manifest: &Manifest,
+        example: Option<String>,
+    ) -> Result<BuiltPdx, Error> {
+        let device_build = Build {
+            device: true,
+            example: example.clone(),
+            features: self.features.clone(),
+            release: true,
+            jobs: self.jobs,
+            pdx_name: self.pdx_name.clone(),
+            emit_version_module: true,
+            ..Default::default()
+        };
+        let device_pdx = device_build.execute(opt, crank_manifest)?;
+
+        // The simulator build runs pdc on its own binary and the device one together, making
+        // a single pdx that runs on the device and in the simulator.
+        let sim_build = Build {
+            device: false,
+            example,
+            device_elf: device_pdx.elf,
+            features: self.features.clone(),
+            release: true,
+            jobs: self.jobs,
+            pdx_name: self.pdx_name.clone(),
+            emit_version_module: true,
+            ..Default::default()
+        };
+
+        sim_build.execute(opt, crank_manifest)
+    }
+
+    /// Shows `path` in the Finder or Explorer, or opens its folder on Linux.
+    fn reveal(path: &Path) -> Result<(), Error> {
+        #[cfg(windows)]
+        let _ = Command::new("Explorer")
+            .arg(format!("/Select,{}", path.to_string_lossy()))
+            .status()?;
+        #[cfg(target_os = "macos")]
+        let _ = Command::new("open").arg("-R").arg(path).status()?;
+        #[cfg(target_os = "linux")]
+        let _ = Command::new("xdg-open")
+            .arg(path.parent().expect("parent"))
+            .status()?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct Test {
+    #[structopt(flatten)]
+    build: Build,
+
+    /// Seconds to wait for each harness to print its result.
+    #[structopt(long, default_value = "60")]
+    timeout: u64,
+}
+
+impl Test {
+    /// The simulator executable itself rather than the app bundle on macOS, since `open`
+    /// doesn't pass the simulator's output back.
+    fn simulator_executable(settings: &Settings) -> Result<PathBuf, Error> {
+        #[cfg(target_os = "macos")]
+        let executable = playdate_sdk_path(settings)?
+            .join("bin")
+            .join(SIMULATOR_NAME)
+            .join("Contents")
+            .join("MacOS")
+            .join("Playdate Simulator");
+        #[cfg(not(target_os = "macos"))]
+        let executable = match find_in_path(Path::new(SIMULATOR_NAME)) {
+            Some(path) => path,
+            None => playdate_sdk_path(settings)?
+                .join("bin")
+                .join(SIMULATOR_NAME),
+        };
+        Ok(executable)
+    }
+
+    pub fn execute(&self, opt: &Opt, crank_manifest: &Manifest) -> Result<(), Error> {
+        if self.build.device {
+            eprintln!("Warning: crank test only runs in the simulator, ignoring --device.");
+        }
+        let build = if self.build.examples.is_empty() && self.build.example_paths.is_empty() {
+            let harnesses: Vec<String> = crank_manifest
+                .targets
+                .iter()
+                .filter(|target| target.test == Some(true))
+                .map(|target| target.name.clone())
+                .collect();
+            if harnesses.is_empty() {
+                bail!(
+                    "No test harness to run. Pass --example, or set test = true on the \
+                    Crank.toml targets of the harness examples."
+                );
+            }
+            Build {
+                examples: harnesses,
+                ..self.build.clone()
+            }
+        } else {
+            self.build.clone()
+        };
+
+        let timeout = time::Duration::from_secs(self.timeout);
+        let mut failures = Vec::new();
+        let builds = build.expand_examples(opt)?;
+        for build in &builds {
+            let build = Build {
+                device: false,
+                run: false,
+                install_simulator: false,
+                ..build.clone()
+            };
+            let pdx = build.execute(opt, crank_manifest)?;
+            let mut command = Command::new(Self::simulator_executable(&crank_manifest.settings)?);
+            command.arg(&pdx.path);
+            let outcome = harness::run(&mut command, timeout)?;
+            let name = build.example.as_deref().unwrap_or(&pdx.name);
+            match outcome {
+                harness::Outcome::Passed => println!("{}: passed", name),
+                harness::Outcome::Failed => failures.push(format!("{}: failed", name)),
+                harness::Outcome::Exited(status) => failures.push(format!(
+                    "{}: the simulator exited ({}) before the harness printed {} or {}",
+                    name,
+                    status,
+                    harness::PASSED_MARKER,
+                    harness::FAILED_MARKER
+                )),
+                harness::Outcome::TimedOut => {
+                    failures.push(format!("{}: no result after {:?}", name, timeout))
+                }
+            }
+        }
+        if !failures.is_empty() {
+            bail!(
+                "{} of {} test harnesses did not pass:\n{}",
+                failures.len(),
+                builds.len(),
+                failures.join("\n")
+            );
+        }
+        println!("All {} test harnesses passed.", builds.len());
+        Ok(())
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct Exec {
+    #[structopt(flatten)]
+    build: Build,
+
+    /// The command to run after the build, following `--`.
+    #[structopt(last = true, required = true)]
+    command: Vec<String>,
+}
+
+impl Exec {
+    pub fn execute(&self, opt: &Opt, crank_manifest: &Manifest) -> Result<(), Error> {
+        for build in self.build.expand_examples(opt)? {
+            self.exec(opt, crank_manifest, &build)?;
+        }
+        Ok(())
+    }
+
+    fn exec(&self, opt: &Opt, crank_manifest: &Manifest, build: &Build) -> Result<(), Error> {
+        let pdx = build.execute(opt, crank_manifest)?;
+        let zip_path = pdx.path.with_file_name(format!("{}.pdx.zip", pdx.name));
+        let compression = crank_manifest
+            .settings
+            .package
+            .compression
+            .unwrap_or_default();
+        let zip_root = crank_manifest.settings.package.zip_root.unwrap_or_default();
+        archive::write_archive(
+            &zip_path,
+            &pdx.path,
+            compression.into(),
+            zip_root == ZipRoot::Pdx,
+            false,
+        )?;
+
+        let mut command = Command::new(&self.command[0]);
+        command
+            .args(&self.command[1..])
+            .env("CRANK_PDX_PATH", &pdx.path)
+            .env("CRANK_PDX_ZIP", &zip_path)
+            .env("CRANK_GAME_TITLE", &pdx.game_title);
+        info!("exec: {}", command_line(&command));
+        let status = command
+            .status()
+            .with_context(|| format!("Running {:?}", self.command[0]))?;
+        if !status.success() {
+            bail!("{} failed with {}", self.command[0], status);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct EnvCommand {
+    /// Serial device of the Playdate, or `tcp://host:port`, as for `crank run`.
+    #[structopt(long)]
+    device_serial: Option<String>,
+}
+
+impl EnvCommand {
+    fn describe<T>(result: Result<T, Error>, describe: impl FnOnce(T) -> String) -> String {
+        match result {
+            Ok(value) => describe(value),
+            Err(err) => format!("error: {:#}", err),
+        }
+    }
+
+    fn describe_tool(path: Result<PathBuf, Error>) -> String {
+        Self::describe(path, |path| match find_in_path(&path) {
+            Some(found) if found != path => format!("{} ({})", path.display(), found.display()),
+            Some(_) => path.display().to_string(),
+            None => format!("{} (not found)", path.display()),
+        })
+    }
+
+    fn serial_device(&self, settings: &Settings) -> String {
+        let device_serial = self.device_serial.as_deref();
+        if let Some(address) = device::remote_address(device_serial, settings) {
+            return format!("tcp://{} (remote)", address);
+        }
+        #[cfg(unix)]
+        {
+            let path = device::serial_device_path(device_serial, settings);
+            let state = if path.exists() {
+                "connected"
+            } else {
+                "not connected"
+            };
+            format!("{} ({})", path.display(), state)
+        }
+        #[cfg(windows)]
+        {
+            device::configured_device(device_serial, settings)
+                .unwrap_or_else(|| "not configured".to_string())
+        }
+    }
+
+    pub fn execute(&self, opt: &Opt, crank_manifest: &Manifest) -> Result<(), Error> {
+        let settings = &crank_manifest.settings;
+        let project_path = match opt.manifest_path.as_ref() {
+            Some(manifest_path) => manifest_path.parent().expect("parent").to_path_buf(),
+            None => env::current_dir()?,
+        };
+        let mut lines: Vec<(&str, String)> = Vec::new();
+        lines.push(("crank version", env!("CARGO_PKG_VERSION").to_string()));
+        lines.push((
+            "global config",
+            settings::global_config_path()
+                .map(|path| {
+                    let state = if path.exists() { "" } else { " (not present)" };
+                    format!("{}{}", path.display(), state)
+                })
+                .unwrap_or_else(|| "unknown".to_string()),
+        ));
+        lines.push((
+            "sdk",
+            Self::describe(locate_sdk(settings), |(path, source)| {
+                format!("{} (from {})", path.display(), source)
+            }),
+        ));
+        lines.push((
+            "sdk version",
+            Self::describe(playdate_sdk_path(settings), |path| {
+                format!(
+                    "{}{}",
+                    sdk_version(&path).unwrap_or_else(|| "unknown".to_string()),
+                    match settings.toolchain.sdk_version.as_ref() {
+                        Some(_) => format!(" ({})", path.display()),
+                        None => String::new(),
+                    }
+                )
+            }),
+        ));
+        lines.push((
+            "installed sdks",
+            Self::describe(playdate_sdk_path_unversioned(settings), |path| {
+                describe_sdk_versions(&installed_sdks(&path))
+            }),
+        ));
+        lines.push((
+            "c api",
+            Self::describe(playdate_c_api_path(settings, &project_path), |path| {
+                let state = if path.is_dir() { "" } else { " (not found)" };
+                format!("{}{}", path.display(), state)
+            }),
+        ));
+        lines.push(("gcc", Self::describe_tool(Ok(gcc_path(settings)))));
+        let gcc = gcc_path(settings);
+        let gcc_version = gcc::version(&gcc);
+        let gcc_warnings = gcc::check(&gcc, gcc_version.as_deref().ok());
+        lines.push((
+            "gcc version",
+            Self::describe(gcc_version, |version| {
+                let mut description = gcc::format_version(&version);
+                for warning in gcc_warnings {
+                    description.push_str(&format!("\n  warning: {}", warning));
+                }
+                description
+            }),
+        ));
+        lines.push(("pdc", Self::describe_tool(pdc_path(settings))));
+        lines.push(("pdutil", Self::describe_tool(pdutil_path(settings))));
+        lines.push((
+            "simulator",
+            match find_in_path(Path::new(SIMULATOR_NAME)) {
+                Some(path) => path.display().to_string(),
+                None => Self::describe(playdate_sdk_path(settings), |path| {
+                    path.join("bin").join(SIMULATOR_NAME).display().to_string()
+                }),
+            },
+        ));
+        lines.push(("device target", "thumbv7em-none-eabihf".to_string()));
+        lines.push((
+            "device cpu",
+            format!(
+                "{} (fpu {})",
+                settings.toolchain.target_cpu(),
+                settings.toolchain.fpu()
+            ),
+        ));
+        lines.push(("serial device", self.serial_device(settings)));
+        lines.push((
+            "serial settings",
+            Self::describe(
+                device::SerialConfig::from_settings(&settings.device),
+                |config| config.to_string(),
+            ),
+        ));
+        lines.push((
+            "target directory",
+            Self::describe(Build::cargo_metadata(opt), |metadata| {
+                metadata.target_directory.display().to_string()
+            }),
+        ));
+
+        let width = lines.iter().map(|(key, _)| key.len()).max().unwrap_or(0);
+        for (key, value) in lines {
+            println!(
+                "{:width$}  {}",
+                format!("{}:", key),
+                value,
+                width = width + 1
+            );
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct Screenshot {
+    /// Where to write the PNG.
+    #[structopt(parse(from_os_str))]
+    path: PathBuf,
+
+    /// Serial device of the Playdate, or `tcp://host:port` for one behind a serial-over-network
+    /// bridge. Overrides PLAYDATE_SERIAL_DEVICE.
+    #[structopt(long)]
+    device_serial: Option<String>,
+}
+
+impl Screenshot {
+    pub fn execute(&self, crank_manifest: &Manifest) -> Result<(), Error> {
+        let mut console =
+            device::open_console(self.device_serial.as_deref(), &crank_manifest.settings)?;
+        let frame = screenshot::capture(&mut *console)?;
+        screenshot::write_png(&self.path, &frame)?;
+        println!("Saved the screen to {}.", self.path.display());
+        Ok(())
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct ListSdks {}
+
+impl ListSdks {
+    pub fn execute(&self, crank_manifest: &Manifest) -> Result<(), Error> {
+        let settings = &crank_manifest.settings;
+        let default_sdk_path = playdate_sdk_path_unversioned(settings)?;
+        let selected = playdate_sdk_path(settings).ok();
+        let installed = installed_sdks(&default_sdk_path);
+        if installed.is_empty() {
+            println!("No Playdate SDK found at {:?}.", default_sdk_path);
+        }
+        for (version, path) in &installed {
+            println!(
+                "{} {:<12} {}",
+                if selected.as_ref() == Some(path) {
+                    "*"
+                } else {
+                    " "
+                },
+                version.as_deref().unwrap_or("unknown"),
+                path.display()
+            );
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct ListTargets {}
+
+impl ListTargets {
+    fn describe_target(target: &Target) -> String {
+        let mut details = Vec::new();
+        match target.metadata.as_ref() {
+            Some(Metadata {
+                name: Some(name), ..
+            }) => details.push(format!("metadata (name = {:?})", name)),
+            Some(_) => details.push("metadata".to_string()),
+            None => details.push("no metadata".to_string()),
+        }
+        match target.assets.as_ref() {
+            Some(assets) => details.push(format!("{} assets", assets.len())),
+            None => details.push("no assets".to_string()),
+        }
+        if target.default_features == Some(false) {
+            details.push("no default features".to_string());
+        }
+        if let Some(assets) = target.debug_assets.as_ref() {
+            details.push(format!("{} debug assets", assets.len()));
+        }
+        if let Some(assets) = target.release_assets.as_ref() {
+            details.push(format!("{} release assets", assets.len()));
+        }
+        details.join(", ")
+    }
+
     pub fn execute(&self, opt: &Opt, crank_manifest: &Manifest) -> Result<(), Error> {
-        if self.clean {
-            info!("cleaning");
-            let manifest_path_str;
-            let mut args = Vec::new();
-            if let Some(manifest_path) = opt.manifest_path.as_ref() {
-                args.push("--manifest-path");
-                manifest_path_str = manifest_path.to_string_lossy();
-                args.push(&manifest_path_str);
+        let metadata = Build::cargo_metadata(opt)?;
+        let static_lib: String = "staticlib".to_string();
+        let cdylib: String = "cdylib".to_string();
+        let example_kind: String = "example".to_string();
+        let default_target = Build::default().get_target_name(&metadata);
+
+        let mut cargo_targets = Vec::new();
+        println!("Cargo targets:");
+        for package in &metadata.packages {
+            for target in &package.targets {
+                let kind = if target.kind.contains(&example_kind) {
+                    "example".to_string()
+                } else if target.kind.contains(&static_lib) && target.kind.contains(&cdylib) {
+                    "lib".to_string()
+                } else if target.kind.contains(&static_lib) || target.kind.contains(&cdylib) {
+                    println!(
+                        "  {} (lib, package {}): not buildable, crate-type needs both \
+                        \"staticlib\" and \"cdylib\"",
+                        target.name, package.name
+                    );
+                    continue;
+                } else {
+                    continue;
+                };
+                let default_marker =
+                    if kind == "lib" && default_target.as_ref() == Some(&target.name) {
+                        ", default"
+                    } else {
+                        ""
+                    };
+                let crank_target = match crank_manifest.get_target(&target.name) {
+                    Some(crank_target) => format!(
+                        "Crank.toml target {:?}: {}",
+                        crank_target.name,
+                        Self::describe_target(crank_target)
+                    ),
+                    None => "no Crank.toml target, built without metadata or assets".to_string(),
+                };
+                println!(
+                    "  {} ({}, package {}{}) -> {}",
+                    target.name, kind, package.name, default_marker, crank_target
+                );
+                cargo_targets.push(target.name.clone());
+            }
+        }
+        if cargo_targets.is_empty() {
+            println!("  (none)");
+        }
+
+        println!("Crank.toml targets:");
+        for target in &crank_manifest.targets {
+            let matched = if cargo_targets.contains(&target.name) {
+                ""
+            } else {
+                " (no matching cargo target, never applied)"
             };
+            println!(
+                "  {}: {}{}",
+                target.name,
+                Self::describe_target(target),
+                matched
+            );
+        }
+        if crank_manifest.targets.is_empty() {
+            println!("  (none)");
+        }
+        Ok(())
+    }
+}
 
-            let status = Command::new("cargo").arg("clean").args(args).status()?;
-            if !status.success() {
-                bail!("cargo failed with error {:?}", status);
+#[derive(Debug, StructOpt)]
+struct Diff {
+    /// The earlier archive, such as the last release.
+    #[structopt(parse(from_os_str))]
+    old: PathBuf,
+
+    /// The newer archive.
+    #[structopt(parse(from_os_str))]
+    new: PathBuf,
+}
+
+impl Diff {
+    fn describe_file(file: &archive::ArchivedFile) -> String {
+        format!("{} bytes, crc32 {:08x}", file.size, file.crc32)
+    }
+
+    pub fn execute(&self) -> Result<(), Error> {
+        let old = archive::read_contents(&self.old)?;
+        let new = archive::read_contents(&self.new)?;
+        let diff = diff::diff(&old, &new);
+
+        let count = |wanted: fn(&diff::FileChange) -> bool| {
+            diff.files
+                .iter()
+                .filter(|(_, change)| wanted(change))
+                .count()
+        };
+        println!(
+            "Files: {} added, {} removed, {} changed, {} unchanged",
+            count(|change| matches!(change, diff::FileChange::Added(_))),
+            count(|change| matches!(change, diff::FileChange::Removed(_))),
+            count(|change| matches!(change, diff::FileChange::Changed { .. })),
+            diff.unchanged
+        );
+        for (path, change) in &diff.files {
+            match change {
+                diff::FileChange::Added(file) => {
+                    println!("  + {} ({})", path, Self::describe_file(file))
+                }
+                diff::FileChange::Removed(file) => {
+                    println!("  - {} ({})", path, Self::describe_file(file))
+                }
+                diff::FileChange::Changed { old, new } => println!(
+                    "  ~ {} ({} -> {})",
+                    path,
+                    Self::describe_file(old),
+                    Self::describe_file(new)
+                ),
             }
         }
-        let device_build = Build {
+
+        if diff.metadata.is_empty() {
+            println!("pdxinfo: unchanged");
+        } else {
+            println!("pdxinfo:");
+        }
+        for change in &diff.metadata {
+            match (&change.old, &change.new) {
+                (Some(old), Some(new)) => println!("  ~ {}: {} -> {}", change.key, old, new),
+                (Some(old), None) => println!("  - {}: {}", change.key, old),
+                (None, Some(new)) => println!("  + {}: {}", change.key, new),
+                (None, None) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct Info {
+    /// The .pdx folder or .pdx.zip archive to inspect.
+    #[structopt(parse(from_os_str))]
+    pdx: PathBuf,
+}
+
+impl Info {
+    pub fn execute(&self) -> Result<(), Error> {
+        let contents = archive::read_pdx(&self.pdx)?;
+        println!("{}", self.pdx.display());
+
+        match contents.pdxinfo.as_deref() {
+            Some(pdxinfo) => {
+                println!("pdxinfo:");
+                for line in pdxinfo.lines().filter(|line| !line.trim().is_empty()) {
+                    println!("  {}", line);
+                }
+            }
+            None => println!("pdxinfo: missing"),
+        }
+
+        let binary = |name: &str| contents.files.get(name).filter(|file| file.size > 0);
+        // Simulator builds carry an empty pdex.bin unless turned off, which isn't a binary.
+        match binary("pdex.bin") {
+            Some(_) => println!("device binary:     pdex.bin"),
+            None => println!("device binary:     none"),
+        }
+        let libraries: Vec<&str> = archive::PDEX_ENTRIES
+            .iter()
+            .copied()
+            .filter(|name| *name != "pdex.bin" && binary(name).is_some())
+            .collect();
+        if libraries.is_empty() {
+            println!("simulator binary:  none");
+        } else {
+            println!("simulator binary:  {}", libraries.join(", "));
+        }
+
+        println!("files:");
+        let width = contents
+            .files
+            .values()
+            .map(|file| file.size.to_string().len())
+            .max()
+            .unwrap_or(0);
+        for (path, file) in &contents.files {
+            println!("  {:>width$}  {}", file.size, path, width = width);
+        }
+        println!(
+            "total: {} files, {} bytes",
+            contents.files.len(),
+            contents.files.values().map(|file| file.size).sum::<u64>()
+        );
+        Ok(())
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct Open {
+    /// The .pdx folder to open, such as a downloaded game or one built earlier.
+    #[structopt(parse(from_os_str))]
+    pdx: PathBuf,
+}
+
+#[derive(Debug, StructOpt)]
+struct Deploy {
+    /// .pdx folders built earlier to copy to the device.
+    #[structopt(parse(from_os_str))]
+    pdxs: Vec<PathBuf>,
+
+    /// Also build every example for the device and copy its pdx.
+    #[structopt(long)]
+    all: bool,
+
+    /// Run this pdx, by folder name with or without .pdx, once the copying is done.
+    #[structopt(long, value_name = "pdx")]
+    launch: Option<String>,
+
+    /// How to build the examples picked with --example or --all. The options about running
+    /// a single build don't apply and are rejected.
+    #[structopt(flatten)]
+    build: Build,
+}
+
+impl Deploy {
+    /// Fails if any of the `crank build` and `crank run` options that mean nothing to a
+    /// deploy were given.
+    fn check_build_options(&self) -> Result<(), Error> {
+        let build = &self.build;
+        let unused: Vec<&str> = [
+            ("--device", build.device),
+            ("--run", build.run),
+            ("--fallback-simulator", build.fallback_simulator),
+            ("--install-simulator", build.install_simulator),
+            ("--tail-logs", build.tail_logs),
+            ("--open-logs", build.open_logs),
+            ("--clippy", build.clippy),
+            ("--profile-device", build.profile_device.is_some()),
+            ("--record", build.record.is_some()),
+            ("--record-screenshots", build.record_screenshots.is_some()),
+        ]
+        .iter()
+        .filter(|(_, given)| *given)
+        .map(|(name, _)| *name)
+        .collect();
+        if !unused.is_empty() {
+            bail!(
+                "crank deploy always builds for the device and only copies, so it doesn't take {}",
+                unused.join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    pub fn execute(&self, opt: &Opt, crank_manifest: &Manifest) -> Result<(), Error> {
+        self.check_build_options()?;
+        let settings = &crank_manifest.settings;
+        if let Some(address) = device::remote_address(self.build.device_serial.as_deref(), settings)
+        {
+            bail!(
+                "{} is a remote device, whose data disk can't be mounted to copy to",
+                address
+            );
+        }
+        let mut pdx_dirs = Vec::new();
+        for pdx in &self.pdxs {
+            if !pdx.is_dir() || pdx.extension() != Some("pdx".as_ref()) {
+                bail!("{:?} is not a .pdx folder", pdx);
+            }
+            pdx_dirs.push(pdx.clone());
+        }
+        let build = Build {
             device: true,
-            example: self.example.clone(),
-            features: self.features.clone(),
-            release: true,
             run: false,
+            examples: if self.all {
+                vec!["*".to_string()]
+            } else {
+                self.build.examples.clone()
+            },
+            ..self.build.clone()
         };
-        device_build.execute(opt, crank_manifest)?;
-
-        let sim_build = Build {
-            device: false,
-            example: self.example.clone(),
-            features: self.features.clone(),
-            release: true,
-            run: false,
+        if !build.examples.is_empty() || !build.example_paths.is_empty() {
+            for build in build.expand_examples(opt)? {
+                pdx_dirs.push(build.execute(opt, crank_manifest)?.path);
+            }
+        }
+        if pdx_dirs.is_empty() {
+            bail!("Nothing to deploy. Pass .pdx folders, --example or --all.");
+        }
+        let launch = match self.launch.as_ref() {
+            Some(launch) => {
+                let folder = if launch.ends_with(".pdx") {
+                    launch.clone()
+                } else {
+                    format!("{}.pdx", launch)
+                };
+                if !pdx_dirs
+                    .iter()
+                    .any(|pdx| pdx.file_name() == Some(folder.as_ref()))
+                {
+                    bail!(
+                        "--launch {} is not one of the pdx folders being deployed",
+                        launch
+                    );
+                }
+                Some(folder)
+            }
+            None => None,
         };
-
-        let (target_dir, game_title) = sim_build.execute(opt, crank_manifest)?;
-        let parent = target_dir.parent().expect("parent");
-        let target_archive = parent.join(format!("{}.pdx.zip", game_title));
-        info!("target_dir {:#?}", target_dir);
-        info!("target_archive {:#?}", target_archive);
-        fs::remove_dir_all(&target_archive).unwrap_or_else(|_err| ());
-        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
-        zip_create_from_directory_with_options(&target_archive, &target_dir, options)?;
-        #[cfg(windows)]
-        if self.reveal {
-            let _ = Command::new("Explorer")
-                .arg(format!("/Select,{}", target_archive.to_string_lossy()))
-                .status()?;
+        if let Some(timeout) = build.wait_for_device {
+            build.wait_for_device(
+                settings,
+                timeout.map_or(WAIT_FOR_DEVICE_TIMEOUT, time::Duration::from_secs),
+            )?;
         }
-        #[cfg(target_os = "macos")]
-        if self.reveal {
-            let _ = Command::new("open")
-                .arg("-R")
-                .arg(target_archive)
-                .status()?;
+        ui::phase("Copying to the device", || {
+            build.install_on_device(settings, &pdx_dirs, launch.as_deref())
+        })
+    }
+}
+
+impl Open {
+    pub fn execute(&self, crank_manifest: &Manifest) -> Result<(), Error> {
+        if !self.pdx.is_dir() {
+            bail!(
+                "{:?} is not a .pdx folder; the simulator can't open archives, so unzip a \
+                .pdx.zip first",
+                self.pdx
+            );
         }
-        #[cfg(target_os = "linux")]
-        if self.reveal {
-            let _ = Command::new("xdg-open").arg(parent).status()?;
+        if !self.pdx.join("pdxinfo").is_file() {
+            eprintln!(
+                "Warning: {:?} has no pdxinfo, so it may not be a pdx.",
+                self.pdx
+            );
         }
-        Ok(())
+        ui::phase("Starting the simulator", || {
+            Build::default().run_simulator(&crank_manifest.settings, &self.pdx)
+        })
     }
 }
 
 #[derive(StructOpt, Debug)]
 #[structopt(name = "crank")]
 struct Opt {
-    #[structopt(short, long)]
+    /// Show the log of crank and the libraries it uses at the info level, and tool output as
+    /// it comes
+    #[structopt(short, long, global = true)]
     verbose: bool,
 
-    /// Path to Cargo.toml
+    /// Turn off all logging, of crank and the libraries it uses, even with RUST_LOG set,
+    /// except what --log-filter asks for
+    #[structopt(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Log filter directives like RUST_LOG's, such as crank=debug or crank::device=info,
+    /// applied over the level set by --verbose or --quiet
+    #[structopt(long, global = true, value_name = "spec")]
+    log_filter: Option<String>,
+
+    /// Print tool output as it comes instead of a summary of each build phase
+    #[structopt(long, global = true)]
+    plain: bool,
+
+    /// When to color the output: auto, always or never
+    #[structopt(long, global = true, default_value = "auto")]
+    color: ui::ColorChoice,
+
+    /// Path to Cargo.toml, or to the directory containing it
     #[structopt(long, global = true)]
     manifest_path: Option<PathBuf>,
 
+    /// Path to the Crank.toml, when it isn't next to Cargo.toml. Asset paths are relative to
+    /// its directory; [toolchain] paths stay relative to the project
+    #[structopt(long, global = true)]
+    crank_manifest: Option<PathBuf>,
+
+    /// Use the Playdate SDK installed as PlaydateSDK-<version> next to the default SDK
+    #[structopt(long, global = true)]
+    sdk_version: Option<String>,
+
+    /// Build the flavor of this name from Crank.toml, with its features, metadata and assets
+    #[structopt(long, global = true)]
+    flavor: Option<String>,
+
+    /// Run cargo, gcc and pdc at a reduced priority, so that builds don't slow down other work
+    #[structopt(long, global = true)]
+    low_priority: bool,
+
+    /// Ring the bell and show a desktop notification with the result and duration when the
+    /// command finishes
+    #[structopt(long, global = true)]
+    notify: bool,
+
     #[structopt(subcommand)]
     cmd: CrankCommand,
 }
 
-fn main() -> Result<(), Error> {
-    let opt = Opt::from_args();
+/// Turns a `--manifest-path` that names a directory into the path of the Cargo.toml inside
+/// it, so the rest of crank can rely on the parent being the project directory.
+fn normalize_manifest_path(manifest_path: Option<PathBuf>) -> Result<Option<PathBuf>, Error> {
+    let manifest_path = match manifest_path {
+        Some(manifest_path) => manifest_path,
+        None => return Ok(None),
+    };
+    if manifest_path.is_dir() {
+        let cargo_toml = manifest_path.join("Cargo.toml");
+        if !cargo_toml.is_file() {
+            bail!(
+                "--manifest-path {:?} is a directory without a Cargo.toml",
+                manifest_path
+            );
+        }
+        Ok(Some(cargo_toml))
+    } else if manifest_path.is_file() {
+        Ok(Some(manifest_path))
+    } else {
+        bail!("--manifest-path {:?} does not exist", manifest_path);
+    }
+}
 
+/// Sets up the logger like `pretty_env_logger::init`, but with the colors chosen by `--color`
+/// rather than by whether stderr is a terminal. The filters are layered: `RUST_LOG`, which
+/// `--quiet` ignores, then the level chosen by `--verbose` or `--quiet`, then `--log-filter`.
+fn init_logger(opt: &Opt) {
+    let mut builder = pretty_env_logger::formatted_builder();
+    if let (Ok(filters), false) = (env::var("RUST_LOG"), opt.quiet) {
+        builder.parse_filters(&filters);
+    }
     if opt.verbose {
-        env::set_var("RUST_LOG", "info");
+        builder.filter_level(LevelFilter::Info);
+    } else if opt.quiet {
+        builder.filter_level(LevelFilter::Off);
     }
+    if let Some(filters) = opt.log_filter.as_ref() {
+        builder.parse_filters(filters);
+    }
+    builder.write_style(if ui::color() {
+        WriteStyle::Always
+    } else {
+        WriteStyle::Never
+    });
+    builder.init();
+}
+
+fn main() -> Result<(), Error> {
+    let mut opt = Opt::from_args();
+
+    opt.manifest_path = normalize_manifest_path(opt.manifest_path.take())?;
+    let project_dir = match opt.manifest_path.as_ref().and_then(|path| path.parent()) {
+        Some(dir) => dir.to_path_buf(),
+        None => env::current_dir()?,
+    };
+    let dotenv_loaded = dotenv::load(&project_dir)?;
 
-    pretty_env_logger::init();
+    ui::init(
+        opt.verbose || opt.log_filter.is_some(),
+        opt.plain,
+        opt.color,
+    );
+    priority::init(opt.low_priority);
+    init_logger(&opt);
 
     info!("starting");
+    if !dotenv_loaded.is_empty() {
+        info!("loaded from .env: {}", dotenv_loaded.join(", "));
+    }
 
-    let crank_manifest = load_manifest(&opt.manifest_path)?;
+    let mut crank_manifest = load_manifest(&opt.manifest_path, opt.crank_manifest.as_deref())?;
+    if let Some(sdk_version) = opt.sdk_version.as_ref() {
+        crank_manifest.settings.toolchain.sdk_version = Some(sdk_version.clone());
+    }
+    if let Some(flavor) = opt.flavor.as_ref() {
+        crank_manifest.select_flavor(flavor)?;
+    }
 
     info!("manifest = {:#?}", crank_manifest);
 
+    let start = time::Instant::now();
+    let result = run_command(&opt, &crank_manifest);
+    if opt.notify {
+        let (title, message) = notify::completion_message(&result, start.elapsed());
+        notify::send(&title, &message);
+    }
+    result
+}
+
+fn run_command(opt: &Opt, crank_manifest: &Manifest) -> Result<(), Error> {
     match &opt.cmd {
         CrankCommand::Build(build) => {
-            build.execute(&opt, &crank_manifest)?;
+            for build in build.expand_examples(opt)? {
+                build.execute(opt, crank_manifest)?;
+            }
         }
         CrankCommand::Run(build) => {
+            if build.affected.is_some() {
+                bail!("--affected only applies to commands that build several targets");
+            }
+            let mut builds = build.expand_examples(opt)?;
+            if builds.len() > 1 {
+                bail!(
+                    "crank run can only run one example at a time, but {} match: {}",
+                    builds.len(),
+                    builds
+                        .iter()
+                        .filter_map(|build| build.example.as_deref())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
             let build_and_run = Build {
                 run: true,
-                ..build.clone()
+                ..builds.remove(0)
             };
-            build_and_run.execute(&opt, &crank_manifest)?;
+            build_and_run.execute(opt, crank_manifest)?;
         }
         CrankCommand::Package(package) => {
-            package.execute(&opt, &crank_manifest)?;
+            package.execute(opt, crank_manifest)?;
+        }
+        CrankCommand::Verify(build) => {
+            for build in build.expand_examples(opt)? {
+                build.verify(opt, crank_manifest)?;
+            }
+        }
+        CrankCommand::Exec(exec) => {
+            exec.execute(opt, crank_manifest)?;
+        }
+        CrankCommand::Test(test) => {
+            test.execute(opt, crank_manifest)?;
+        }
+        CrankCommand::Env(env_command) => {
+            env_command.execute(opt, crank_manifest)?;
+        }
+        CrankCommand::Screenshot(screenshot) => {
+            screenshot.execute(crank_manifest)?;
+        }
+        CrankCommand::ListSdks(list_sdks) => {
+            list_sdks.execute(crank_manifest)?;
+        }
+        CrankCommand::ListTargets(list_targets) => {
+            list_targets.execute(opt, crank_manifest)?;
+        }
+        CrankCommand::Diff(diff) => {
+            diff.execute()?;
+        }
+        CrankCommand::Explain(build) => {
+            if build.affected.is_some() {
+                bail!("--affected only applies to commands that build several targets");
+            }
+            let mut builds = build.expand_examples(opt)?;
+            if builds.len() > 1 {
+                bail!(
+                    "crank explain can only explain one example at a time, but {} match",
+                    builds.len()
+                );
+            }
+            builds.remove(0).explain(opt, crank_manifest)?;
+        }
+        CrankCommand::Deploy(deploy) => {
+            deploy.execute(opt, crank_manifest)?;
+        }
+        CrankCommand::Open(open) => {
+            open.execute(crank_manifest)?;
+        }
+        CrankCommand::Info(info) => {
+            info.execute()?;
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn quotes_command_lines() {
+        let mut command = Command::new("cargo");
+        command
+            .env("RUSTFLAGS", "-Cpanic=abort -Crelocation-model=pic")
+            .args(["build", "--features=a,b", "it's"]);
+        assert_eq!(
+            command_line(&command),
+            "RUSTFLAGS='-Cpanic=abort -Crelocation-model=pic' cargo build --features=a,b 'it'\\''s'"
+        );
+        assert_eq!(
+            shell_quote("-Cpanic=abort\x1f-Clink-args=-T 'a b'"),
+            "$'-Cpanic=abort\\x1f-Clink-args=-T \\'a b\\''"
+        );
+    }
+
+    #[test]
+    fn source_dir_replaces_stray_file() {
+        let dir = TestDir::new("source-dir");
+        fs::write(dir.join("My Game"), "stray").unwrap();
+
+        let source_dir = Build::default().make_source_dir(&dir, "My Game").unwrap();
+        assert_eq!(source_dir, dir.join("My Game"));
+        assert!(source_dir.is_dir());
+    }
+
+    #[test]
+    fn vendored_c_api() {
+        let dir = TestDir::new("c-api");
+        let mut settings = Settings::default();
+        settings.toolchain.c_api_path = Some(PathBuf::from("vendor/C_API"));
+        assert!(playdate_c_api_path(&settings, &dir).is_err());
+
+        fs::create_dir_all(dir.join("vendor/C_API/buildsupport")).unwrap();
+        assert_eq!(
+            Build::setup_path(&settings, &dir).unwrap(),
+            dir.join("vendor/C_API/buildsupport/setup.c")
+        );
+        assert_eq!(
+            Build::link_map_path(&settings, &dir).unwrap(),
+            dir.join("vendor/C_API/buildsupport/link_map.ld")
+        );
+    }
+
+    #[test]
+    fn custom_link_map() {
+        let dir = TestDir::new("link-map");
+        fs::create_dir_all(dir.join("c")).unwrap();
+        let mut settings = Settings::default();
+        settings.toolchain.link_map = Some(PathBuf::from("c/link_map.ld"));
+        assert!(Build::link_map_path(&settings, &dir).is_err());
+
+        fs::write(dir.join("c").join("link_map.ld"), "SECTIONS {}").unwrap();
+        assert_eq!(
+            Build::link_map_path(&settings, &dir).unwrap(),
+            dir.join("c/link_map.ld")
+        );
+    }
+
+    #[test]
+    fn keeps_link_intermediates() {
+        let dir = TestDir::new("intermediates");
+        let target_dir = dir.join("target");
+        let source_dir = dir.join("Game");
+        fs::create_dir_all(&target_dir).unwrap();
+        fs::create_dir_all(&source_dir).unwrap();
+        fs::write(target_dir.join("setup.o"), "object").unwrap();
+        fs::write(target_dir.join("game.elf"), "linked").unwrap();
+        fs::write(source_dir.join("pdex.elf"), "linked").unwrap();
+
+        let kept = dir.join("kept");
+        Build::default()
+            .keep_intermediates(&target_dir, "game", &source_dir, &kept)
+            .unwrap();
+        for name in ["setup.o", "game.elf", "pdex.elf"].iter() {
+            assert!(kept.join(name).is_file(), "{} was not kept", name);
+        }
+    }
+
+    #[test]
+    fn source_dir_rejects_separators() {
+        let test_dir = TestDir::new("source-sep");
+        let dir = test_dir.join("target");
+        let build = Build::default();
+        assert!(build.make_source_dir(&dir, "../escape").is_err());
+        assert!(build.make_source_dir(&dir, "a/b").is_err());
+        assert!(build.make_source_dir(&dir, "..").is_err());
+        assert!(!dir.exists());
+
+        let title = title::file_name("AC/DC: The Game");
+        assert!(build.make_source_dir(&dir, &title).unwrap().is_dir());
+    }
+
+    #[test]
+    fn deploy_rejects_run_options() {
+        let deploy = |args: &[&str]| match Opt::from_iter(args).cmd {
+            CrankCommand::Deploy(deploy) => deploy,
+            _ => unreachable!(),
+        };
+        assert!(deploy(&["crank", "deploy", "--all", "--release"])
+            .check_build_options()
+            .is_ok());
+        let error = deploy(&["crank", "deploy", "--all", "--run", "--clippy"])
+            .check_build_options()
+            .unwrap_err()
+            .to_string();
+        assert!(error.ends_with("doesn't take --run, --clippy"), "{}", error);
+    }
+
+    #[test]
+    fn resolves_features() {
+        let manifest_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("fixtures")
+            .join("workspace")
+            .join("game")
+            .join("Cargo.toml");
+        let metadata = cargo_metadata::MetadataCommand::new()
+            .manifest_path(manifest_path)
+            .no_deps()
+            .exec()
+            .unwrap();
+        let mut crank_manifest: Manifest = toml::from_str(
+            r#"
+            [[flavor]]
+            name = "demo"
+            features = ["debug-overlay"]
+            "#,
+        )
+        .unwrap();
+        crank_manifest.select_flavor("demo").unwrap();
+        let build = |features: &[&str]| Build {
+            features: features.iter().map(|feature| feature.to_string()).collect(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            build(&["sound,debug-overlay", "sound  default", "engine/std"])
+                .resolve_features(&metadata, &crank_manifest)
+                .unwrap(),
+            vec!["sound", "debug-overlay", "default", "engine/std"]
+        );
+        let error = build(&["sound", "sonud"])
+            .resolve_features(&metadata, &crank_manifest)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            error,
+            "unknown feature 'sonud'; available: debug-overlay, sound"
+        );
+    }
+
+    #[test]
+    fn example_with_custom_path() {
+        let workspace = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("fixtures")
+            .join("workspace");
+        let metadata = cargo_metadata::MetadataCommand::new()
+            .manifest_path(workspace.join("Cargo.toml"))
+            .no_deps()
+            .exec()
+            .unwrap();
+        let boss = workspace.join("game").join("levels").join("boss");
+
+        let by_file = Build::example_for_path(&metadata, &boss.join("main.rs")).unwrap();
+        assert_eq!(by_file, "boss");
+        assert_eq!(Build::example_for_path(&metadata, &boss).unwrap(), "boss");
+        let level1 = workspace.join("game").join("examples").join("level1.rs");
+        assert_eq!(
+            Build::example_for_path(&metadata, &level1).unwrap(),
+            "level1"
+        );
+        assert!(Build::example_for_path(&metadata, &workspace.join("game")).is_err());
+    }
+
+    #[test]
+    fn assets_relative_to_crank_toml() {
+        let manifest_path = Path::new("tests")
+            .join("fixtures")
+            .join("workspace")
+            .join("game")
+            .join("Cargo.toml");
+        let crank_manifest = load_manifest(&Some(manifest_path), None).unwrap();
+        let dir = TestDir::new("asset-root");
+        let dest = dir.join("Game");
+        fs::create_dir_all(&dest).unwrap();
+
+        Build::default()
+            .copy_assets("game", &crank_manifest, &dest)
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(dest.join("images").join("player.txt")).unwrap(),
+            "player\n"
+        );
+    }
+
+    #[test]
+    fn assets_relative_to_crank_manifest() {
+        let manifest_path = Path::new("tests")
+            .join("fixtures")
+            .join("workspace")
+            .join("game")
+            .join("Cargo.toml");
+        let dir = TestDir::new("crank-manifest");
+        let config = dir.join("config");
+        fs::create_dir_all(config.join("art")).unwrap();
+        fs::write(config.join("art").join("title.txt"), "title\n").unwrap();
+        fs::write(config.join("assets.list"), "art\n").unwrap();
+        let crank_toml = config.join("Crank.toml");
+        fs::write(
+            &crank_toml,
+            "[[target]]\nname = \"game\"\nassets_file = \"assets.list\"\n",
+        )
+        .unwrap();
+        let crank_manifest =
+            load_manifest(&Some(manifest_path.clone()), Some(&crank_toml)).unwrap();
+        assert_eq!(crank_manifest.dir, config);
+        let dest = dir.join("Game");
+        fs::create_dir_all(&dest).unwrap();
+
+        Build::default()
+            .copy_assets("game", &crank_manifest, &dest)
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(dest.join("art").join("title.txt")).unwrap(),
+            "title\n"
+        );
+        assert!(!dest.join("images").exists());
+        assert!(load_manifest(&Some(manifest_path), Some(&dir.join("Missing.toml"))).is_err());
+    }
+
+    #[test]
+    fn copies_pdxinfo_path() {
+        let dir = TestDir::new("pdxinfo-path");
+        let staging = dir.join("staging");
+        fs::create_dir_all(&staging).unwrap();
+        fs::write(dir.join("pdxinfo.in"), "name=Generated\nbuildNumber=7\n").unwrap();
+        let mut crank_manifest: Manifest = toml::from_str(
+            r#"
+            [[target]]
+            name = "game"
+            pdxinfo_path = "pdxinfo.in"
+
+            [target.metadata]
+            name = "Ignored"
+            "#,
+        )
+        .unwrap();
+        crank_manifest.dir = dir.to_path_buf();
+
+        Build::default()
+            .make_manifest(&crank_manifest, "game", &staging)
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(staging.join("pdxinfo")).unwrap(),
+            "name=Generated\nbuildNumber=7\n"
+        );
+    }
+
+    #[test]
+    fn example_in_workspace_member() {
+        let manifest_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("fixtures")
+            .join("workspace")
+            .join("Cargo.toml");
+        let opt = Opt {
+            verbose: false,
+            quiet: false,
+            log_filter: None,
+            plain: false,
+            color: ui::ColorChoice::Never,
+            manifest_path: Some(manifest_path),
+            crank_manifest: None,
+            sdk_version: None,
+            flavor: None,
+            low_priority: false,
+            notify: false,
+            cmd: CrankCommand::ListTargets(ListTargets {}),
+        };
+        let build = Build {
+            example: Some("level1".to_string()),
+            ..Default::default()
+        };
+        let crank_manifest = Manifest::default();
+
+        let build_target = build.cargo_build(&opt, &crank_manifest).unwrap();
+        assert_eq!(build_target.target_name, "level1");
+        let lib_path = build_target.artifact(env::consts::DLL_EXTENSION).unwrap();
+        assert!(lib_path.is_file());
+        assert!(lib_path.starts_with(build_target.target_directory.join("debug").join("examples")));
+    }
+}
@@ -0,0 +1,945 @@
+use crate::{assets::Asset, settings, settings::Settings, title};
+use anyhow::{anyhow, bail, Context, Error};
+use log::debug;
+use serde_derive::Deserialize;
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+pub type Assets = Vec<Asset>;
+
+/// Placeholder in `bundle_id` that is replaced with the build's bundle suffix.
+pub const BUNDLE_SUFFIX_PLACEHOLDER: &str = "{suffix}";
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Metadata {
+    pub name: Option<String>,
+    pub author: Option<String>,
+    pub description: Option<String>,
+    pub bundle_id: Option<String>,
+    pub version: Option<String>,
+    pub build_number: Option<u64>,
+    pub image_path: Option<String>,
+    pub launch_sound_path: Option<String>,
+    /// File name for the `.pdx`, `.pdx.zip` and staging directory, instead of one derived from
+    /// `name`. Not written to pdxinfo.
+    pub pdx_name: Option<String>,
+    /// Overrides applied only to debug builds, such as a `-dev` version.
+    pub debug: Option<Box<Metadata>>,
+    /// Overrides applied only to release builds.
+    pub release: Option<Box<Metadata>>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Target {
+    pub name: String,
+    pub assets: Option<Assets>,
+    /// File listing more assets, one pattern per line, added after `assets`.
+    pub assets_file: Option<PathBuf>,
+    /// Assets only included in debug builds, such as test levels.
+    pub debug_assets: Option<Assets>,
+    /// Assets only included in release builds.
+    pub release_assets: Option<Assets>,
+    /// Whether to build with the package's default features, `true` unless set.
+    pub default_features: Option<bool>,
+    /// Marks an example as a test harness for `crank test`.
+    pub test: Option<bool>,
+    /// A pdxinfo, relative to the directory of the Crank.toml, to copy into the pdx as it is
+    /// instead of generating one from the metadata.
+    pub pdxinfo_path: Option<PathBuf>,
+    pub metadata: Option<Metadata>,
+}
+
+impl Target {
+    /// The common assets followed by those for the chosen profile.
+    pub fn assets_for(&self, release: bool) -> Assets {
+        let profile_assets = if release {
+            &self.release_assets
+        } else {
+            &self.debug_assets
+        };
+        self.assets
+            .iter()
+            .chain(profile_assets.iter())
+            .flatten()
+            .cloned()
+            .collect()
+    }
+}
+
+/// A named set of features, metadata and assets that make up one variant of the game, such as
+/// a free and a paid version, chosen with `--flavor`.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Flavor {
+    pub name: String,
+    /// Features enabled along with any given with `--features`.
+    pub features: Option<Vec<String>>,
+    /// Put in front of the profile's bundle id suffix, so `.free` gives `.free.debug` in debug
+    /// builds. `--bundle-suffix` still replaces the whole suffix.
+    pub bundle_suffix: Option<String>,
+    /// Assets added to every target's.
+    pub assets: Option<Assets>,
+    /// Metadata that overrides both the targets' and the top-level metadata.
+    pub metadata: Option<Metadata>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Manifest {
+    #[serde(default, alias = "target")]
+    pub targets: Vec<Target>,
+    #[serde(default, alias = "flavor")]
+    pub flavors: Vec<Flavor>,
+    /// The flavor chosen with `--flavor`, once `select_flavor` has applied it.
+    #[serde(skip)]
+    pub flavor: Option<Flavor>,
+    /// Metadata shared by every target, overridden field by field by the target's own.
+    pub metadata: Option<Metadata>,
+    #[serde(flatten)]
+    pub settings: Settings,
+    /// Directory the Crank.toml is looked for in, next to the Cargo.toml. Asset paths are
+    /// relative to it.
+    #[serde(skip)]
+    pub dir: PathBuf,
+}
+
+impl Manifest {
+    /// Merges in `fallback`, such as the `[package.metadata.crank]` table of Cargo.toml, with
+    /// `self` winning. Targets with the same name are merged field by field and the rest of
+    /// the fallback's targets are added.
+    pub fn or(self, fallback: Manifest) -> Manifest {
+        let mut targets = self.targets;
+        for fallback_target in fallback.targets {
+            match targets
+                .iter_mut()
+                .find(|target| target.name == fallback_target.name)
+            {
+                Some(target) => {
+                    target.assets = target.assets.take().or(fallback_target.assets);
+                    target.assets_file = target.assets_file.take().or(fallback_target.assets_file);
+                    target.debug_assets =
+                        target.debug_assets.take().or(fallback_target.debug_assets);
+                    target.release_assets = target
+                        .release_assets
+                        .take()
+                        .or(fallback_target.release_assets);
+                    target.default_features =
+                        target.default_features.or(fallback_target.default_features);
+                    target.test = target.test.or(fallback_target.test);
+                    target.pdxinfo_path =
+                        target.pdxinfo_path.take().or(fallback_target.pdxinfo_path);
+                    target.metadata = match (target.metadata.take(), fallback_target.metadata) {
+                        (Some(metadata), Some(fallback)) => Some(metadata.or(fallback)),
+                        (metadata, fallback) => metadata.or(fallback),
+                    };
+                }
+                None => targets.push(fallback_target),
+            }
+        }
+        let mut flavors = self.flavors;
+        for fallback_flavor in fallback.flavors {
+            if !flavors
+                .iter()
+                .any(|flavor| flavor.name == fallback_flavor.name)
+            {
+                flavors.push(fallback_flavor);
+            }
+        }
+        let metadata = match (self.metadata, fallback.metadata) {
+            (Some(metadata), Some(fallback)) => Some(metadata.or(fallback)),
+            (metadata, fallback) => metadata.or(fallback),
+        };
+        Manifest {
+            targets,
+            flavors,
+            flavor: self.flavor.or(fallback.flavor),
+            metadata,
+            settings: self.settings.or(fallback.settings),
+            dir: self.dir,
+        }
+    }
+
+    /// Adds the assets listed in each target's `assets_file`, relative to `dir`, after its
+    /// inline `assets`.
+    fn include_assets_files(&mut self, dir: &Path) -> Result<(), Error> {
+        for target in &mut self.targets {
+            if let Some(assets_file) = target.assets_file.as_ref() {
+                let path = dir.join(assets_file);
+                let contents = fs::read_to_string(&path).with_context(|| {
+                    format!("Reading the assets file of target '{}'", target.name)
+                })?;
+                target
+                    .assets
+                    .get_or_insert_with(Vec::new)
+                    .extend(parse_assets_list(&contents));
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the flavor called `name` to every target at once: its assets are added to each
+    /// target's and its metadata overrides theirs. Its features and bundle suffix are read from
+    /// `flavor` by the build.
+    pub fn select_flavor(&mut self, name: &str) -> Result<(), Error> {
+        let flavor = match self.flavors.iter().find(|flavor| flavor.name == name) {
+            Some(flavor) => flavor.clone(),
+            None => bail!(
+                "no flavor named '{}'; available: {}",
+                name,
+                if self.flavors.is_empty() {
+                    "(none)".to_string()
+                } else {
+                    self.flavors
+                        .iter()
+                        .map(|flavor| flavor.name.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                }
+            ),
+        };
+        let over = |metadata: Option<Metadata>| match (flavor.metadata.clone(), metadata) {
+            (Some(flavor_metadata), Some(metadata)) => Some(flavor_metadata.or(metadata)),
+            (flavor_metadata, metadata) => flavor_metadata.or(metadata),
+        };
+        for target in &mut self.targets {
+            if let Some(assets) = flavor.assets.as_ref() {
+                target
+                    .assets
+                    .get_or_insert_with(Vec::new)
+                    .extend(assets.iter().cloned());
+            }
+            target.metadata = over(target.metadata.take());
+        }
+        self.metadata = over(self.metadata.take());
+        debug!("flavor: {:#?}", flavor);
+        self.flavor = Some(flavor);
+        Ok(())
+    }
+
+    pub fn get_target(&self, target_name: &str) -> Option<&Target> {
+        self.targets
+            .iter()
+            .find(|target| target.name == target_name)
+    }
+
+    /// The assets of the target named `target_name` for the chosen profile. A target with no
+    /// [[target]] still gets the chosen flavor's assets, as `select_flavor` only adds them to
+    /// the listed targets. `None` when there is neither.
+    pub fn assets_for(&self, target_name: &str, release: bool) -> Option<Assets> {
+        match self.get_target(target_name) {
+            Some(target) => Some(target.assets_for(release)),
+            None => self
+                .flavor
+                .as_ref()
+                .and_then(|flavor| flavor.assets.clone()),
+        }
+    }
+
+    /// A warning for building the example `example` when no target is named after it, so
+    /// that it gets no assets and only the top-level metadata, if any.
+    pub fn untargeted_example_warning(&self, example: &str) -> Option<String> {
+        if self.get_target(example).is_some() {
+            return None;
+        }
+        let applies = if self
+            .flavor
+            .as_ref()
+            .is_some_and(|flavor| flavor.assets.is_some())
+        {
+            "gets only the top-level [metadata] and the flavor's assets"
+        } else if self.metadata.is_some() {
+            "gets only the top-level [metadata] and no assets"
+        } else {
+            "gets no metadata or assets"
+        };
+        Some(format!(
+            "example {} has no [[target]] in Crank.toml, so its pdx {}. Add a [[target]] \
+            with name = \"{}\" to give it its own.",
+            example, applies, example
+        ))
+    }
+
+    /// The metadata to write for a target: its own `metadata` merged over the top-level one.
+    pub fn metadata_for(&self, target_name: &str, release: bool) -> Option<Metadata> {
+        let target_metadata = self
+            .get_target(target_name)
+            .and_then(|target| target.metadata.clone());
+        let metadata = match (target_metadata, self.metadata.clone()) {
+            (Some(target_metadata), Some(base)) => Some(target_metadata.or(base)),
+            (target_metadata, base) => target_metadata.or(base),
+        }
+        .map(|metadata| metadata.for_profile(release));
+        debug!("metadata for {}: {:#?}", target_name, metadata);
+        metadata
+    }
+
+    /// Checks the manifest for mistakes that would otherwise only show up after a long build,
+    /// returning every problem found rather than stopping at the first.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if let Some(metadata) = self.metadata.as_ref() {
+            for problem in metadata.validate() {
+                problems.push(format!("metadata: {}", problem));
+            }
+        }
+        for (index, target) in self.targets.iter().enumerate() {
+            if target.name.trim().is_empty() {
+                problems.push(format!("target #{} has an empty name", index + 1));
+                continue;
+            }
+            if self.targets[..index]
+                .iter()
+                .any(|other| other.name == target.name)
+            {
+                problems.push(format!(
+                    "target '{}' is defined more than once",
+                    target.name
+                ));
+            }
+            for asset in [&target.assets, &target.debug_assets, &target.release_assets]
+                .iter()
+                .copied()
+                .flatten()
+                .flatten()
+            {
+                let mut paths = vec![asset.source(), asset.destination()];
+                paths.dedup();
+                for path in paths {
+                    if let Some(problem) = check_relative_path(path) {
+                        problems.push(format!(
+                            "target '{}': asset '{}' {}",
+                            target.name, path, problem
+                        ));
+                    }
+                }
+            }
+            if let Some(metadata) = target.metadata.as_ref() {
+                for problem in metadata.validate() {
+                    problems.push(format!("target '{}': {}", target.name, problem));
+                }
+            }
+        }
+        for (index, flavor) in self.flavors.iter().enumerate() {
+            if flavor.name.trim().is_empty() {
+                problems.push(format!("flavor #{} has an empty name", index + 1));
+                continue;
+            }
+            if self.flavors[..index]
+                .iter()
+                .any(|other| other.name == flavor.name)
+            {
+                problems.push(format!(
+                    "flavor '{}' is defined more than once",
+                    flavor.name
+                ));
+            }
+            for asset in flavor.assets.iter().flatten() {
+                if let Some(problem) = check_relative_path(asset.source()) {
+                    problems.push(format!(
+                        "flavor '{}': asset '{}' {}",
+                        flavor.name,
+                        asset.source(),
+                        problem
+                    ));
+                }
+            }
+            if let Some(metadata) = flavor.metadata.as_ref() {
+                for problem in metadata.validate() {
+                    problems.push(format!("flavor '{}': {}", flavor.name, problem));
+                }
+            }
+        }
+        problems
+    }
+
+    /// Versions that don't look like `1.2.0`, which the Playdate still accepts, so they are
+    /// only warned about.
+    pub fn version_warnings(&self) -> Vec<String> {
+        let mut metadata: Vec<(String, &Metadata)> = Vec::new();
+        metadata.extend(self.metadata.iter().map(|m| ("metadata".to_string(), m)));
+        for target in &self.targets {
+            let prefix = format!("target '{}'", target.name);
+            metadata.extend(target.metadata.iter().map(|m| (prefix.clone(), m)));
+        }
+        for flavor in &self.flavors {
+            let prefix = format!("flavor '{}'", flavor.name);
+            metadata.extend(flavor.metadata.iter().map(|m| (prefix.clone(), m)));
+        }
+        let mut warnings = Vec::new();
+        for (prefix, metadata) in metadata {
+            for (profile, metadata) in [
+                ("", Some(metadata)),
+                ("debug: ", metadata.debug.as_deref()),
+                ("release: ", metadata.release.as_deref()),
+            ] {
+                let version = metadata.and_then(|metadata| metadata.version.as_ref());
+                if let Some(version) = version.filter(|version| !is_valid_version(version)) {
+                    warnings.push(format!(
+                        "{}: {}version '{}' should be dot-separated numbers, like 1.2.0 or \
+                        1.2.0-beta",
+                        prefix, profile, version
+                    ));
+                }
+            }
+        }
+        warnings
+    }
+}
+
+/// Merges the overrides of one profile field by field, like `Metadata::or`.
+fn or_profile(
+    overrides: Option<Box<Metadata>>,
+    fallback: Option<Box<Metadata>>,
+) -> Option<Box<Metadata>> {
+    match (overrides, fallback) {
+        (Some(overrides), Some(fallback)) => Some(Box::new(overrides.or(*fallback))),
+        (overrides, fallback) => overrides.or(fallback),
+    }
+}
+
+impl Metadata {
+    /// Fills every field not set in `self` from `fallback`.
+    pub fn or(self, fallback: Metadata) -> Metadata {
+        Metadata {
+            name: self.name.or(fallback.name),
+            author: self.author.or(fallback.author),
+            description: self.description.or(fallback.description),
+            bundle_id: self.bundle_id.or(fallback.bundle_id),
+            version: self.version.or(fallback.version),
+            build_number: self.build_number.or(fallback.build_number),
+            image_path: self.image_path.or(fallback.image_path),
+            launch_sound_path: self.launch_sound_path.or(fallback.launch_sound_path),
+            pdx_name: self.pdx_name.or(fallback.pdx_name),
+            debug: or_profile(self.debug, fallback.debug),
+            release: or_profile(self.release, fallback.release),
+        }
+    }
+
+    /// The metadata with the overrides for the debug or release profile applied.
+    pub fn for_profile(mut self, release: bool) -> Metadata {
+        let (debug, release_overrides) = (self.debug.take(), self.release.take());
+        match if release { release_overrides } else { debug } {
+            Some(overrides) => Metadata {
+                debug: None,
+                release: None,
+                ..*overrides
+            }
+            .or(self),
+            None => self,
+        }
+    }
+
+    /// The bundle id with its `{suffix}` placeholder replaced by `suffix`, so that debug and
+    /// release builds of the same game can be installed side by side.
+    pub fn resolved_bundle_id(&self, suffix: &str) -> Option<String> {
+        self.bundle_id
+            .as_ref()
+            .map(|bundle_id| bundle_id.replace(BUNDLE_SUFFIX_PLACEHOLDER, suffix))
+    }
+
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if let Some(bundle_id) = self.resolved_bundle_id("") {
+            if !is_valid_bundle_id(&bundle_id) {
+                problems.push(format!(
+                    "bundle_id '{}' should be reverse-DNS style, like com.example.game",
+                    self.bundle_id.as_deref().unwrap_or_default()
+                ));
+            }
+        }
+        if let Some(pdx_name) = self.pdx_name.as_ref() {
+            if title::file_name(pdx_name) != *pdx_name {
+                problems.push(format!(
+                    "pdx_name '{}' should be usable as a file name, like '{}'",
+                    pdx_name,
+                    title::file_name(pdx_name)
+                ));
+            }
+        }
+        for (key, path) in &[
+            ("image_path", &self.image_path),
+            ("launch_sound_path", &self.launch_sound_path),
+        ] {
+            if let Some(path) = path {
+                if let Some(problem) = check_relative_path(path) {
+                    problems.push(format!("{} '{}' {}", key, path, problem));
+                }
+            }
+        }
+        for (profile, overrides) in &[("debug", &self.debug), ("release", &self.release)] {
+            for problem in overrides.iter().flat_map(|overrides| overrides.validate()) {
+                problems.push(format!("{}: {}", profile, problem));
+            }
+        }
+        problems
+    }
+}
+
+/// Asset and metadata paths are relative to the project and to the root of the pdx, so they
+/// must not be absolute or climb out with `..`.
+fn check_relative_path(path: &str) -> Option<&'static str> {
+    let path = Path::new(path);
+    if path.as_os_str().is_empty() {
+        Some("is empty")
+    } else if path.has_root() {
+        Some("must be a relative path")
+    } else if path
+        .components()
+        .any(|component| component == Component::ParentDir)
+    {
+        Some("must not contain '..'")
+    } else {
+        None
+    }
+}
+
+/// Reads an assets file: one asset pattern per line, skipping blank lines and comments that
+/// start with `#`.
+fn parse_assets_list(contents: &str) -> Assets {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| Asset::Path(line.to_string()))
+        .collect()
+}
+
+fn is_valid_bundle_id(bundle_id: &str) -> bool {
+    let segments: Vec<&str> = bundle_id.split('.').collect();
+    segments.len() >= 2
+        && segments.iter().all(|segment| {
+            !segment.is_empty()
+                && segment
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        })
+}
+
+fn is_valid_version(version: &str) -> bool {
+    let numbers = version.split(['-', '+']).next().unwrap_or("");
+    let suffix = &version[numbers.len()..];
+    !numbers.is_empty()
+        && numbers
+            .split('.')
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+        && suffix
+            .chars()
+            .skip(1)
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+        && suffix.len() != 1
+}
+
+fn check_manifest(manifest: &Manifest, source: &str) -> Result<(), Error> {
+    for warning in manifest.version_warnings() {
+        eprintln!("Warning: {}: {}", source, warning);
+    }
+    let problems = manifest.validate();
+    if !problems.is_empty() {
+        bail!(
+            "{} has {} problem(s):\n  {}",
+            source,
+            problems.len(),
+            problems.join("\n  ")
+        );
+    }
+    Ok(())
+}
+
+/// Reads the `[package.metadata.crank]` table of a Cargo.toml, which takes the same keys as
+/// Crank.toml.
+fn load_cargo_metadata(cargo_toml_path: &Path) -> Result<Option<Manifest>, Error> {
+    if !cargo_toml_path.exists() {
+        return Ok(None);
+    }
+    let cargo_toml: toml::Value = toml::from_str(&fs::read_to_string(cargo_toml_path)?)
+        .map_err(|err| anyhow!("{}: {}", cargo_toml_path.display(), err))?;
+    let crank_table = cargo_toml
+        .get("package")
+        .and_then(|package| package.get("metadata"))
+        .and_then(|metadata| metadata.get("crank"));
+    match crank_table {
+        Some(crank_table) => {
+            let manifest = crank_table.clone().try_into().map_err(|err| {
+                anyhow!(
+                    "{} [package.metadata.crank]: {}",
+                    cargo_toml_path.display(),
+                    err
+                )
+            })?;
+            Ok(Some(manifest))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Loads the Crank.toml next to the Cargo.toml at `manifest_path`, or the one at
+/// `crank_manifest_path` if given, and merges it with the Cargo.toml's
+/// `[package.metadata.crank]` and the global settings. Asset paths are relative to the
+/// directory of the Crank.toml, which becomes `Manifest::dir`. The `[toolchain]` paths are
+/// not: they are resolved against the project directory when building.
+pub fn load_manifest(
+    manifest_path: &Option<PathBuf>,
+    crank_manifest_path: Option<&Path>,
+) -> Result<Manifest, Error> {
+    let cwd: PathBuf = if let Some(actual_manifest_path) = manifest_path.as_ref() {
+        actual_manifest_path
+            .parent()
+            .expect("manifest_path parent")
+            .to_path_buf()
+    } else {
+        std::env::current_dir()?
+    };
+    let cargo_toml_path = manifest_path
+        .clone()
+        .unwrap_or_else(|| cwd.join("Cargo.toml"));
+    let (manifest_path, crank_dir) = match crank_manifest_path {
+        Some(crank_manifest_path) => {
+            if !crank_manifest_path.is_file() {
+                bail!("--crank-manifest {:?} does not exist", crank_manifest_path);
+            }
+            let dir = match crank_manifest_path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+                _ => std::env::current_dir()?,
+            };
+            (crank_manifest_path.to_path_buf(), dir)
+        }
+        None => (cwd.join("Crank.toml"), cwd.clone()),
+    };
+    let crank_toml: Option<Manifest> = if manifest_path.exists() {
+        let manifest_contents = fs::read_to_string(&manifest_path)?;
+        let mut manifest: Manifest = toml::from_str(&manifest_contents)?;
+        manifest.include_assets_files(&crank_dir)?;
+        check_manifest(&manifest, &manifest_path.display().to_string())?;
+        Some(manifest)
+    } else {
+        None
+    };
+    let mut cargo_toml = load_cargo_metadata(&cargo_toml_path)?;
+    if let Some(cargo_toml) = cargo_toml.as_mut() {
+        cargo_toml.include_assets_files(&cwd)?;
+        check_manifest(
+            cargo_toml,
+            &format!("{} [package.metadata.crank]", cargo_toml_path.display()),
+        )?;
+    }
+    let mut manifest = match (crank_toml, cargo_toml) {
+        (Some(crank_toml), Some(cargo_toml)) => crank_toml.or(cargo_toml),
+        (crank_toml, cargo_toml) => crank_toml.or(cargo_toml).unwrap_or_default(),
+    };
+    manifest.settings = manifest.settings.or(settings::load_global()?);
+    manifest.dir = crank_dir;
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn reports_every_problem() {
+        let manifest: Manifest = toml::from_str(
+            r#"
+            [[target]]
+            name = "game"
+            assets = ["../outside.png", { from = "art/a.png", to = "/abs.png" }]
+
+            [target.metadata]
+            bundle_id = "game"
+            version = "1.x"
+
+            [[target]]
+            name = "game"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            manifest.validate(),
+            vec![
+                "target 'game': asset '../outside.png' must not contain '..'",
+                "target 'game': asset '/abs.png' must be a relative path",
+                "target 'game': bundle_id 'game' should be reverse-DNS style, like com.example.game",
+                "target 'game' is defined more than once",
+            ]
+        );
+        assert_eq!(
+            manifest.version_warnings(),
+            vec![
+                "target 'game': version '1.x' should be dot-separated numbers, like 1.2.0 or 1.2.0-beta"
+            ]
+        );
+    }
+
+    #[test]
+    fn target_metadata_overrides_base() {
+        let manifest: Manifest = toml::from_str(
+            r#"
+            [metadata]
+            author = "Me"
+            bundle_id = "com.me.shared"
+
+            [[target]]
+            name = "game"
+
+            [target.metadata]
+            name = "Game"
+            bundle_id = "com.me.game"
+            "#,
+        )
+        .unwrap();
+        let metadata = manifest.metadata_for("game", false).unwrap();
+        assert_eq!(metadata.name.as_deref(), Some("Game"));
+        assert_eq!(metadata.author.as_deref(), Some("Me"));
+        assert_eq!(metadata.bundle_id.as_deref(), Some("com.me.game"));
+        let metadata = manifest.metadata_for("other", false).unwrap();
+        assert_eq!(metadata.name, None);
+        assert_eq!(metadata.author.as_deref(), Some("Me"));
+        assert_eq!(manifest.untargeted_example_warning("game"), None);
+        assert!(manifest
+            .untargeted_example_warning("other")
+            .unwrap()
+            .contains("only the top-level [metadata]"));
+    }
+
+    #[test]
+    fn profile_metadata_overrides() {
+        let manifest: Manifest = toml::from_str(
+            r#"
+            [metadata]
+            name = "Game"
+            version = "1.2.0"
+
+            [metadata.debug]
+            name = "Game (DEV)"
+            version = "1.2.0-dev"
+
+            [[target]]
+            name = "game"
+
+            [target.metadata]
+            author = "Me"
+
+            [target.metadata.release]
+            build_number = 7
+            "#,
+        )
+        .unwrap();
+        let debug = manifest.metadata_for("game", false).unwrap();
+        assert_eq!(debug.name.as_deref(), Some("Game (DEV)"));
+        assert_eq!(debug.version.as_deref(), Some("1.2.0-dev"));
+        assert_eq!(debug.author.as_deref(), Some("Me"));
+        assert_eq!(debug.build_number, None);
+        let release = manifest.metadata_for("game", true).unwrap();
+        assert_eq!(release.name.as_deref(), Some("Game"));
+        assert_eq!(release.version.as_deref(), Some("1.2.0"));
+        assert_eq!(release.build_number, Some(7));
+    }
+
+    #[test]
+    fn profile_assets() {
+        let manifest: Manifest = toml::from_str(
+            r#"
+            [[target]]
+            name = "game"
+            assets = ["images"]
+            debug_assets = ["test_levels", "../outside"]
+            release_assets = ["credits.txt"]
+            "#,
+        )
+        .unwrap();
+        let game = manifest.get_target("game").unwrap();
+        let sources = |release| {
+            game.assets_for(release)
+                .iter()
+                .map(|asset| asset.source().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sources(false), ["images", "test_levels", "../outside"]);
+        assert_eq!(sources(true), ["images", "credits.txt"]);
+        assert_eq!(manifest.validate().len(), 1);
+    }
+
+    #[test]
+    fn assets_from_file() {
+        let dir = TestDir::new("assets-file");
+        fs::write(
+            dir.join("assets.list"),
+            "# generated\nimages/player.png\n\n  sounds/jump.wav  \n",
+        )
+        .unwrap();
+        let mut manifest: Manifest = toml::from_str(
+            r#"
+            [[target]]
+            name = "game"
+            assets = ["fonts/main.fnt"]
+            assets_file = "assets.list"
+            "#,
+        )
+        .unwrap();
+        manifest.include_assets_files(&dir).unwrap();
+        assert_eq!(
+            manifest.targets[0].assets,
+            Some(vec![
+                Asset::Path("fonts/main.fnt".to_string()),
+                Asset::Path("images/player.png".to_string()),
+                Asset::Path("sounds/jump.wav".to_string()),
+            ])
+        );
+        assert!(manifest.include_assets_files(&dir.join("missing")).is_err());
+    }
+
+    #[test]
+    fn flavor_overrides_targets() {
+        let mut manifest: Manifest = toml::from_str(
+            r#"
+            [metadata]
+            author = "Me"
+            bundle_id = "com.me.game{suffix}"
+
+            [[target]]
+            name = "game"
+            assets = ["images"]
+
+            [target.metadata]
+            name = "Game"
+
+            [[flavor]]
+            name = "free"
+            features = ["ads"]
+            assets = ["ads"]
+
+            [flavor.metadata]
+            name = "Game Free"
+            bundle_id = "com.me.gamefree{suffix}"
+            "#,
+        )
+        .unwrap();
+        assert!(manifest.validate().is_empty());
+        assert!(manifest.select_flavor("paid").is_err());
+
+        manifest.select_flavor("free").unwrap();
+        let metadata = manifest.metadata_for("game", false).unwrap();
+        assert_eq!(metadata.name.as_deref(), Some("Game Free"));
+        assert_eq!(metadata.author.as_deref(), Some("Me"));
+        assert_eq!(
+            metadata.bundle_id.as_deref(),
+            Some("com.me.gamefree{suffix}")
+        );
+        assert_eq!(
+            manifest.get_target("game").unwrap().assets_for(false),
+            vec![
+                Asset::Path("images".to_string()),
+                Asset::Path("ads".to_string())
+            ]
+        );
+        // Targets without a [[target]] get the flavor's metadata and assets too.
+        let metadata = manifest.metadata_for("level1", false).unwrap();
+        assert_eq!(metadata.name.as_deref(), Some("Game Free"));
+        assert_eq!(
+            manifest.assets_for("level1", false),
+            Some(vec![Asset::Path("ads".to_string())])
+        );
+        assert!(manifest
+            .untargeted_example_warning("level1")
+            .unwrap()
+            .contains("the flavor's assets"));
+        assert_eq!(
+            manifest.flavor.unwrap().features,
+            Some(vec!["ads".to_string()])
+        );
+    }
+
+    #[test]
+    fn pdx_name_must_be_a_file_name() {
+        let manifest: Manifest = toml::from_str(
+            r#"
+            [[target]]
+            name = "game"
+            metadata = { name = "My Great Game", pdx_name = "mygreatgame" }
+
+            [[target]]
+            name = "other"
+            metadata = { pdx_name = "../other" }
+            "#,
+        )
+        .unwrap();
+        let metadata = manifest.metadata_for("game", false).unwrap();
+        assert_eq!(metadata.pdx_name.as_deref(), Some("mygreatgame"));
+        let problems = manifest.validate();
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("'../other'"), "{}", problems[0]);
+    }
+
+    #[test]
+    fn accepts_common_versions() {
+        assert!(is_valid_version("1"));
+        assert!(is_valid_version("1.2.3"));
+        assert!(is_valid_version("1.2.3-beta.1"));
+        assert!(!is_valid_version("1.2."));
+        assert!(!is_valid_version("1.2-"));
+        assert!(!is_valid_version("v1"));
+    }
+
+    #[test]
+    fn crank_toml_overrides_cargo_metadata() {
+        let crank_toml: Manifest = toml::from_str(
+            r#"
+            [[target]]
+            name = "game"
+            assets = ["images"]
+
+            [target.metadata]
+            name = "Game"
+            "#,
+        )
+        .unwrap();
+        let cargo_toml: toml::Value = toml::from_str(
+            r#"
+            [package]
+            name = "game"
+
+            [package.metadata.crank]
+            metadata = { author = "Me" }
+
+            [[package.metadata.crank.target]]
+            name = "game"
+            assets = ["sounds"]
+            metadata = { name = "Cargo Game", version = "1.0" }
+
+            [[package.metadata.crank.target]]
+            name = "other"
+            "#,
+        )
+        .unwrap();
+        let cargo_metadata: Manifest = cargo_toml["package"]["metadata"]["crank"]
+            .clone()
+            .try_into()
+            .unwrap();
+
+        let manifest = crank_toml.or(cargo_metadata);
+        let game = manifest.get_target("game").unwrap();
+        assert_eq!(game.assets, Some(vec![Asset::Path("images".to_string())]));
+        let metadata = manifest.metadata_for("game", false).unwrap();
+        assert_eq!(metadata.name.as_deref(), Some("Game"));
+        assert_eq!(metadata.version.as_deref(), Some("1.0"));
+        assert_eq!(metadata.author.as_deref(), Some("Me"));
+        assert!(manifest.get_target("other").is_some());
+    }
+
+    #[test]
+    fn bundle_id_suffix_placeholder() {
+        let metadata = Metadata {
+            bundle_id: Some("com.me.game{suffix}".to_string()),
+            ..Default::default()
+        };
+        assert!(metadata.validate().is_empty());
+        assert_eq!(
+            metadata.resolved_bundle_id(".debug").as_deref(),
+            Some("com.me.game.debug")
+        );
+        assert_eq!(
+            metadata.resolved_bundle_id("").as_deref(),
+            Some("com.me.game")
+        );
+    }
+}
@@ -0,0 +1,110 @@
+use anyhow::Error;
+use log::info;
+use std::{io::Write, process::Command, time::Duration};
+
+/// The title and message announcing that a command finished, with how long it took and, if
+/// it failed, the first line of the error.
+pub fn completion_message(result: &Result<(), Error>, elapsed: Duration) -> (String, String) {
+    let seconds = elapsed.as_secs();
+    let duration = if seconds >= 60 {
+        format!("{}m {:02}s", seconds / 60, seconds % 60)
+    } else {
+        format!("{:.1}s", elapsed.as_secs_f32())
+    };
+    match result {
+        Ok(()) => (
+            "crank succeeded".to_string(),
+            format!("Finished in {}", duration),
+        ),
+        Err(error) => (
+            "crank failed".to_string(),
+            format!(
+                "Failed after {}: {}",
+                duration,
+                error.to_string().lines().next().unwrap_or_default()
+            ),
+        ),
+    }
+}
+
+/// The command that shows a desktop notification. The text goes through the environment so
+/// that nothing in it needs quoting for AppleScript or PowerShell.
+fn notification_command(title: &str, message: &str) -> Command {
+    #[cfg(target_os = "macos")]
+    let mut command = {
+        let mut command = Command::new("osascript");
+        command.arg("-e").arg(
+            "display notification (system attribute \"CRANK_NOTIFY_MESSAGE\") \
+            with title (system attribute \"CRANK_NOTIFY_TITLE\")",
+        );
+        command
+    };
+    #[cfg(windows)]
+    let mut command = {
+        let mut command = Command::new("powershell");
+        command.arg("-NoProfile").arg("-Command").arg(
+            "[Windows.UI.Notifications.ToastNotificationManager, Windows.UI.Notifications, \
+            ContentType = WindowsRuntime] > $null; \
+            $template = [Windows.UI.Notifications.ToastNotificationManager]::GetTemplateContent(\
+            [Windows.UI.Notifications.ToastTemplateType]::ToastText02); \
+            $text = $template.GetElementsByTagName('text'); \
+            $text.Item(0).AppendChild($template.CreateTextNode($env:CRANK_NOTIFY_TITLE)) > $null; \
+            $text.Item(1).AppendChild($template.CreateTextNode($env:CRANK_NOTIFY_MESSAGE)) > $null; \
+            [Windows.UI.Notifications.ToastNotificationManager]::CreateToastNotifier('crank').Show(\
+            [Windows.UI.Notifications.ToastNotification]::new($template))",
+        );
+        command
+    };
+    #[cfg(not(any(target_os = "macos", windows)))]
+    let mut command = {
+        let mut command = Command::new("notify-send");
+        command.arg(title).arg(message);
+        command
+    };
+    command
+        .env("CRANK_NOTIFY_TITLE", title)
+        .env("CRANK_NOTIFY_MESSAGE", message);
+    command
+}
+
+/// Rings the terminal bell and shows a desktop notification. A missing notifier, such as
+/// `notify-send` on a Linux machine without a notification daemon, only leaves the bell.
+pub fn send(title: &str, message: &str) {
+    eprint!("\x07");
+    let _ = std::io::stderr().flush();
+    match notification_command(title, message).output() {
+        Ok(output) if output.status.success() => {}
+        Ok(output) => info!(
+            "notification failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+        Err(err) => info!("can't show a notification: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn describes_completion() {
+        assert_eq!(
+            completion_message(&Ok(()), Duration::from_millis(4200)),
+            (
+                "crank succeeded".to_string(),
+                "Finished in 4.2s".to_string()
+            )
+        );
+        assert_eq!(
+            completion_message(
+                &Err(anyhow!("cargo failed with error 101\nmore detail")),
+                Duration::from_secs(125)
+            ),
+            (
+                "crank failed".to_string(),
+                "Failed after 2m 05s: cargo failed with error 101".to_string()
+            )
+        );
+    }
+}
@@ -0,0 +1,43 @@
+use std::{process::Command, sync::OnceLock};
+
+/// Niceness given to the tools crank runs with `--low-priority`, on a scale where 0 is the
+/// default and 19 the lowest priority.
+#[cfg(unix)]
+const LOW_PRIORITY_NICENESS: i32 = 10;
+
+/// `BELOW_NORMAL_PRIORITY_CLASS` from the Windows API.
+#[cfg(windows)]
+const BELOW_NORMAL_PRIORITY_CLASS: u32 = 0x0000_4000;
+
+static LOW_PRIORITY: OnceLock<bool> = OnceLock::new();
+
+/// Chooses, once at startup, whether the build tools run at a reduced priority.
+pub fn init(low_priority: bool) {
+    let _ = LOW_PRIORITY.set(low_priority);
+}
+
+/// Makes `command` run at a reduced priority with `--low-priority`, so that long builds don't
+/// slow down interactive work. The tools it starts in turn, such as rustc, inherit it.
+pub fn apply(command: &mut Command) {
+    if !LOW_PRIORITY.get().copied().unwrap_or(false) {
+        return;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        // SAFETY: setpriority is a plain system call, which is safe to make between fork and
+        // exec.
+        unsafe {
+            command.pre_exec(|| {
+                // A failure only means the tool runs at the usual priority.
+                libc::setpriority(libc::PRIO_PROCESS, 0, LOW_PRIORITY_NICENESS);
+                Ok(())
+            });
+        }
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        command.creation_flags(BELOW_NORMAL_PRIORITY_CLASS);
+    }
+}
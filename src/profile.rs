@@ -0,0 +1,197 @@
+use crate::{device, settings::Settings};
+use anyhow::{anyhow, Error};
+use std::{
+    io::{self, BufRead, BufReader},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Marks a line of console output as a profiling sample, such as
+/// `CRANK_PROFILE frame_ms=16.7 cpu=54`, printed by the game once a frame or so.
+pub const MARKER: &str = "CRANK_PROFILE";
+
+/// One profiling sample: how long a frame took and, if the game reports it, the share of the
+/// CPU it used, in percent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sample {
+    pub frame_ms: f32,
+    pub cpu: Option<f32>,
+}
+
+/// The sample a line of console output reports, if any. The marker can come after a prefix
+/// such as a timestamp, and keys other than `frame_ms` and `cpu` are ignored.
+pub fn parse_line(line: &str) -> Option<Sample> {
+    let (_, fields) = line.split_once(MARKER)?;
+    let (mut frame_ms, mut cpu) = (None, None);
+    for field in fields.split_whitespace() {
+        match field.split_once('=') {
+            Some(("frame_ms", value)) => frame_ms = value.parse().ok(),
+            Some(("cpu", value)) => cpu = value.trim_end_matches('%').parse().ok(),
+            _ => {}
+        }
+    }
+    Some(Sample {
+        frame_ms: frame_ms?,
+        cpu,
+    })
+}
+
+/// Frame time and CPU statistics over a profiling run.
+#[derive(Debug, PartialEq)]
+pub struct Report {
+    pub samples: usize,
+    pub frame_ms_min: f32,
+    pub frame_ms_mean: f32,
+    pub frame_ms_p95: f32,
+    pub frame_ms_max: f32,
+    /// Mean and maximum CPU use, from the samples that report it.
+    pub cpu: Option<(f32, f32)>,
+}
+
+pub fn summarize(samples: &[Sample]) -> Option<Report> {
+    if samples.is_empty() {
+        return None;
+    }
+    let mut frame_ms: Vec<f32> = samples.iter().map(|sample| sample.frame_ms).collect();
+    frame_ms.sort_by(f32::total_cmp);
+    let p95_index = ((frame_ms.len() as f32 * 0.95).ceil() as usize).clamp(1, frame_ms.len()) - 1;
+    let cpu: Vec<f32> = samples.iter().filter_map(|sample| sample.cpu).collect();
+    Some(Report {
+        samples: samples.len(),
+        frame_ms_min: frame_ms[0],
+        frame_ms_mean: frame_ms.iter().sum::<f32>() / frame_ms.len() as f32,
+        frame_ms_p95: frame_ms[p95_index],
+        frame_ms_max: frame_ms[frame_ms.len() - 1],
+        cpu: if cpu.is_empty() {
+            None
+        } else {
+            Some((
+                cpu.iter().sum::<f32>() / cpu.len() as f32,
+                cpu.iter().copied().fold(f32::MIN, f32::max),
+            ))
+        },
+    })
+}
+
+impl Report {
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!("samples:   {}", self.samples),
+            format!(
+                "frame ms:  min {:.1}, mean {:.1}, p95 {:.1}, max {:.1} ({:.1} fps mean)",
+                self.frame_ms_min,
+                self.frame_ms_mean,
+                self.frame_ms_p95,
+                self.frame_ms_max,
+                1000.0 / self.frame_ms_mean
+            ),
+        ];
+        if let Some((mean, max)) = self.cpu {
+            lines.push(format!("cpu:       mean {:.0}%, max {:.0}%", mean, max));
+        }
+        lines
+    }
+}
+
+/// Reads the device's console for `duration`, echoing the game's output and collecting the
+/// profiling samples in it. Reading a local device node can't time out on its own, so the
+/// console is read on its own thread, which is left behind once the time is up.
+pub fn collect(
+    device_serial: Option<&str>,
+    settings: &Settings,
+    duration: Duration,
+) -> Result<Vec<Sample>, Error> {
+    let device_serial = device_serial.map(String::from);
+    let settings = settings.clone();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let console = match device::open_console(device_serial.as_deref(), &settings) {
+            Ok(console) => console,
+            Err(err) => {
+                let _ = sender.send(Err(err));
+                return;
+            }
+        };
+        for line in BufReader::new(console).lines() {
+            let line = match line {
+                Ok(line) => line,
+                // A network bridge times out reads while the game is quiet.
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    continue
+                }
+                Err(_) => break,
+            };
+            if sender.send(Ok(line)).is_err() {
+                break;
+            }
+        }
+    });
+
+    let deadline = Instant::now() + duration;
+    let mut samples = Vec::new();
+    loop {
+        match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(Ok(line)) => match parse_line(&line) {
+                Some(sample) => samples.push(sample),
+                None => println!("{}", line.trim_end()),
+            },
+            Ok(Err(err)) => return Err(err),
+            Err(mpsc::RecvTimeoutError::Timeout) | Err(mpsc::RecvTimeoutError::Disconnected) => {
+                break
+            }
+        }
+    }
+    if samples.is_empty() {
+        return Err(anyhow!(
+            "No {} lines on the console after {:?}. Have the game print lines such as \
+            \"{} frame_ms=16.7 cpu=54\".",
+            MARKER,
+            duration,
+            MARKER
+        ));
+    }
+    Ok(samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_samples() {
+        assert_eq!(
+            parse_line("00:01:02 CRANK_PROFILE frame_ms=20.5 cpu=61% draw=3"),
+            Some(Sample {
+                frame_ms: 20.5,
+                cpu: Some(61.0),
+            })
+        );
+        assert_eq!(parse_line("CRANK_PROFILE cpu=61"), None);
+        assert_eq!(parse_line("player jumped"), None);
+
+        let samples: Vec<Sample> = (1..=20)
+            .map(|frame| Sample {
+                frame_ms: frame as f32,
+                cpu: if frame % 2 == 0 { Some(40.0) } else { None },
+            })
+            .collect();
+        assert_eq!(
+            summarize(&samples),
+            Some(Report {
+                samples: 20,
+                frame_ms_min: 1.0,
+                frame_ms_mean: 10.5,
+                frame_ms_p95: 19.0,
+                frame_ms_max: 20.0,
+                cpu: Some((40.0, 40.0)),
+            })
+        );
+        assert_eq!(summarize(&[]), None);
+    }
+}
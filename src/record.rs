@@ -0,0 +1,145 @@
+use crate::{build_info, device::Console, screenshot};
+use anyhow::{Context, Error};
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// The console or simulator log of a run, with the time since the session started in front
+/// of each line.
+pub const CONSOLE_LOG: &str = "console.log";
+/// What was run, where and when.
+pub const SESSION_INFO: &str = "session.txt";
+
+/// The directory a recorded run is saved into, named after the pdx and the time it started.
+pub struct Session {
+    dir: PathBuf,
+    log: File,
+    start: Instant,
+    screenshots: usize,
+}
+
+impl Session {
+    /// Creates `<parent>/<pdx_name>-<UTC time>`, with a `session.txt` describing the run.
+    pub fn create(parent: &Path, pdx_name: &str, target: &str) -> Result<Session, Error> {
+        let unix_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0);
+        let started = build_info::utc_timestamp(unix_time);
+        // Colons aren't allowed in file names on Windows.
+        let dir = parent.join(format!("{}-{}", pdx_name, started.replace(':', "-")));
+        fs::create_dir_all(&dir).with_context(|| format!("Creating {:?}", dir))?;
+        fs::write(
+            dir.join(SESSION_INFO),
+            format!(
+                "pdx: {}\ntarget: {}\nstarted: {}\ncrank: {}\n",
+                pdx_name,
+                target,
+                started,
+                env!("CARGO_PKG_VERSION")
+            ),
+        )?;
+        let log_path = dir.join(CONSOLE_LOG);
+        let log = File::create(&log_path).with_context(|| format!("Creating {:?}", log_path))?;
+        Ok(Session {
+            dir,
+            log,
+            start: Instant::now(),
+            screenshots: 0,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Adds a line to the console log. Lines are written as they come, so the log is complete
+    /// up to the moment the recording is interrupted.
+    pub fn log_line(&mut self, line: &str) -> Result<(), Error> {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        writeln!(self.log, "[{:9.3}] {}", elapsed, line.trim_end())?;
+        Ok(())
+    }
+
+    /// Saves a frame from the device as the next `screenshot-NNNN.png`.
+    pub fn screenshot(&mut self, frame: &[u8]) -> Result<PathBuf, Error> {
+        self.screenshots += 1;
+        let path = self
+            .dir
+            .join(format!("screenshot-{:04}.png", self.screenshots));
+        screenshot::write_png(&path, frame)?;
+        self.log_line(&format!(
+            "(saved {})",
+            path.file_name().unwrap_or_default().to_string_lossy()
+        ))?;
+        Ok(path)
+    }
+}
+
+/// Echoes and records the device console until it closes or crank is interrupted. With
+/// `screenshot_interval`, a screenshot is taken at that interval, and the console output is
+/// recorded in batches, as it comes back with each screenshot.
+pub fn record_device(
+    mut console: Box<dyn Console>,
+    session: &mut Session,
+    screenshot_interval: Option<Duration>,
+) -> Result<(), Error> {
+    if let Some(interval) = screenshot_interval {
+        loop {
+            thread::sleep(interval);
+            let (output, frame) = screenshot::capture_with_output(&mut *console)?;
+            for line in String::from_utf8_lossy(&output).lines() {
+                println!("{}", line);
+                session.log_line(line)?;
+            }
+            session.screenshot(&frame)?;
+        }
+    }
+    for line in BufReader::new(console).lines() {
+        let line = match line {
+            Ok(line) => line,
+            // A network bridge times out reads while the game is quiet.
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                continue
+            }
+            Err(_) => break,
+        };
+        println!("{}", line.trim_end());
+        session.log_line(&line)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn records_session() {
+        let parent = TestDir::new("record");
+        let mut session = Session::create(&parent, "My Game", "simulator").unwrap();
+        session.log_line("hello\r").unwrap();
+        session.screenshot(&[0xff; 50 * 240]).unwrap();
+
+        let name = session.dir().file_name().unwrap().to_string_lossy();
+        assert!(name.starts_with("My Game-") && !name.contains(':'));
+        let info = fs::read_to_string(session.dir().join(SESSION_INFO)).unwrap();
+        assert!(info.contains("target: simulator\n"));
+        let log = fs::read_to_string(session.dir().join(CONSOLE_LOG)).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with('[') && lines[0].ends_with("] hello"));
+        assert!(lines[1].ends_with("] (saved screenshot-0001.png)"));
+        assert!(session.dir().join("screenshot-0001.png").is_file());
+    }
+}
@@ -0,0 +1,129 @@
+use std::{
+    io::{BufRead, BufReader, Read},
+    thread,
+};
+
+/// Messages in cargo's output that point at the filesystem or another process getting in the
+/// way rather than at the code, so that running the build again is likely to succeed. These
+/// mostly show up with `-Zbuild-std` on networked or CI filesystems.
+const TRANSIENT_SIGNATURES: &[&str] = &[
+    "Resource temporarily unavailable",
+    "Device or resource busy",
+    "Text file busy",
+    "Stale file handle",
+    "failed to rename",
+    "being used by another process",
+];
+
+/// Whether a failed build is worth retrying: cargo's output has one of the transient
+/// signatures and no compiler error. An error code, or a crate that could not compile,
+/// always means the code itself is wrong.
+pub fn is_transient_failure(lines: &[String]) -> bool {
+    let compile_error = lines.iter().any(|line| {
+        let line = strip_color(line);
+        line.starts_with("error[") || line.starts_with("error: could not compile")
+    });
+    !compile_error
+        && lines.iter().any(|line| {
+            TRANSIENT_SIGNATURES
+                .iter()
+                .any(|signature| line.contains(signature))
+        })
+}
+
+/// The diagnostics in cargo's output, such as warnings, without the status lines like
+/// `Compiling` and `Finished` that report its progress. Those are indented to right-align a
+/// capitalized verb, while diagnostics start with their level or with source context.
+pub fn diagnostics(lines: &[String]) -> Vec<&String> {
+    lines
+        .iter()
+        .filter(|line| {
+            let line = strip_color(line);
+            let verb = line.trim_start();
+            !(line.starts_with(' ') && verb.starts_with(|c: char| c.is_ascii_uppercase()))
+        })
+        .collect()
+}
+
+/// Removes the color escape sequences cargo adds when asked for colored output.
+fn strip_color(line: &str) -> String {
+    let mut plain = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
+
+/// Reads `stream` line by line on another thread, with `echo` also printing each line to
+/// stderr as it arrives, and returns every line once it ends.
+pub fn read_lines(
+    stream: impl Read + Send + 'static,
+    echo: bool,
+) -> thread::JoinHandle<Vec<String>> {
+    thread::spawn(move || {
+        let mut lines = Vec::new();
+        for line in BufReader::new(stream).lines().map_while(Result::ok) {
+            if echo {
+                eprintln!("{}", line);
+            }
+            lines.push(line);
+        }
+        lines
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_transient_failures() {
+        let lines = |text: &str| text.lines().map(String::from).collect::<Vec<_>>();
+        assert!(is_transient_failure(&lines(
+            "   Compiling core v0.0.0\n\
+             error: failed to rename `/ci/target/deps/libcore.rlib`: \
+             Stale file handle (os error 116)"
+        )));
+        assert!(!is_transient_failure(&lines(
+            "\x1b[1m\x1b[31merror[E0425]\x1b[0m: cannot find value `x` in this scope\n\
+             error: failed to rename `/ci/target/deps/libgame.rlib`: Text file busy"
+        )));
+        assert!(!is_transient_failure(&lines(
+            "error: couldn't read src/lib.rs: No such file or directory (os error 2)"
+        )));
+        assert!(!is_transient_failure(&lines(
+            "error: could not compile `my_game` (lib) due to 1 previous error\n\
+             error: failed to rename `/ci/target/deps/libgame.rlib`: Device or resource busy"
+        )));
+    }
+
+    #[test]
+    fn keeps_diagnostics() {
+        let output: Vec<String> = [
+            "   \x1b[32mCompiling\x1b[0m game v0.1.0",
+            "warning: unused variable: `x`",
+            " --> src/lib.rs:3:9",
+            "  |",
+            "3 |     let x = 1;",
+            "  = note: `#[warn(unused_variables)]` on by default",
+            "warning: `game` (lib) generated 1 warning",
+            "    Finished `release` profile [optimized] target(s) in 0.5s",
+        ]
+        .iter()
+        .map(|line| line.to_string())
+        .collect();
+        assert_eq!(
+            diagnostics(&output),
+            output[1..7].iter().collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,141 @@
+use crate::device::{self, Console};
+use anyhow::{bail, Context, Error};
+use flate2::{write::ZlibEncoder, Compression};
+use log::info;
+use std::{fs, io::Write, path::Path};
+
+const SCREEN_WIDTH: u32 = 400;
+const SCREEN_HEIGHT: u32 = 240;
+/// The frame buffer has one bit per pixel, set for white, with rows padded to whole bytes.
+const ROW_BYTES: usize = (SCREEN_WIDTH as usize).div_ceil(8);
+const FRAME_BYTES: usize = ROW_BYTES * SCREEN_HEIGHT as usize;
+
+/// What the device prints before the frame buffer in response to `screen`.
+const SCREEN_MARKER: &[u8] = b"~screen:\n";
+/// How much other console output to skip while looking for the marker before giving up.
+const MAX_PREAMBLE: usize = 64 * 1024;
+
+/// Asks the device for the current frame over its serial console and returns the raw
+/// frame buffer.
+pub fn capture(console: &mut dyn Console) -> Result<Vec<u8>, Error> {
+    capture_with_output(console).map(|(_, frame)| frame)
+}
+
+/// Like `capture`, but also returns the console output that came before the frame, such as
+/// what the game printed since the console was last read.
+pub fn capture_with_output(console: &mut dyn Console) -> Result<(Vec<u8>, Vec<u8>), Error> {
+    device::send_command(console, "screen")?;
+
+    let mut preamble = Vec::new();
+    let mut byte = [0u8; 1];
+    while !preamble.ends_with(SCREEN_MARKER) {
+        if preamble.len() > MAX_PREAMBLE {
+            bail!("The device did not answer the screen command");
+        }
+        console
+            .read_exact(&mut byte)
+            .context("Waiting for the screen from the device")?;
+        preamble.push(byte[0]);
+    }
+    info!("skipped {} bytes of console output", preamble.len());
+
+    let mut frame = vec![0u8; FRAME_BYTES];
+    console
+        .read_exact(&mut frame)
+        .context("Reading the screen from the device")?;
+    preamble.truncate(preamble.len() - SCREEN_MARKER.len());
+    Ok((preamble, frame))
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32fast::hash(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Encodes a frame buffer as a 1-bit grayscale PNG, which uses the same bit order and the same
+/// meaning for set bits as the Playdate.
+pub fn encode_png(frame: &[u8]) -> Result<Vec<u8>, Error> {
+    if frame.len() != FRAME_BYTES {
+        bail!(
+            "Expected a {} byte frame, got {} bytes",
+            FRAME_BYTES,
+            frame.len()
+        );
+    }
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&SCREEN_WIDTH.to_be_bytes());
+    header.extend_from_slice(&SCREEN_HEIGHT.to_be_bytes());
+    // Bit depth 1, grayscale, default compression, filtering and no interlacing.
+    header.extend_from_slice(&[1, 0, 0, 0, 0]);
+    write_chunk(&mut png, b"IHDR", &header);
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    for row in frame.chunks(ROW_BYTES) {
+        // Each scanline starts with its filter type, none here.
+        encoder.write_all(&[0])?;
+        encoder.write_all(row)?;
+    }
+    write_chunk(&mut png, b"IDAT", &encoder.finish()?);
+    write_chunk(&mut png, b"IEND", &[]);
+    Ok(png)
+}
+
+pub fn write_png(path: &Path, frame: &[u8]) -> Result<(), Error> {
+    fs::write(path, encode_png(frame)?).with_context(|| format!("Writing {:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Read};
+
+    /// A console that records what is written and plays back a canned reply.
+    struct FakeConsole {
+        reply: Cursor<Vec<u8>>,
+        written: Vec<u8>,
+    }
+
+    impl Read for FakeConsole {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.reply.read(buf)
+        }
+    }
+
+    impl Write for FakeConsole {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.written.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn captures_and_encodes_frame() {
+        let mut reply = b"echo off\n~screen:\n".to_vec();
+        reply.extend((0..FRAME_BYTES).map(|i| i as u8));
+        let mut console = FakeConsole {
+            reply: Cursor::new(reply),
+            written: Vec::new(),
+        };
+
+        let frame = capture(&mut console).unwrap();
+        assert_eq!(console.written, b"screen\n");
+        assert_eq!(frame.len(), FRAME_BYTES);
+        assert_eq!(frame[1], 1);
+
+        let png = encode_png(&frame).unwrap();
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..20], &SCREEN_WIDTH.to_be_bytes());
+        assert_eq!(&png[20..24], &SCREEN_HEIGHT.to_be_bytes());
+        assert!(png.ends_with(&crc32fast::hash(b"IEND").to_be_bytes()));
+    }
+}
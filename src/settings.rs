@@ -0,0 +1,448 @@
+use anyhow::{anyhow, bail, Error};
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use zip::CompressionMethod;
+
+pub const GLOBAL_CFG_DIR: &str = "crank";
+pub const GLOBAL_CFG_FILENAME: &str = "config.toml";
+
+/// Serial settings of the Playdate's USB console.
+pub const DEFAULT_BAUD_RATE: u32 = 115_200;
+pub const DEFAULT_DATA_BITS: u8 = 8;
+pub const DEFAULT_STOP_BITS: u8 = 1;
+
+pub const DEFAULT_TARGET_CPU: &str = "cortex-m7";
+pub const DEFAULT_FPU: &str = "fpv5-sp-d16";
+
+/// Flags for linking the device binary with gcc. `--emit-relocs` keeps the relocations pdc
+/// needs to load the binary at any address. There is no `--cref`, as the linker would print
+/// its cross reference table along with the link output.
+pub const DEFAULT_GCC_LINK_ARGS: &str = "-nostartfiles -mthumb -mfloat-abi=hard \
+    -D__FPU_USED=1 -Wl,--gc-sections,--no-warn-mismatch,--emit-relocs -fno-exceptions";
+/// Link args rustc passes for device builds, for the same reason.
+pub const DEFAULT_RUSTC_LINK_ARGS: &str = "--emit-relocs";
+
+/// Working files of image editors, which are exported to images the game loads.
+pub const DEFAULT_SOURCE_EXTENSIONS: &[&str] = &["aseprite", "ase", "psd", "kra", "xcf"];
+
+/// Settings that cannot change what a device build produces, as `section.key` or a whole
+/// section, left out of `Settings::build_key`. Any other setting, including ones added later,
+/// is part of it.
+const NOT_IN_BUILD_KEY: &[&str] = &[
+    // Serial console and install location.
+    "device",
+    // Only used after the pdx is made.
+    "package",
+    "toolchain.pdutil_path",
+    "simulator.log_path",
+    // Checked before building, with no effect on the output.
+    "build.min_free_space_mb",
+];
+
+/// Settings that can appear both in a project's Crank.toml and in the machine-wide config
+/// file. Values are resolved with the precedence CLI flags > Crank.toml > global config >
+/// built-in defaults.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Settings {
+    #[serde(default)]
+    pub toolchain: ToolchainSettings,
+    #[serde(default)]
+    pub device: DeviceSettings,
+    #[serde(default)]
+    pub package: PackageSettings,
+    #[serde(default)]
+    pub build: BuildSettings,
+    #[serde(default)]
+    pub simulator: SimulatorSettings,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ToolchainSettings {
+    /// Path to the Playdate SDK, used instead of the SDK's own config. `PLAYDATE_SDK_PATH`
+    /// takes priority.
+    pub sdk_path: Option<PathBuf>,
+    /// Version of the SDK to use when several are installed side by side as
+    /// `PlaydateSDK-<version>` next to the default SDK.
+    pub sdk_version: Option<String>,
+    /// Path to `pdc`, used instead of the one in the SDK. `CRANK_PDC_PATH` takes priority.
+    pub pdc_path: Option<PathBuf>,
+    /// Path to `pdutil`, used instead of the one in the SDK. `CRANK_PDUTIL_PATH` takes priority.
+    pub pdutil_path: Option<PathBuf>,
+    /// Path to `arm-none-eabi-gcc`.
+    pub gcc_path: Option<PathBuf>,
+    /// Extra flags appended to the ones crank compiles the SDK's setup.c with.
+    pub gcc_compile_args: Option<Vec<String>>,
+    /// Extra include directories for compiling setup.c, relative to the project directory.
+    pub gcc_include_dirs: Option<Vec<PathBuf>>,
+    /// C API directory, with the headers and `buildsupport`, relative to the project directory,
+    /// used instead of the SDK's `C_API` for compiling setup.c and linking.
+    pub c_api_path: Option<PathBuf>,
+    /// Linker script for the device binary, relative to the project directory, used instead of
+    /// the SDK's `buildsupport/link_map.ld`.
+    pub link_map: Option<PathBuf>,
+    /// CPU passed to both rustc (`-Ctarget-cpu`) and gcc (`-mcpu`) for device builds.
+    pub target_cpu: Option<String>,
+    /// FPU passed to gcc (`-mfpu`) for device builds.
+    pub fpu: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct DeviceSettings {
+    /// Serial device of the Playdate, used when `PLAYDATE_SERIAL_DEVICE` is not set.
+    pub serial_device: Option<String>,
+    /// Folder on the device games are installed into, used when `--device-games-dir` is not
+    /// given.
+    pub games_dir: Option<String>,
+    /// Baud rate of the serial console, 115200 by default.
+    pub baud_rate: Option<u32>,
+    /// Data bits per character, from 5 to 8, 8 by default.
+    pub data_bits: Option<u8>,
+    /// Parity bit of the serial console, none by default.
+    pub parity: Option<Parity>,
+    /// Stop bits, 1 or 2, 1 by default.
+    pub stop_bits: Option<u8>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Parity {
+    #[default]
+    None,
+    Even,
+    Odd,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct PackageSettings {
+    /// Compression used for the `.pdx.zip` archive.
+    pub compression: Option<Compression>,
+    /// Whether the `.pdx.zip` has the `.pdx` folder at its root or only its contents.
+    pub zip_root: Option<ZipRoot>,
+    /// Refuse to package with uncommitted changes, as with `--require-clean`.
+    pub require_clean: Option<bool>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct SimulatorSettings {
+    /// Whether simulator builds get an empty `pdex.bin`, true by default.
+    pub create_pdex_bin_stub: Option<bool>,
+    /// Log file the simulator writes, for `--tail-logs` and `--open-logs`. Needed except on
+    /// macOS, where it defaults to `~/Library/Logs/Playdate Simulator.log`.
+    pub log_path: Option<PathBuf>,
+}
+
+/// Advanced knobs for experimenting with the device toolchain.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct BuildSettings {
+    /// Replaces the flags gcc is given when linking the device binary.
+    pub gcc_link_args: Option<String>,
+    /// Replaces the `-Clink-args` rustc is given for device builds.
+    pub rustc_link_args: Option<String>,
+    /// Extra flags appended to the `RUSTFLAGS` crank sets for device builds.
+    pub rustflags: Option<Vec<String>>,
+    /// Rustup toolchain for device builds, `nightly` by default.
+    pub device_toolchain: Option<String>,
+    /// Rustup toolchain for simulator builds, by default the one rustup picks for the project.
+    pub simulator_toolchain: Option<String>,
+    /// Generate a module with the build time, git commit and version for the game to
+    /// `include!`.
+    pub emit_version_module: Option<bool>,
+    /// Directory for the staging directory and device link intermediates instead of the
+    /// target directory, relative to the project directory, as with `--staging-dir`.
+    pub staging_dir: Option<PathBuf>,
+    /// Extensions of source files, such as art in an editor's format, that are left out of
+    /// the pdx when the file converted from them is next to them. Replaces
+    /// `DEFAULT_SOURCE_EXTENSIONS`.
+    pub source_extensions: Option<Vec<String>>,
+    /// Free space, in megabytes, the target and staging directories need before a build
+    /// starts. When set, crank stops with less; otherwise it warns with less than
+    /// `DEFAULT_MIN_FREE_SPACE_MB`.
+    pub min_free_space_mb: Option<u64>,
+    /// How the game title is made from the target name when the metadata has no `name`.
+    pub title_style: Option<TitleStyle>,
+}
+
+/// How a target name such as `my_cool_game` becomes the game title when the metadata has no
+/// `name`, which also names the pdx.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TitleStyle {
+    /// `My Cool Game`.
+    #[default]
+    TitleCase,
+    /// `my_cool_game`, the target name unchanged.
+    AsIs,
+    /// `my-cool-game`.
+    Kebab,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Stored,
+    #[default]
+    Deflated,
+    Bzip2,
+    Zstd,
+}
+
+impl FromStr for Compression {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stored" => Ok(Compression::Stored),
+            "deflated" => Ok(Compression::Deflated),
+            "bzip2" => Ok(Compression::Bzip2),
+            "zstd" => Ok(Compression::Zstd),
+            _ => bail!(
+                "unknown compression '{}'; expected one of stored, deflated, bzip2, zstd",
+                s
+            ),
+        }
+    }
+}
+
+/// What the root of a `.pdx.zip` holds.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ZipRoot {
+    /// The files of the pdx, with no folder around them.
+    #[default]
+    None,
+    /// The `.pdx` folder, as when zipping the folder itself.
+    Pdx,
+}
+
+impl FromStr for ZipRoot {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(ZipRoot::None),
+            "pdx" => Ok(ZipRoot::Pdx),
+            _ => bail!("unknown zip root '{}'; expected none or pdx", s),
+        }
+    }
+}
+
+impl From<Compression> for CompressionMethod {
+    fn from(compression: Compression) -> Self {
+        match compression {
+            Compression::Stored => CompressionMethod::Stored,
+            Compression::Deflated => CompressionMethod::Deflated,
+            Compression::Bzip2 => CompressionMethod::Bzip2,
+            Compression::Zstd => CompressionMethod::Zstd,
+        }
+    }
+}
+
+impl Settings {
+    /// Fills every value not set in `self` from `fallback`.
+    pub fn or(self, fallback: Settings) -> Settings {
+        Settings {
+            toolchain: self.toolchain.or(fallback.toolchain),
+            device: self.device.or(fallback.device),
+            package: self.package.or(fallback.package),
+            build: self.build.or(fallback.build),
+            simulator: self.simulator.or(fallback.simulator),
+        }
+    }
+
+    /// The settings a device build depends on, for its build key: all of them except
+    /// `NOT_IN_BUILD_KEY`, so a new setting invalidates the cache without anyone having to
+    /// remember to add it.
+    pub fn build_key(&self) -> String {
+        let mut value = serde_json::to_value(self).expect("settings serialize to JSON");
+        for name in NOT_IN_BUILD_KEY {
+            let mut parts = name.splitn(2, '.');
+            let section = parts.next().unwrap_or_default();
+            let root = value.as_object_mut().expect("settings are a table");
+            match parts.next() {
+                Some(key) => {
+                    if let Some(table) = root.get_mut(section).and_then(|t| t.as_object_mut()) {
+                        table.remove(key);
+                    }
+                }
+                None => {
+                    root.remove(section);
+                }
+            }
+        }
+        value.to_string()
+    }
+}
+
+impl ToolchainSettings {
+    fn or(self, fallback: ToolchainSettings) -> ToolchainSettings {
+        ToolchainSettings {
+            sdk_path: self.sdk_path.or(fallback.sdk_path),
+            sdk_version: self.sdk_version.or(fallback.sdk_version),
+            pdc_path: self.pdc_path.or(fallback.pdc_path),
+            pdutil_path: self.pdutil_path.or(fallback.pdutil_path),
+            gcc_path: self.gcc_path.or(fallback.gcc_path),
+            gcc_compile_args: self.gcc_compile_args.or(fallback.gcc_compile_args),
+            gcc_include_dirs: self.gcc_include_dirs.or(fallback.gcc_include_dirs),
+            c_api_path: self.c_api_path.or(fallback.c_api_path),
+            link_map: self.link_map.or(fallback.link_map),
+            target_cpu: self.target_cpu.or(fallback.target_cpu),
+            fpu: self.fpu.or(fallback.fpu),
+        }
+    }
+
+    pub fn target_cpu(&self) -> &str {
+        self.target_cpu.as_deref().unwrap_or(DEFAULT_TARGET_CPU)
+    }
+
+    pub fn fpu(&self) -> &str {
+        self.fpu.as_deref().unwrap_or(DEFAULT_FPU)
+    }
+}
+
+impl DeviceSettings {
+    fn or(self, fallback: DeviceSettings) -> DeviceSettings {
+        DeviceSettings {
+            serial_device: self.serial_device.or(fallback.serial_device),
+            games_dir: self.games_dir.or(fallback.games_dir),
+            baud_rate: self.baud_rate.or(fallback.baud_rate),
+            data_bits: self.data_bits.or(fallback.data_bits),
+            parity: self.parity.or(fallback.parity),
+            stop_bits: self.stop_bits.or(fallback.stop_bits),
+        }
+    }
+}
+
+impl PackageSettings {
+    fn or(self, fallback: PackageSettings) -> PackageSettings {
+        PackageSettings {
+            compression: self.compression.or(fallback.compression),
+            zip_root: self.zip_root.or(fallback.zip_root),
+            require_clean: self.require_clean.or(fallback.require_clean),
+        }
+    }
+}
+
+impl SimulatorSettings {
+    fn or(self, fallback: SimulatorSettings) -> SimulatorSettings {
+        SimulatorSettings {
+            create_pdex_bin_stub: self.create_pdex_bin_stub.or(fallback.create_pdex_bin_stub),
+            log_path: self.log_path.or(fallback.log_path),
+        }
+    }
+}
+
+impl BuildSettings {
+    fn or(self, fallback: BuildSettings) -> BuildSettings {
+        BuildSettings {
+            gcc_link_args: self.gcc_link_args.or(fallback.gcc_link_args),
+            rustc_link_args: self.rustc_link_args.or(fallback.rustc_link_args),
+            rustflags: self.rustflags.or(fallback.rustflags),
+            device_toolchain: self.device_toolchain.or(fallback.device_toolchain),
+            simulator_toolchain: self.simulator_toolchain.or(fallback.simulator_toolchain),
+            emit_version_module: self.emit_version_module.or(fallback.emit_version_module),
+            staging_dir: self.staging_dir.or(fallback.staging_dir),
+            source_extensions: self.source_extensions.or(fallback.source_extensions),
+            min_free_space_mb: self.min_free_space_mb.or(fallback.min_free_space_mb),
+            title_style: self.title_style.or(fallback.title_style),
+        }
+    }
+
+    pub fn source_extensions(&self) -> Vec<String> {
+        match self.source_extensions.as_ref() {
+            Some(extensions) => extensions.clone(),
+            None => DEFAULT_SOURCE_EXTENSIONS
+                .iter()
+                .map(|ext| ext.to_string())
+                .collect(),
+        }
+    }
+
+    pub fn gcc_link_args(&self) -> &str {
+        self.gcc_link_args
+            .as_deref()
+            .unwrap_or(DEFAULT_GCC_LINK_ARGS)
+    }
+
+    pub fn rustc_link_args(&self) -> &str {
+        self.rustc_link_args
+            .as_deref()
+            .unwrap_or(DEFAULT_RUSTC_LINK_ARGS)
+    }
+}
+
+/// Location of the machine-wide config file: `$XDG_CONFIG_HOME/crank/config.toml`, falling
+/// back to `~/.config/crank/config.toml` on unix and `%APPDATA%\crank\config.toml` on Windows.
+pub fn global_config_path() -> Option<PathBuf> {
+    let config_dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ if cfg!(windows) => dirs::config_dir()?,
+        _ => dirs::home_dir()?.join(".config"),
+    };
+    Some(config_dir.join(GLOBAL_CFG_DIR).join(GLOBAL_CFG_FILENAME))
+}
+
+/// Loads the machine-wide config file, returning the defaults if there is none.
+pub fn load_global() -> Result<Settings, Error> {
+    match global_config_path() {
+        Some(path) if path.exists() => load(&path),
+        _ => Ok(Settings::default()),
+    }
+}
+
+fn load(path: &Path) -> Result<Settings, Error> {
+    let contents = fs::read_to_string(path)?;
+    toml::from_str(&contents).map_err(|err| anyhow!("{}: {}", path.display(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn project_overrides_global() {
+        let project: Settings = toml::from_str(
+            r#"
+            [toolchain]
+            gcc_path = "/project/gcc"
+            "#,
+        )
+        .unwrap();
+        let global: Settings = toml::from_str(
+            r#"
+            [toolchain]
+            sdk_path = "/global/sdk"
+            gcc_path = "/global/gcc"
+
+            [package]
+            compression = "zstd"
+            "#,
+        )
+        .unwrap();
+        let settings = project.or(global);
+        assert_eq!(
+            settings.toolchain.gcc_path,
+            Some(PathBuf::from("/project/gcc"))
+        );
+        assert_eq!(
+            settings.toolchain.sdk_path,
+            Some(PathBuf::from("/global/sdk"))
+        );
+        assert_eq!(settings.package.compression, Some(Compression::Zstd));
+        assert_eq!(settings.device.serial_device, None);
+    }
+
+    #[test]
+    fn build_key_ignores_only_listed_settings() {
+        let key = |toml: &str| toml::from_str::<Settings>(toml).unwrap().build_key();
+        let base = key("");
+        assert_eq!(key("[device]\nserial_device = \"/dev/ttyACM1\""), base);
+        assert_eq!(key("[build]\nmin_free_space_mb = 100"), base);
+        assert_ne!(key("[build]\nrustflags = [\"-Copt-level=s\"]"), base);
+        assert_ne!(key("[toolchain]\nfpu = \"fpv4-sp-d16\""), base);
+        assert_ne!(key("[simulator]\ncreate_pdex_bin_stub = false"), base);
+    }
+}
@@ -0,0 +1,179 @@
+use anyhow::{anyhow, bail, Context, Error};
+use log::debug;
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+/// How often the log is checked for new output while tailing it.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Where the simulator writes its log on macOS, `~/Library/Logs/Playdate Simulator.log`. On
+/// other platforms the location isn't known, so it has to be configured.
+pub fn default_path() -> Option<PathBuf> {
+    if cfg!(target_os = "macos") {
+        Some(dirs::home_dir()?.join("Library/Logs/Playdate Simulator.log"))
+    } else {
+        None
+    }
+}
+
+/// The simulator log: `configured`, from `[simulator] log_path`, or the default location.
+pub fn path(configured: Option<&PathBuf>) -> Result<PathBuf, Error> {
+    if let Some(configured) = configured {
+        return Ok(configured.clone());
+    }
+    if cfg!(target_os = "macos") {
+        default_path()
+            .ok_or_else(|| anyhow!("Unable to find the home directory for the simulator log"))
+    } else {
+        bail!(
+            "Crank doesn't know where the simulator writes its log on this platform. Set \
+            log_path in the [simulator] table of Crank.toml to the log file."
+        )
+    }
+}
+
+/// Opens the log in the default viewer for its file type.
+pub fn open(path: &Path) -> Result<(), Error> {
+    #[cfg(windows)]
+    let mut command = {
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    };
+    #[cfg(target_os = "macos")]
+    let mut command = Command::new("open");
+    #[cfg(not(any(windows, target_os = "macos")))]
+    let mut command = Command::new("xdg-open");
+    command
+        .arg(path)
+        .status()
+        .with_context(|| format!("Opening {:?}", path))?;
+    Ok(())
+}
+
+/// Reads what was added to a log since the last poll, starting from its length when created.
+/// A log that shrank was started over, so it is read again from the start.
+pub struct Follower {
+    path: PathBuf,
+    position: u64,
+    partial: String,
+}
+
+impl Follower {
+    pub fn new(path: PathBuf) -> Follower {
+        let position = path.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        Follower {
+            path,
+            position,
+            partial: String::new(),
+        }
+    }
+
+    /// The complete lines added since the last poll. A line without its newline yet is kept
+    /// for the next poll.
+    pub fn poll(&mut self) -> Vec<String> {
+        let mut file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(_) => return Vec::new(),
+        };
+        let len = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        if len < self.position {
+            debug!("{:?} was truncated, reading from the start", self.path);
+            self.position = 0;
+            self.partial.clear();
+        }
+        let mut added = Vec::new();
+        if file.seek(SeekFrom::Start(self.position)).is_err()
+            || file.read_to_end(&mut added).is_err()
+        {
+            return Vec::new();
+        }
+        self.position += added.len() as u64;
+        self.partial.push_str(&String::from_utf8_lossy(&added));
+        let mut lines: Vec<String> = self.partial.split('\n').map(String::from).collect();
+        self.partial = lines.pop().unwrap_or_default();
+        lines
+            .into_iter()
+            .map(|line| line.trim_end_matches('\r').to_string())
+            .collect()
+    }
+}
+
+/// Hands the lines added to a log to a callback on a background thread.
+pub struct Tail {
+    stop: Arc<AtomicBool>,
+    handle: thread::JoinHandle<()>,
+}
+
+impl Tail {
+    pub fn start(path: PathBuf, mut on_line: impl FnMut(&str) + Send + 'static) -> Tail {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let handle = thread::spawn(move || {
+            let mut follower = Follower::new(path);
+            loop {
+                // Read once more after being stopped, for what was written just before.
+                let last = stopped.load(Ordering::SeqCst);
+                for line in follower.poll() {
+                    on_line(&line);
+                }
+                if last {
+                    break;
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        });
+        Tail { stop, handle }
+    }
+
+    /// Stops tailing once the lines written so far are printed.
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::SeqCst);
+        let _ = self.handle.join();
+    }
+
+    /// Keeps tailing until crank is interrupted.
+    pub fn wait(self) {
+        let _ = self.handle.join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+    use std::{fs, io::Write};
+
+    #[test]
+    fn follows_added_lines() {
+        let dir = TestDir::new("sim-log");
+        let path = dir.join("simulator.log");
+        fs::write(&path, "from an earlier run\n").unwrap();
+        let mut follower = Follower::new(path.clone());
+        assert!(follower.poll().is_empty());
+
+        let append = |text: &str| {
+            let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+            file.write_all(text.as_bytes()).unwrap();
+        };
+        append("one\r\ntw");
+        assert_eq!(follower.poll(), vec!["one"]);
+        append("o\n");
+        assert_eq!(follower.poll(), vec!["two"]);
+
+        fs::write(&path, "restarted\n").unwrap();
+        assert_eq!(follower.poll(), vec!["restarted"]);
+        fs::remove_file(&path).unwrap();
+        assert!(follower.poll().is_empty());
+    }
+}
@@ -0,0 +1,239 @@
+use anyhow::{Context, Error};
+use log::info;
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    path::Path,
+    time::{Duration, UNIX_EPOCH},
+};
+
+/// FAT, which the Playdate's data disk uses, only stores modification times to two seconds,
+/// and the time read back right after a copy may not be rounded yet.
+const MTIME_TOLERANCE: Duration = Duration::from_secs(2);
+
+/// What `sync_directory` did.
+#[derive(Debug, Default, PartialEq)]
+pub struct SyncStats {
+    pub copied: usize,
+    pub unchanged: usize,
+    pub removed: usize,
+}
+
+/// A file as the last sync left it on the device.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+struct SyncedFile {
+    /// The SHA-256 of what was copied.
+    sha256: String,
+    /// The size and modification time, in nanoseconds since the epoch, of the device's copy
+    /// right after it was made.
+    size: u64,
+    modified: u128,
+}
+
+/// The files the last sync copied, by their path relative to the pdx, kept on the host.
+type SyncRecord = BTreeMap<String, SyncedFile>;
+
+fn sha256(path: &Path) -> Result<String, Error> {
+    let contents = fs::read(path).with_context(|| format!("Reading {:?}", path))?;
+    Ok(Sha256::digest(&contents)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// The size and modification time of the file at `path`, as `SyncedFile` records them.
+fn size_and_time(path: &Path) -> Option<(u64, u128)> {
+    let metadata = fs::metadata(path)
+        .ok()
+        .filter(|metadata| metadata.is_file())?;
+    let modified = metadata.modified().ok()?;
+    let nanos = modified.duration_since(UNIX_EPOCH).ok()?.as_nanos();
+    Some((metadata.len(), nanos))
+}
+
+/// Whether `dst` still holds what the last sync copied there, per `synced`, and that was the
+/// same as the file whose hash is `sha256`. Only the metadata of `dst` is read, as reading
+/// the files back from the device would be as slow as copying them.
+fn unchanged(sha256: &str, dst: &Path, synced: Option<&SyncedFile>) -> bool {
+    match (synced, size_and_time(dst)) {
+        (Some(synced), Some((size, modified))) => {
+            synced.sha256 == sha256
+                && synced.size == size
+                && synced.modified.abs_diff(modified) <= MTIME_TOLERANCE.as_nanos()
+        }
+        _ => false,
+    }
+}
+
+fn copy_file(src: &Path, dst: &Path) -> Result<(), Error> {
+    info!("copy_file {:?} -> {:?}", src, dst);
+    fs::copy(src, dst).with_context(|| format!("Copying {:?} to {:?}", src, dst))?;
+    // Keep the pdx's modification time, as a plain copy would have.
+    let modified = fs::metadata(src)?.modified()?;
+    File::options()
+        .write(true)
+        .open(dst)
+        .and_then(|file| file.set_modified(modified))
+        .with_context(|| format!("Setting the modification time of {:?}", dst))
+}
+
+fn remove(path: &Path) -> Result<(), Error> {
+    info!("remove {:?}", path);
+    if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+    .with_context(|| format!("Removing {:?}", path))
+}
+
+/// Makes `dst` a copy of `src`, copying only files that changed and removing anything in
+/// `dst` that is no longer in `src`. Copying a large pdx to the device is slow, and usually
+/// only the game binary changes between runs.
+///
+/// Whether a file changed is judged by the record at `record_path`, on the host, of what
+/// the last sync copied: the device's FAT disk only keeps modification times to two
+/// seconds, and a same-size rebuild within those two seconds must still be copied. A file
+/// is unchanged if its contents hash the same as the last copy and the device's copy still
+/// has the size it had right after that copy, and the time give or take those two seconds.
+pub fn sync_directory(src: &Path, dst: &Path, record_path: &Path) -> Result<SyncStats, Error> {
+    let previous: SyncRecord = fs::read(record_path)
+        .ok()
+        .and_then(|contents| serde_json::from_slice(&contents).ok())
+        .unwrap_or_default();
+    let mut sync = Sync {
+        previous,
+        record: SyncRecord::new(),
+        stats: SyncStats::default(),
+    };
+    sync.sync_into(src, dst, "")?;
+    fs::write(record_path, serde_json::to_string(&sync.record)?)
+        .with_context(|| format!("Writing {:?}", record_path))?;
+    Ok(sync.stats)
+}
+
+struct Sync {
+    previous: SyncRecord,
+    record: SyncRecord,
+    stats: SyncStats,
+}
+
+impl Sync {
+    fn sync_into(&mut self, src: &Path, dst: &Path, prefix: &str) -> Result<(), Error> {
+        if dst.exists() && !dst.is_dir() {
+            remove(dst)?;
+            self.stats.removed += 1;
+        }
+        fs::create_dir_all(dst).with_context(|| format!("Creating directory {:?}", dst))?;
+
+        for entry in fs::read_dir(dst).with_context(|| format!("Reading {:?}", dst))? {
+            let entry = entry?;
+            if !src.join(entry.file_name()).exists() {
+                remove(&entry.path())?;
+                self.stats.removed += 1;
+            }
+        }
+
+        for entry in fs::read_dir(src).with_context(|| format!("Reading {:?}", src))? {
+            let entry = entry?;
+            let src_path = entry.path();
+            let dst_path = dst.join(entry.file_name());
+            let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+            if src_path.is_dir() {
+                self.sync_into(&src_path, &dst_path, &format!("{}/", name))?;
+                continue;
+            }
+            let sha256 = sha256(&src_path)?;
+            if unchanged(&sha256, &dst_path, self.previous.get(&name)) {
+                self.stats.unchanged += 1;
+                let synced = self.previous[&name].clone();
+                self.record.insert(name, synced);
+                continue;
+            }
+            if dst_path.is_dir() {
+                remove(&dst_path)?;
+            }
+            copy_file(&src_path, &dst_path)?;
+            self.stats.copied += 1;
+            if let Some((size, modified)) = size_and_time(&dst_path) {
+                self.record.insert(
+                    name,
+                    SyncedFile {
+                        sha256,
+                        size,
+                        modified,
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn copies_only_changes() {
+        let dir = TestDir::new("sync");
+        let src = dir.join("src");
+        let dst = dir.join("dst");
+        let record = dir.join("record");
+        fs::create_dir_all(src.join("images")).unwrap();
+        fs::write(src.join("pdex.bin"), "first").unwrap();
+        fs::write(src.join("images").join("a.pdi"), "image").unwrap();
+        fs::write(src.join("old.pdz"), "old").unwrap();
+
+        let first = sync_directory(&src, &dst, &record).unwrap();
+        assert_eq!(first.copied, 3);
+        assert_eq!(
+            fs::read(dst.join("images").join("a.pdi")).unwrap(),
+            b"image"
+        );
+
+        // pdc writes every file again, but only the changed ones are copied.
+        fs::write(src.join("pdex.bin"), "second").unwrap();
+        fs::write(src.join("images").join("a.pdi"), "image").unwrap();
+        fs::remove_file(src.join("old.pdz")).unwrap();
+        let second = sync_directory(&src, &dst, &record).unwrap();
+        assert_eq!(
+            second,
+            SyncStats {
+                copied: 1,
+                unchanged: 1,
+                removed: 1,
+            }
+        );
+        assert_eq!(fs::read(dst.join("pdex.bin")).unwrap(), b"second");
+        assert!(!dst.join("old.pdz").exists());
+
+        // A rebuild of the same size with the same modification time.
+        let modified = fs::metadata(src.join("pdex.bin"))
+            .unwrap()
+            .modified()
+            .unwrap();
+        fs::write(src.join("pdex.bin"), "SECOND").unwrap();
+        File::options()
+            .write(true)
+            .open(src.join("pdex.bin"))
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        let third = sync_directory(&src, &dst, &record).unwrap();
+        assert_eq!(third.copied, 1);
+        assert_eq!(fs::read(dst.join("pdex.bin")).unwrap(), b"SECOND");
+
+        // The device's copy changed behind crank's back.
+        fs::write(dst.join("images").join("a.pdi"), "edited").unwrap();
+        let fourth = sync_directory(&src, &dst, &record).unwrap();
+        assert_eq!(fourth.copied, 1);
+        assert_eq!(
+            fs::read(dst.join("images").join("a.pdi")).unwrap(),
+            b"image"
+        );
+    }
+}
@@ -0,0 +1,49 @@
+use std::{
+    env, fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Tells apart the directories of tests running at the same time in one process.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// An empty directory for a test, removed with everything in it when dropped, even if the
+/// test fails.
+pub struct TestDir {
+    path: PathBuf,
+}
+
+impl TestDir {
+    /// Creates `crank-<name>-<process>-<n>` in the system's temporary directory, empty.
+    pub fn new(name: &str) -> TestDir {
+        let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+        let path = env::temp_dir().join(format!("crank-{}-{}-{}", name, process::id(), id));
+        if path.exists() {
+            fs::remove_dir_all(&path).unwrap();
+        }
+        fs::create_dir_all(&path).unwrap();
+        TestDir { path }
+    }
+}
+
+impl Deref for TestDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for TestDir {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,71 @@
+use crate::settings::TitleStyle;
+use inflector::cases::{kebabcase::to_kebab_case, titlecase::to_title_case};
+
+/// Characters that are not allowed in file names on at least one of the platforms crank runs
+/// on, or that would be read as a separator in a device path such as `/Games/<title>.pdx`.
+const RESERVED_CHARS: &[char] = &['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+/// Turns a game title into a single path component that can be used for the staging
+/// directory, the `.pdx` and `.pdx.zip` names and the install path on the device.
+///
+/// Reserved and control characters are replaced with `_`. Spaces and non-ASCII characters are
+/// kept, since every external tool is invoked with the path as one argument rather than
+/// through a shell. Leading and trailing dots and whitespace are trimmed, as Windows rejects
+/// them and they would turn `.` or `..` into a parent directory.
+pub fn file_name(title: &str) -> String {
+    let name: String = title
+        .chars()
+        .map(|c| {
+            if RESERVED_CHARS.contains(&c) || c.is_control() {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    let name = name.trim_matches(|c: char| c == '.' || c.is_whitespace());
+    if name.is_empty() {
+        "_".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// The game title for a target whose metadata has no `name`.
+pub fn from_target_name(target_name: &str, style: TitleStyle) -> String {
+    match style {
+        TitleStyle::TitleCase => to_title_case(target_name),
+        TitleStyle::AsIs => target_name.to_string(),
+        TitleStyle::Kebab => to_kebab_case(target_name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derives_title_from_target_name() {
+        let title = |style| from_target_name("my_cool_game", style);
+        assert_eq!(title(TitleStyle::TitleCase), "My Cool Game");
+        assert_eq!(title(TitleStyle::AsIs), "my_cool_game");
+        assert_eq!(title(TitleStyle::Kebab), "my-cool-game");
+    }
+
+    #[test]
+    fn keeps_spaces() {
+        assert_eq!(file_name("My Project"), "My Project");
+    }
+
+    #[test]
+    fn replaces_separators() {
+        assert_eq!(file_name("AC/DC: The Game"), "AC_DC_ The Game");
+        assert_eq!(file_name("..\\..\\escape"), "_.._escape");
+        assert_eq!(file_name(".."), "_");
+    }
+
+    #[test]
+    fn keeps_unicode() {
+        assert_eq!(file_name("Café ☕ Crank"), "Café ☕ Crank");
+    }
+}
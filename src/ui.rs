@@ -0,0 +1,157 @@
+use anyhow::{bail, Context, Error};
+use std::{
+    env,
+    io::{self, IsTerminal, Write},
+    process::{Command, ExitStatus, Stdio},
+    str::FromStr,
+    sync::OnceLock,
+    time::Instant,
+};
+
+/// How crank presents the phases of a build.
+#[derive(Clone, Copy, Debug)]
+pub struct Ui {
+    /// Print a header and a result line for each phase, and hold back the output of the tools
+    /// run in it unless they fail.
+    pub summarized: bool,
+    /// Color the result lines.
+    pub color: bool,
+}
+
+static UI: OnceLock<Ui> = OnceLock::new();
+
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// The value of `--color`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => bail!(
+                "Unknown color choice {:?}, expected auto, always or never",
+                s
+            ),
+        }
+    }
+}
+
+impl ColorChoice {
+    /// Whether to use color. With `auto`, `--plain` and `NO_COLOR` turn it off,
+    /// `CLICOLOR_FORCE` turns it on even when not writing to a terminal, and `CLICOLOR=0`
+    /// turns it off; otherwise color is used when stderr is a terminal.
+    pub fn enabled(self, plain: bool) -> bool {
+        let set = |name| env::var_os(name).is_some_and(|value| !value.is_empty());
+        let zero = |name| env::var_os(name).is_some_and(|value| value == "0");
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto if plain || set("NO_COLOR") => false,
+            ColorChoice::Auto if set("CLICOLOR_FORCE") && !zero("CLICOLOR_FORCE") => true,
+            ColorChoice::Auto if zero("CLICOLOR") => false,
+            ColorChoice::Auto => io::stderr().is_terminal(),
+        }
+    }
+}
+
+/// Chooses the output mode for the rest of the run. Summaries are the default when stderr is
+/// a terminal, and are turned off by `--verbose`, so the log lines aren't split up, or
+/// `--plain`.
+pub fn init(verbose: bool, plain: bool, color: ColorChoice) {
+    let terminal = io::stderr().is_terminal();
+    let ui = Ui {
+        summarized: terminal && !verbose && !plain,
+        color: color.enabled(plain),
+    };
+    let _ = UI.set(ui);
+}
+
+/// Whether colored output was chosen, for the logger.
+pub fn color() -> bool {
+    get().color
+}
+
+fn get() -> Ui {
+    *UI.get_or_init(|| Ui {
+        summarized: false,
+        color: false,
+    })
+}
+
+pub fn summarized() -> bool {
+    get().summarized
+}
+
+fn paint(color: &str, text: &str) -> String {
+    if get().color {
+        format!("{}{}{}", color, text, RESET)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Runs one phase of the build, such as linking or compiling assets, framed by a header and
+/// a success or failure line in summarized mode.
+pub fn phase<T>(name: &str, f: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+    if !get().summarized {
+        return f();
+    }
+    eprintln!("{} {}", paint(BOLD, "==>"), name);
+    let start = Instant::now();
+    let result = f();
+    let elapsed = start.elapsed().as_secs_f32();
+    match &result {
+        Ok(_) => eprintln!("  {} {} ({:.1}s)", paint(GREEN, "✓"), name, elapsed),
+        Err(_) => eprintln!("  {} {} ({:.1}s)", paint(RED, "✗"), name, elapsed),
+    }
+    result
+}
+
+/// Runs a tool such as gcc or pdc. In summarized mode its output is captured and only shown
+/// if it fails; otherwise it goes straight to the terminal.
+pub fn run(command: &mut Command) -> Result<ExitStatus, Error> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    if !get().summarized {
+        return command
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .status()
+            .with_context(|| format!("Running {}", program));
+    }
+    let output = command
+        .output()
+        .with_context(|| format!("Running {}", program))?;
+    if !output.status.success() {
+        io::stdout().write_all(&output.stdout)?;
+        io::stderr().write_all(&output.stderr)?;
+    }
+    Ok(output.status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_choice() {
+        assert!(ColorChoice::Always.enabled(true));
+        assert!(!ColorChoice::Never.enabled(false));
+        assert!(!ColorChoice::Auto.enabled(true));
+        assert_eq!("never".parse::<ColorChoice>().unwrap(), ColorChoice::Never);
+        assert!("sometimes".parse::<ColorChoice>().is_err());
+    }
+}